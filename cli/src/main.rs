@@ -0,0 +1,390 @@
+//
+// Operates the marketplace from the command line against any RPC endpoint, by default a
+// local `solana-test-validator`. Prices are in lamports and times in unix seconds.
+//
+
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_spl::token::spl_token;
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use mpl_token_metadata::state::{Metadata, TokenMetadataAccount};
+use solana_client::client_error::ClientError;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+use spl_associated_token_account::instruction::create_associated_token_account;
+use unicus_client::{args, decode_account, instruction, pda, Auction, CreatorShare, Marketplace, Order, UnicusError};
+
+
+#[derive(Parser)]
+#[clap(name = "unicus-cli", about = "Operate the unicus_ts marketplace")]
+struct Cli {
+    #[clap(long, default_value = "http://127.0.0.1:8899")]
+    url: String,
+
+    /// Keypair that signs and pays for every transaction.
+    #[clap(long, default_value = "~/.config/solana/id.json")]
+    keypair: String,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+
+#[derive(Subcommand)]
+enum Command {
+    /// Mint an nft to the keypair's wallet.
+    Mint {
+        #[clap(long)]
+        name: String,
+        #[clap(long)]
+        symbol: String,
+        #[clap(long)]
+        uri: String,
+        /// Royalty in basis points.
+        #[clap(long)]
+        royalty: u16,
+        /// `<pubkey>:<share>`, repeated for each creator. Defaults to the keypair with a 100 share.
+        #[clap(long = "creator")]
+        creators: Vec<String>,
+    },
+    /// List an nft for sale at a fixed price.
+    List {
+        #[clap(long)]
+        mint: Pubkey,
+        #[clap(long)]
+        price: u64,
+        #[clap(long, default_value_t = 0)]
+        listing_id: u64,
+        #[clap(long, default_value = "")]
+        memo: String,
+        #[clap(long)]
+        expires_at: Option<i64>,
+    },
+    /// Cancel one of the keypair's orders.
+    Cancel {
+        #[clap(long)]
+        mint: Pubkey,
+        #[clap(long, default_value_t = 0)]
+        listing_id: u64,
+    },
+    /// Buy a listed nft.
+    Fill {
+        #[clap(long)]
+        mint: Pubkey,
+        #[clap(long)]
+        seller: Pubkey,
+        #[clap(long, default_value_t = 0)]
+        listing_id: u64,
+    },
+    /// Put an nft up for auction.
+    CreateAuction {
+        #[clap(long)]
+        mint: Pubkey,
+        #[clap(long)]
+        price: u64,
+        #[clap(long)]
+        start_time: i64,
+        #[clap(long)]
+        end_time: i64,
+        #[clap(long, default_value_t = 0)]
+        listing_id: u64,
+        #[clap(long, default_value = "")]
+        memo: String,
+        #[clap(long, default_value_t = 0)]
+        min_increment_bps: u16,
+        #[clap(long, default_value_t = 0)]
+        min_increment: u64,
+        #[clap(long, default_value_t = 0)]
+        reserve_price: u64,
+        #[clap(long, default_value_t = 0)]
+        extension_window: u64,
+        #[clap(long, default_value_t = 0)]
+        extension_duration: u64,
+        #[clap(long, default_value_t = 0)]
+        max_extensions: u16,
+        #[clap(long)]
+        buy_now_price: Option<u64>,
+    },
+    /// Cancel one of the keypair's auctions that has not ended, refunding the leading bid.
+    CancelAuction {
+        #[clap(long)]
+        mint: Pubkey,
+        #[clap(long, default_value_t = 0)]
+        listing_id: u64,
+    },
+    /// Bid on an auction, refunding the current leader.
+    Bid {
+        #[clap(long)]
+        mint: Pubkey,
+        #[clap(long)]
+        seller: Pubkey,
+        #[clap(long)]
+        price: u64,
+        #[clap(long, default_value_t = 0)]
+        listing_id: u64,
+    },
+    /// Settle an ended auction. Anyone may settle and collects the crank fee.
+    Settle {
+        #[clap(long)]
+        mint: Pubkey,
+        #[clap(long)]
+        seller: Pubkey,
+        #[clap(long, default_value_t = 0)]
+        listing_id: u64,
+    },
+    /// Print an order account.
+    ShowOrder {
+        #[clap(long)]
+        mint: Pubkey,
+        #[clap(long)]
+        seller: Pubkey,
+        #[clap(long, default_value_t = 0)]
+        listing_id: u64,
+    },
+    /// Print an auction account.
+    ShowAuction {
+        #[clap(long)]
+        mint: Pubkey,
+        #[clap(long)]
+        seller: Pubkey,
+        #[clap(long, default_value_t = 0)]
+        listing_id: u64,
+    },
+}
+
+
+struct Config {
+    rpc: RpcClient,
+    payer: Keypair,
+}
+
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let keypair_path = match cli.keypair.strip_prefix("~/") {
+        Some(path) => format!("{}/{}", std::env::var("HOME")?, path),
+        None => cli.keypair.clone(),
+    };
+    let config = Config {
+        rpc: RpcClient::new_with_commitment(cli.url.clone(), CommitmentConfig::confirmed()),
+        payer: read_keypair_file(&keypair_path)
+            .map_err(|err| anyhow!("reading keypair {}: {}", keypair_path, err))?,
+    };
+    let payer = config.payer.pubkey();
+
+    match cli.command {
+        Command::Mint { name, symbol, uri, royalty, creators } => {
+            let creator_shares = if creators.is_empty() {
+                vec![CreatorShare { address: payer, share: 100 }]
+            } else {
+                creators.iter().map(|creator| parse_creator(creator)).collect::<Result<_>>()?
+            };
+
+            let mint = Keypair::new();
+            let rent = config.rpc.get_minimum_balance_for_rent_exemption(spl_token::state::Mint::LEN)?;
+            let mut instructions = instruction::create_mint(&payer, &mint.pubkey(), rent);
+            instructions.push(instruction::mint_nft(&payer, &mint.pubkey(), creator_shares, name, symbol, uri, royalty));
+            send(&config, &instructions, &[&mint])?;
+            println!("mint {}", mint.pubkey());
+        }
+        Command::List { mint, price, listing_id, memo, expires_at } => {
+            let treasury = marketplace(&config)?.treasury;
+            send(&config, &[instruction::create_order(&payer, &mint, &treasury, memo, price, listing_id, expires_at)], &[])?;
+            println!("order {}", pda::order(&mint, &payer, listing_id));
+        }
+        Command::Cancel { mint, listing_id } => {
+            send(&config, &[instruction::cancel_order(&payer, &mint, listing_id)], &[])?;
+        }
+        Command::Fill { mint, seller, listing_id } => {
+            let mut instructions = create_token_account(&config, &mint)?;
+            instructions.push(instruction::fill_order(&payer, &seller, &mint, listing_id, &creators(&config, &mint)?));
+            send(&config, &instructions, &[])?;
+        }
+        Command::CreateAuction {
+            mint,
+            price,
+            start_time,
+            end_time,
+            listing_id,
+            memo,
+            min_increment_bps,
+            min_increment,
+            reserve_price,
+            extension_window,
+            extension_duration,
+            max_extensions,
+            buy_now_price,
+        } => {
+            let treasury = marketplace(&config)?.treasury;
+            let args = args::CreateAuction {
+                memo,
+                price,
+                start_time,
+                end_time,
+                listing_id,
+                min_increment_bps,
+                min_increment,
+                reserve_price,
+                reserve_hash: None,
+                extension_window,
+                extension_duration,
+                max_extensions,
+                buy_now_price,
+            };
+            send(&config, &[instruction::create_auction(&payer, &mint, &treasury, args)], &[])?;
+            println!("auction {}", pda::auction(&mint, &payer, listing_id));
+        }
+        Command::CancelAuction { mint, listing_id } => {
+            let leader = auction(&config, &mint, &payer, listing_id)?.refund_receiver;
+            let treasury = marketplace(&config)?.treasury;
+            send(&config, &[instruction::cancel_auction(&payer, &mint, listing_id, &leader, &treasury)], &[])?;
+        }
+        Command::Bid { mint, seller, price, listing_id } => {
+            let leader = auction(&config, &mint, &seller, listing_id)?.refund_receiver;
+            send(&config, &[instruction::bid(&payer, &seller, &mint, listing_id, &leader, price)], &[])?;
+        }
+        Command::Settle { mint, seller, listing_id } => {
+            let winner = auction(&config, &mint, &seller, listing_id)?.refund_receiver;
+            let treasury = marketplace(&config)?.treasury;
+            let creators = creators(&config, &mint)?;
+            send(&config, &[instruction::auction_resolve(&payer, &seller, &mint, listing_id, &winner, &treasury, &creators)], &[])?;
+        }
+        Command::ShowOrder { mint, seller, listing_id } => {
+            let address = pda::order(&mint, &seller, listing_id);
+            let order: Order = fetch(&config, &address)?;
+            println!("order:        {}", address);
+            println!("creator:      {}", order.creator);
+            println!("mint:         {}", order.mint_key);
+            println!("listing id:   {}", order.listing_id);
+            println!("memo:         {}", order.memo);
+            println!("price:        {}", order.price);
+            println!("payment mint: {}", optional(order.payment_mint));
+            println!("expires at:   {}", optional(order.expires_at));
+        }
+        Command::ShowAuction { mint, seller, listing_id } => {
+            let address = pda::auction(&mint, &seller, listing_id);
+            let auction = auction(&config, &mint, &seller, listing_id)?;
+            println!("auction:       {}", address);
+            println!("creator:       {}", auction.creator);
+            println!("mint:          {}", auction.mint_key);
+            println!("listing id:    {}", auction.listing_id);
+            println!("memo:          {}", auction.memo);
+            println!("price:         {}", auction.price);
+            println!("leader:        {}", auction.refund_receiver);
+            println!("payment mint:  {}", optional(auction.payment_mint));
+            println!("start time:    {}", auction.start_time);
+            println!("end time:      {}", auction.end_time);
+            println!("reserve price: {}", auction.reserve_price);
+            println!("buy now price: {}", optional(auction.buy_now_price));
+            println!("extensions:    {}/{}", auction.extensions, auction.max_extensions);
+            println!("sealed:        {}", auction.is_sealed());
+        }
+    }
+
+    Ok(())
+}
+
+
+fn parse_creator(creator: &str) -> Result<CreatorShare> {
+    let (address, share) = creator
+        .split_once(':')
+        .ok_or_else(|| anyhow!("creator {} is not <pubkey>:<share>", creator))?;
+    Ok(CreatorShare {
+        address: Pubkey::from_str(address)?,
+        share: share.parse()?,
+    })
+}
+
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| value.to_string())
+}
+
+
+fn fetch<T: anchor_lang::AccountDeserialize>(config: &Config, address: &Pubkey) -> Result<T> {
+    let data = config.rpc.get_account_data(address).with_context(|| format!("fetching {}", address))?;
+    decode_account(&data).map_err(|err| anyhow!("decoding {}: {}", address, err))
+}
+
+
+fn marketplace(config: &Config) -> Result<Marketplace> {
+    fetch(config, &pda::marketplace())
+}
+
+
+fn auction(config: &Config, mint: &Pubkey, seller: &Pubkey, listing_id: u64) -> Result<Auction> {
+    fetch(config, &pda::auction(mint, seller, listing_id))
+}
+
+
+// Creators in the order the program expects them, read from the mint's metadata.
+fn creators(config: &Config, mint: &Pubkey) -> Result<Vec<Pubkey>> {
+    let data = config.rpc.get_account_data(&pda::metadata(mint))?;
+    let metadata: Metadata = Metadata::safe_deserialize(&data)
+        .map_err(|err| anyhow!("decoding metadata of {}: {}", mint, err))?;
+    Ok(metadata
+        .data
+        .creators
+        .unwrap_or_default()
+        .iter()
+        .map(|creator| creator.address)
+        .collect())
+}
+
+
+// Creates the payer's associated token account for `mint` if it does not exist yet.
+fn create_token_account(config: &Config, mint: &Pubkey) -> Result<Vec<Instruction>> {
+    let payer = config.payer.pubkey();
+    let token_account = pda::token_account(&payer, mint);
+    let existing = config.rpc.get_account_with_commitment(&token_account, config.rpc.commitment())?.value;
+    Ok(match existing {
+        Some(_) => vec![],
+        None => vec![create_associated_token_account(&payer, &payer, mint)],
+    })
+}
+
+
+fn send(config: &Config, instructions: &[Instruction], signers: &[&Keypair]) -> Result<()> {
+    let mut all_signers: Vec<&dyn Signer> = vec![&config.payer];
+    all_signers.extend(signers.iter().map(|signer| *signer as &dyn Signer));
+
+    let blockhash = config.rpc.get_latest_blockhash()?;
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&config.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+
+    match config.rpc.send_and_confirm_transaction(&transaction) {
+        Ok(signature) => {
+            println!("signature {}", signature);
+            Ok(())
+        }
+        Err(err) => Err(program_error(instructions, err)),
+    }
+}
+
+
+// Names the program error behind a failed transaction when there is one.
+fn program_error(instructions: &[Instruction], err: ClientError) -> anyhow::Error {
+    if let Some(TransactionError::InstructionError(index, error)) = err.get_transaction_error() {
+        if let Some(instruction) = instructions.get(index as usize) {
+            if instruction.program_id == unicus_client::PROGRAM_ID {
+                if let Some(error) = UnicusError::from_instruction_error(&error) {
+                    return anyhow!("{}", error);
+                }
+            }
+        }
+        if let InstructionError::Custom(code) = error {
+            return anyhow!("instruction {} failed with custom error {}", index, code);
+        }
+    }
+    err.into()
+}
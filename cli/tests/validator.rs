@@ -0,0 +1,253 @@
+//
+// Runs the cli against a local `solana-test-validator`, one process per command, the way
+// an operator would.
+//
+// Ignored by default since it needs the Solana tool suite on `PATH` (a
+// `solana-test-validator` new enough for `--upgradeable-program`) and two programs built:
+//
+//   anchor build
+//   programs/unicus_ts/tests/fixtures/build-token-metadata.sh
+//   cargo test -p unicus-cli -- --ignored
+//
+// The program is loaded under the upgradeable loader with `admin` as its upgrade
+// authority, so the marketplace is initialized the same way as on chain.
+//
+
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use anchor_lang::prelude::Pubkey;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::account::from_account;
+use solana_sdk::clock::Clock;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::signature::{write_keypair_file, Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::sysvar;
+use solana_sdk::transaction::Transaction;
+use unicus_client::{instruction, pda, PROGRAM_ID};
+
+const RPC_PORT: u16 = 18899;
+const FAUCET_PORT: u16 = 19900;
+const PRICE: u64 = LAMPORTS_PER_SOL;
+const FEE_BASIS_POINTS: u16 = 200;
+const MAX_ROYALTY_BASIS_POINTS: u16 = 1000;
+
+
+// A validator with the marketplace initialized, killed when dropped.
+struct Validator {
+    process: Child,
+    dir: PathBuf,
+    rpc: RpcClient,
+    admin: Keypair,
+}
+
+
+impl Validator {
+    fn start() -> Self {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+        let program = root.join("target/deploy/unicus_ts.so");
+        let token_metadata = root.join("programs/unicus_ts/tests/fixtures/mpl_token_metadata.so");
+        for path in [&program, &token_metadata] {
+            assert!(path.exists(), "{} is missing, see the top of this file", path.display());
+        }
+
+        let dir = std::env::temp_dir().join(format!("unicus-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let admin = Keypair::new();
+
+        let process = Command::new("solana-test-validator")
+            .arg("--reset")
+            .arg("--quiet")
+            .arg("--ledger")
+            .arg(dir.join("ledger"))
+            .args(["--rpc-port", &RPC_PORT.to_string(), "--faucet-port", &FAUCET_PORT.to_string()])
+            .args(["--mint", &admin.pubkey().to_string()])
+            .arg("--upgradeable-program")
+            .arg(PROGRAM_ID.to_string())
+            .arg(&program)
+            .arg(admin.pubkey().to_string())
+            .arg("--bpf-program")
+            .arg(mpl_token_metadata::ID.to_string())
+            .arg(&token_metadata)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("starting solana-test-validator");
+
+        let rpc = RpcClient::new_with_commitment(format!("http://127.0.0.1:{}", RPC_PORT), CommitmentConfig::confirmed());
+        let validator = Validator { process, dir, rpc, admin };
+        let started = Instant::now();
+        while validator.rpc.get_latest_blockhash().is_err() {
+            assert!(started.elapsed() < Duration::from_secs(60), "solana-test-validator did not start");
+            sleep(Duration::from_millis(500));
+        }
+
+        let treasury = Keypair::new().pubkey();
+        validator.send(&[
+            system_instruction::transfer(&validator.admin.pubkey(), &treasury, LAMPORTS_PER_SOL),
+            instruction::initialize_marketplace(&validator.admin.pubkey(), treasury, FEE_BASIS_POINTS, MAX_ROYALTY_BASIS_POINTS),
+        ]);
+        validator
+    }
+
+    // Sends `instructions` signed and paid for by `admin`.
+    fn send(&self, instructions: &[Instruction]) {
+        let blockhash = self.rpc.get_latest_blockhash().unwrap();
+        let transaction =
+            Transaction::new_signed_with_payer(instructions, Some(&self.admin.pubkey()), &[&self.admin], blockhash);
+        self.rpc.send_and_confirm_transaction(&transaction).unwrap();
+    }
+
+    // A new wallet funded with `sol` by `admin`, saved where the cli can read it.
+    fn user(&self, sol: u64) -> User {
+        let keypair = Keypair::new();
+        let path = self.dir.join(format!("{}.json", keypair.pubkey()));
+        write_keypair_file(&keypair, &path).unwrap();
+        self.send(&[system_instruction::transfer(&self.admin.pubkey(), &keypair.pubkey(), sol * LAMPORTS_PER_SOL)]);
+        User { pubkey: keypair.pubkey(), path }
+    }
+
+    fn now(&self) -> i64 {
+        let account = self.rpc.get_account(&sysvar::clock::ID).unwrap();
+        from_account::<Clock, _>(&account).unwrap().unix_timestamp
+    }
+
+    fn wait_until(&self, time: i64) {
+        while self.now() <= time {
+            sleep(Duration::from_millis(500));
+        }
+    }
+
+    fn nft_balance(&self, owner: &Pubkey, mint: &Pubkey) -> String {
+        self.rpc.get_token_account_balance(&pda::token_account(owner, mint)).unwrap().amount
+    }
+}
+
+
+impl Drop for Validator {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+
+struct User {
+    pubkey: Pubkey,
+    path: PathBuf,
+}
+
+
+impl User {
+    // Runs the cli as this user and returns what it printed, failing on a non-zero exit.
+    fn run(&self, args: &[&str]) -> String {
+        let output = self.command(args).output().unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            output.status.success(),
+            "unicus-cli {:?} failed:\n{}{}",
+            args,
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        stdout
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_unicus-cli"));
+        command
+            .args(["--url", &format!("http://127.0.0.1:{}", RPC_PORT)])
+            .arg("--keypair")
+            .arg(&self.path)
+            .args(args);
+        command
+    }
+
+    fn mint(&self) -> Pubkey {
+        let stdout = self.run(&["mint", "--name", "Test", "--symbol", "TST", "--uri", "https://example.com", "--royalty", "500"]);
+        printed(&stdout, "mint")
+    }
+}
+
+
+// The value the cli printed after `label`.
+fn printed(stdout: &str, label: &str) -> Pubkey {
+    let line = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix(label))
+        .unwrap_or_else(|| panic!("no {} in {}", label, stdout));
+    Pubkey::from_str(line.trim()).unwrap()
+}
+
+
+#[test]
+#[ignore]
+fn lists_and_auctions_through_the_cli() {
+    let validator = Validator::start();
+    mint_list_and_fill(&validator);
+    create_auction_bid_and_settle(&validator);
+}
+
+
+fn mint_list_and_fill(validator: &Validator) {
+    let seller = validator.user(2);
+    let buyer = validator.user(3);
+    let mint = seller.mint();
+    let mint_arg = mint.to_string();
+
+    let stdout = seller.run(&["list", "--mint", &mint_arg, "--price", &PRICE.to_string()]);
+    assert_eq!(printed(&stdout, "order"), pda::order(&mint, &seller.pubkey, 0));
+    assert_eq!(validator.nft_balance(&seller.pubkey, &mint), "0");
+
+    let seller_balance = validator.rpc.get_balance(&seller.pubkey).unwrap();
+    buyer.run(&["fill", "--mint", &mint_arg, "--seller", &seller.pubkey.to_string()]);
+    assert_eq!(validator.nft_balance(&buyer.pubkey, &mint), "1");
+    assert!(validator.rpc.get_balance(&seller.pubkey).unwrap() > seller_balance);
+    assert!(validator.rpc.get_account(&pda::order(&mint, &seller.pubkey, 0)).is_err());
+}
+
+
+fn create_auction_bid_and_settle(validator: &Validator) {
+    let seller = validator.user(2);
+    let bidder = validator.user(3);
+    let settler = validator.user(1);
+    let mint = seller.mint();
+    let mint_arg = mint.to_string();
+    let seller_arg = seller.pubkey.to_string();
+
+    let now = validator.now();
+    let end_time = now + 20;
+    let stdout = seller.run(&[
+        "create-auction",
+        "--mint",
+        &mint_arg,
+        "--price",
+        &PRICE.to_string(),
+        "--start-time",
+        &(now - 60).to_string(),
+        "--end-time",
+        &end_time.to_string(),
+    ]);
+    let auction = printed(&stdout, "auction");
+    assert_eq!(auction, pda::auction(&mint, &seller.pubkey, 0));
+
+    bidder.run(&["bid", "--mint", &mint_arg, "--seller", &seller_arg, "--price", &PRICE.to_string()]);
+    let stdout = bidder.run(&["show-auction", "--mint", &mint_arg, "--seller", &seller_arg]);
+    assert!(stdout.contains(&format!("leader:        {}", bidder.pubkey)), "{}", stdout);
+
+    // Settling before the end fails with the program's error named.
+    let result = settler.command(&["settle", "--mint", &mint_arg, "--seller", &seller_arg]).output().unwrap();
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("AuctionNotEnded"));
+
+    validator.wait_until(end_time);
+    settler.run(&["settle", "--mint", &mint_arg, "--seller", &seller_arg]);
+    assert_eq!(validator.nft_balance(&bidder.pubkey, &mint), "1");
+    assert!(validator.rpc.get_account(&auction).is_err());
+}
@@ -0,0 +1,195 @@
+//
+// Maps the program's custom error codes back to `MintError` and `MarketError`.
+//
+// `MarketError` starts at anchor's 6000 offset and `MintError` at its own, so the code
+// alone names the variant. The tables are checked against the program's source below.
+//
+
+use std::fmt;
+
+use anchor_lang::error::ERROR_CODE_OFFSET;
+use anchor_lang::solana_program::instruction::InstructionError;
+use unicus_ts::{MarketError, MintError};
+
+// Matches `#[error_code(offset = ...)]` on `MintError`.
+const MINT_ERROR_OFFSET: u32 = 7000;
+
+// In declaration order, so a variant's index is its code minus its enum's offset.
+const MINT_ERRORS: [MintError; 6] = [
+    MintError::MintFailed,
+    MintError::MetadataCreateFailed,
+    MintError::RoyaltyExceeded,
+    MintError::InvalidCreatorShares,
+    MintError::RoyaltyAlreadyMigrated,
+    MintError::MetadataUpdateFailed,
+];
+
+const MARKET_ERRORS: [MarketError; 47] = [
+    MarketError::TokenTransferFailed,
+    MarketError::TokenTransferFailed2,
+    MarketError::TokenTransferFailed3,
+    MarketError::SolTransferFailed,
+    MarketError::TokenCloseFailed,
+    MarketError::InsufficientMoney,
+    MarketError::AuctionEnded,
+    MarketError::InvalidEndTIme,
+    MarketError::AuctionNotEnded,
+    MarketError::InvalidReceiver,
+    MarketError::InvalidTreasury,
+    MarketError::InvalidFee,
+    MarketError::Unauthorized,
+    MarketError::InvalidMetadata,
+    MarketError::InvalidRoyaltyReceiver,
+    MarketError::InvalidQuantity,
+    MarketError::InvalidCollection,
+    MarketError::InvalidPaymentMint,
+    MarketError::InvalidNft,
+    MarketError::TokenNotHeld,
+    MarketError::InvalidExpiry,
+    MarketError::OrderExpired,
+    MarketError::OrderNotExpired,
+    MarketError::BidVaultInsufficientFunds,
+    MarketError::InvalidReserve,
+    MarketError::InvalidStartTime,
+    MarketError::AuctionNotStarted,
+    MarketError::InvalidFloorPrice,
+    MarketError::InvalidRevealTime,
+    MarketError::InvalidSlash,
+    MarketError::SealedAuction,
+    MarketError::NotSealedAuction,
+    MarketError::NotBiddingPhase,
+    MarketError::NotRevealPhase,
+    MarketError::AlreadyRevealed,
+    MarketError::InvalidCommitment,
+    MarketError::RevealNotEnded,
+    MarketError::AuctionStarted,
+    MarketError::InvalidBuyNowPrice,
+    MarketError::BuyNowUnavailable,
+    MarketError::MathOverflow,
+    MarketError::OfferFilled,
+    MarketError::OfferNotFilled,
+    MarketError::ReserveNotRevealed,
+    MarketError::ReserveRevealEnded,
+    MarketError::InvalidExtension,
+    MarketError::InvalidPrice,
+];
+
+#[derive(Debug, Clone, Copy)]
+pub enum UnicusError {
+    Mint(MintError),
+    Market(MarketError),
+    // Errors raised by anchor itself, such as failed account constraints.
+    Anchor(u32),
+    Unknown(u32),
+}
+
+impl UnicusError {
+    pub fn from_code(code: u32) -> Self {
+        if code < ERROR_CODE_OFFSET {
+            return UnicusError::Anchor(code);
+        }
+
+        if code >= MINT_ERROR_OFFSET {
+            return match MINT_ERRORS.get((code - MINT_ERROR_OFFSET) as usize) {
+                Some(error) => UnicusError::Mint(*error),
+                None => UnicusError::Unknown(code),
+            };
+        }
+
+        match MARKET_ERRORS.get((code - ERROR_CODE_OFFSET) as usize) {
+            Some(error) => UnicusError::Market(*error),
+            None => UnicusError::Unknown(code),
+        }
+    }
+
+    // Returns `None` for failures that are not custom program errors.
+    pub fn from_instruction_error(error: &InstructionError) -> Option<Self> {
+        match error {
+            InstructionError::Custom(code) => Some(Self::from_code(*code)),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> u32 {
+        match self {
+            UnicusError::Mint(error) => (*error).into(),
+            UnicusError::Market(error) => (*error).into(),
+            UnicusError::Anchor(code) | UnicusError::Unknown(code) => *code,
+        }
+    }
+}
+
+impl fmt::Display for UnicusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnicusError::Mint(error) => write!(f, "{}: {}", error.name(), error),
+            UnicusError::Market(error) => write!(f, "{}: {}", error.name(), error),
+            UnicusError::Anchor(code) => write!(f, "anchor error {}", code),
+            UnicusError::Unknown(code) => write!(f, "unknown error {}", code),
+        }
+    }
+}
+
+impl std::error::Error for UnicusError {}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Variant names of `name`'s enum, in the order the program declares them.
+    fn declared_variants(name: &str) -> Vec<String> {
+        let source = include_str!("../../programs/unicus_ts/src/lib.rs");
+        let start = source.find(&format!("pub enum {} {{", name)).unwrap();
+        let body = &source[start..];
+        let body = &body[body.find('{').unwrap() + 1..body.find("\n}").unwrap()];
+        body.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("#[") && !line.starts_with("//"))
+            .map(|line| line.trim_end_matches(',').to_string())
+            .collect()
+    }
+
+    #[test]
+    fn tables_list_every_variant_in_order() {
+        let mint_errors: Vec<String> = MINT_ERRORS.iter().map(|error| error.name()).collect();
+        let market_errors: Vec<String> = MARKET_ERRORS.iter().map(|error| error.name()).collect();
+        assert_eq!(mint_errors, declared_variants("MintError"));
+        assert_eq!(market_errors, declared_variants("MarketError"));
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        for error in MINT_ERRORS {
+            let code: u32 = error.into();
+            assert!(matches!(UnicusError::from_code(code), UnicusError::Mint(decoded) if decoded.name() == error.name()));
+        }
+        for error in MARKET_ERRORS {
+            let code: u32 = error.into();
+            assert!(matches!(UnicusError::from_code(code), UnicusError::Market(decoded) if decoded.name() == error.name()));
+        }
+    }
+
+    #[test]
+    fn decodes_codes_by_range() {
+        assert_eq!(u32::from(MarketError::TokenTransferFailed), 6000);
+        assert_eq!(u32::from(MintError::MintFailed), 7000);
+
+        assert!(matches!(UnicusError::from_code(2006), UnicusError::Anchor(2006)));
+        assert!(matches!(UnicusError::from_code(6005), UnicusError::Market(MarketError::InsufficientMoney)));
+        assert!(matches!(UnicusError::from_code(7002), UnicusError::Mint(MintError::RoyaltyExceeded)));
+
+        let past_market = ERROR_CODE_OFFSET + MARKET_ERRORS.len() as u32;
+        let past_mint = MINT_ERROR_OFFSET + MINT_ERRORS.len() as u32;
+        assert!(matches!(UnicusError::from_code(past_market), UnicusError::Unknown(code) if code == past_market));
+        assert!(matches!(UnicusError::from_code(past_mint), UnicusError::Unknown(code) if code == past_mint));
+    }
+
+    #[test]
+    fn only_custom_errors_decode() {
+        let error = UnicusError::from_instruction_error(&InstructionError::Custom(6009)).unwrap();
+        assert_eq!(error.code(), 6009);
+        assert_eq!(error.to_string(), "InvalidReceiver: Invalid Refund Receiver address");
+        assert!(UnicusError::from_instruction_error(&InstructionError::MissingRequiredSignature).is_none());
+    }
+}
@@ -0,0 +1,812 @@
+//
+// Instruction builders. Every PDA and associated token account the program checks is
+// derived here, so callers only pass the wallets, mints and listing ids involved.
+//
+// Sales pay royalties to each creator stored in the mint's metadata; `creators` must list
+// them in that order. Token priced sales pay each creator's associated token account for
+// the payment mint instead.
+//
+
+#![allow(clippy::too_many_arguments)]
+
+use anchor_lang::prelude::{AccountMeta, Pubkey};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::{system_instruction, system_program, sysvar};
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anchor_spl::token::spl_token;
+use unicus_ts::{accounts, instruction as ix, CreatorShare};
+
+use crate::pda;
+
+fn instruction(accounts: impl ToAccountMetas, data: impl InstructionData, remaining: Vec<AccountMeta>) -> Instruction {
+    let mut accounts = accounts.to_account_metas(None);
+    accounts.extend(remaining);
+    Instruction {
+        program_id: unicus_ts::ID,
+        accounts,
+        data: data.data(),
+    }
+}
+
+fn creator_accounts(creators: &[Pubkey]) -> Vec<AccountMeta> {
+    creators.iter().map(|creator| AccountMeta::new(*creator, false)).collect()
+}
+
+fn creator_payment_accounts(creators: &[Pubkey], payment_mint: &Pubkey) -> Vec<AccountMeta> {
+    creators
+        .iter()
+        .map(|creator| AccountMeta::new(pda::token_account(creator, payment_mint), false))
+        .collect()
+}
+
+
+// Creates and initializes `mint` with `payer` as its authority, along with the payer's
+// associated token account, ready for `mint_nft`.
+pub fn create_mint(payer: &Pubkey, mint: &Pubkey, rent_lamports: u64) -> Vec<Instruction> {
+    vec![
+        system_instruction::create_account(
+            payer,
+            mint,
+            rent_lamports,
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::ID,
+        ),
+        spl_token::instruction::initialize_mint(&spl_token::ID, mint, payer, Some(payer), 0)
+            .expect("initialize_mint with the token program id"),
+        spl_associated_token_account::instruction::create_associated_token_account(payer, payer, mint),
+    ]
+}
+
+
+pub fn mint_nft(payer: &Pubkey, mint: &Pubkey, creator_shares: Vec<CreatorShare>, name: String, symbol: String, uri: String, royalty: u16) -> Instruction {
+    instruction(
+        accounts::MintNFT {
+            mint_authority: *payer,
+            mint: *mint,
+            token_program: spl_token::ID,
+            metadata: pda::metadata(mint),
+            token_account: pda::token_account(payer, mint),
+            token_metadata_program: mpl_token_metadata::ID,
+            payer: *payer,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+            marketplace: pda::marketplace(),
+            royalty_version: pda::royalty_version(mint),
+        },
+        ix::MintNft { creator_shares, name, symbol, uri, royalty },
+        vec![],
+    )
+}
+
+
+pub fn create_order(creator: &Pubkey, mint: &Pubkey, treasury: &Pubkey, memo: String, price: u64, listing_id: u64, expires_at: Option<i64>) -> Instruction {
+    let order = pda::order(mint, creator, listing_id);
+    instruction(
+        accounts::CreateOrder {
+            order,
+            order_token_account: pda::token_account(&order, mint),
+            mint_key: *mint,
+            creator: *creator,
+            creator_token_account: pda::token_account(creator, mint),
+            marketplace: pda::marketplace(),
+            treasury_account: *treasury,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+            rent: sysvar::rent::ID,
+        },
+        ix::CreateOrder { memo, price, listing_id, expires_at },
+        vec![],
+    )
+}
+
+
+pub fn cancel_order(creator: &Pubkey, mint: &Pubkey, listing_id: u64) -> Instruction {
+    let order = pda::order(mint, creator, listing_id);
+    instruction(
+        accounts::CancelOrder {
+            order,
+            order_token_account: pda::token_account(&order, mint),
+            mint_key: *mint,
+            creator: *creator,
+            creator_token_account: pda::token_account(creator, mint),
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+        },
+        ix::CancelOrder {},
+        vec![],
+    )
+}
+
+
+// `closer` pays for the creator's associated token account if it no longer exists.
+pub fn close_expired_order(closer: &Pubkey, creator: &Pubkey, mint: &Pubkey, listing_id: u64) -> Instruction {
+    let order = pda::order(mint, creator, listing_id);
+    instruction(
+        accounts::CloseExpiredOrder {
+            order,
+            order_token_account: pda::token_account(&order, mint),
+            mint_key: *mint,
+            creator: *creator,
+            creator_token_account: pda::token_account(creator, mint),
+            closer: *closer,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+            rent: sysvar::rent::ID,
+        },
+        ix::CloseExpiredOrder {},
+        vec![],
+    )
+}
+
+
+pub fn update_order(creator: &Pubkey, mint: &Pubkey, listing_id: u64, treasury: &Pubkey, memo: String, price: u64) -> Instruction {
+    instruction(
+        accounts::UpdateOrder {
+            order: pda::order(mint, creator, listing_id),
+            mint_key: *mint,
+            creator: *creator,
+            marketplace: pda::marketplace(),
+            treasury_account: *treasury,
+            system_program: system_program::ID,
+        },
+        ix::UpdateOrder { memo, price },
+        vec![],
+    )
+}
+
+
+// The buyer's associated token account for the mint must already exist.
+pub fn fill_order(buyer: &Pubkey, creator: &Pubkey, mint: &Pubkey, listing_id: u64, creators: &[Pubkey]) -> Instruction {
+    let order = pda::order(mint, creator, listing_id);
+    instruction(
+        accounts::FillOrder {
+            order,
+            order_token_account: pda::token_account(&order, mint),
+            mint_key: *mint,
+            creator: *creator,
+            buyer: *buyer,
+            buyer_token_account: pda::token_account(buyer, mint),
+            metadata: pda::metadata(mint),
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+        },
+        ix::FillOrder {},
+        creator_accounts(creators),
+    )
+}
+
+
+// `winner` is the auction's `refund_receiver`: the leading bidder, or the creator when
+// nobody has bid. `treasury` is the marketplace's, paid the listing fee a scheduled
+// auction held back.
+pub fn auction_resolve(settler: &Pubkey, creator: &Pubkey, mint: &Pubkey, listing_id: u64, winner: &Pubkey, treasury: &Pubkey, creators: &[Pubkey]) -> Instruction {
+    let auction = pda::auction(mint, creator, listing_id);
+    instruction(
+        accounts::AuctionResolve {
+            auction,
+            bid_vault: pda::bid_vault(&auction),
+            auction_token_account: pda::token_account(&auction, mint),
+            mint_key: *mint,
+            creator: *creator,
+            creator_token_account: pda::token_account(creator, mint),
+            refund_receiver: *winner,
+            refund_receiver_token_account: pda::token_account(winner, mint),
+            metadata: pda::metadata(mint),
+            marketplace: pda::marketplace(),
+            treasury_account: *treasury,
+            settler: *settler,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+            rent: sysvar::rent::ID,
+        },
+        ix::AuctionResolve {},
+        creator_accounts(creators),
+    )
+}
+
+
+// `leader` is the auction's current `refund_receiver`, refunded when outbid.
+pub fn bid(bidder: &Pubkey, creator: &Pubkey, mint: &Pubkey, listing_id: u64, leader: &Pubkey, price: u64) -> Instruction {
+    let auction = pda::auction(mint, creator, listing_id);
+    instruction(
+        accounts::Bid {
+            auction,
+            bid_vault: pda::bid_vault(&auction),
+            mint_key: *mint,
+            creator: *creator,
+            bidder: *bidder,
+            refund_receiver: *leader,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+        },
+        ix::Bid { price },
+        vec![],
+    )
+}
+
+
+pub fn deferred_bid(bidder: &Pubkey, creator: &Pubkey, mint: &Pubkey, listing_id: u64, leader: &Pubkey, price: u64) -> Instruction {
+    let auction = pda::auction(mint, creator, listing_id);
+    instruction(
+        accounts::DeferredBid {
+            auction,
+            bid_vault: pda::bid_vault(&auction),
+            mint_key: *mint,
+            creator: *creator,
+            bidder: *bidder,
+            bidder_refund: pda::bid_refund(&auction, bidder),
+            previous_bid_refund: pda::bid_refund(&auction, leader),
+            previous_bidder: *leader,
+            system_program: system_program::ID,
+        },
+        ix::DeferredBid { price },
+        vec![],
+    )
+}
+
+
+pub fn reveal_reserve(creator: &Pubkey, mint: &Pubkey, listing_id: u64, reserve_price: u64, salt: [u8; 32]) -> Instruction {
+    instruction(
+        accounts::RevealReserve {
+            auction: pda::auction(mint, creator, listing_id),
+            mint_key: *mint,
+            creator: *creator,
+        },
+        ix::RevealReserve { reserve_price, salt },
+        vec![],
+    )
+}
+
+
+pub fn claim_refund(bidder: &Pubkey, auction: &Pubkey) -> Instruction {
+    instruction(
+        accounts::ClaimRefund {
+            bid_refund: pda::bid_refund(auction, bidder),
+            bidder: *bidder,
+        },
+        ix::ClaimRefund {},
+        vec![],
+    )
+}
+
+
+// `leader` is refunded when an auction is cancelled after it started.
+pub fn cancel_auction(creator: &Pubkey, mint: &Pubkey, listing_id: u64, leader: &Pubkey, treasury: &Pubkey) -> Instruction {
+    let auction = pda::auction(mint, creator, listing_id);
+    instruction(
+        accounts::CancelAuction {
+            auction,
+            bid_vault: pda::bid_vault(&auction),
+            auction_token_account: pda::token_account(&auction, mint),
+            mint_key: *mint,
+            creator: *creator,
+            creator_token_account: pda::token_account(creator, mint),
+            refund_receiver: *leader,
+            marketplace: pda::marketplace(),
+            treasury_account: *treasury,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+        },
+        ix::CancelAuction {},
+        vec![],
+    )
+}
+
+
+fn create_auction_accounts(creator: &Pubkey, mint: &Pubkey, treasury: &Pubkey, listing_id: u64) -> accounts::CreateAuction {
+    let auction = pda::auction(mint, creator, listing_id);
+    accounts::CreateAuction {
+        auction,
+        bid_vault: pda::bid_vault(&auction),
+        auction_token_account: pda::token_account(&auction, mint),
+        mint_key: *mint,
+        creator: *creator,
+        creator_token_account: pda::token_account(creator, mint),
+        marketplace: pda::marketplace(),
+        treasury_account: *treasury,
+        system_program: system_program::ID,
+        token_program: spl_token::ID,
+        associated_token_program: spl_associated_token_account::ID,
+        rent: sysvar::rent::ID,
+    }
+}
+
+
+pub fn create_auction(creator: &Pubkey, mint: &Pubkey, treasury: &Pubkey, args: ix::CreateAuction) -> Instruction {
+    instruction(
+        create_auction_accounts(creator, mint, treasury, args.listing_id),
+        args,
+        vec![],
+    )
+}
+
+
+// The buyer's associated token account for the mint must already exist.
+pub fn buy_now(buyer: &Pubkey, creator: &Pubkey, mint: &Pubkey, listing_id: u64, leader: &Pubkey, treasury: &Pubkey, creators: &[Pubkey]) -> Instruction {
+    let auction = pda::auction(mint, creator, listing_id);
+    instruction(
+        accounts::BuyNow {
+            auction,
+            bid_vault: pda::bid_vault(&auction),
+            auction_token_account: pda::token_account(&auction, mint),
+            mint_key: *mint,
+            creator: *creator,
+            buyer: *buyer,
+            buyer_token_account: pda::token_account(buyer, mint),
+            refund_receiver: *leader,
+            metadata: pda::metadata(mint),
+            marketplace: pda::marketplace(),
+            treasury_account: *treasury,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+        },
+        ix::BuyNow {},
+        creator_accounts(creators),
+    )
+}
+
+
+pub fn create_sealed_auction(creator: &Pubkey, mint: &Pubkey, treasury: &Pubkey, args: ix::CreateSealedAuction) -> Instruction {
+    instruction(
+        create_auction_accounts(creator, mint, treasury, args.listing_id),
+        args,
+        vec![],
+    )
+}
+
+
+pub fn commit_bid(bidder: &Pubkey, creator: &Pubkey, mint: &Pubkey, listing_id: u64, commitment: [u8; 32], deposit: u64) -> Instruction {
+    let auction = pda::auction(mint, creator, listing_id);
+    instruction(
+        accounts::CommitBid {
+            auction,
+            mint_key: *mint,
+            creator: *creator,
+            bidder: *bidder,
+            sealed_bid: pda::sealed_bid(&auction, bidder),
+            system_program: system_program::ID,
+        },
+        ix::CommitBid { commitment, deposit },
+        vec![],
+    )
+}
+
+
+pub fn reveal_bid(bidder: &Pubkey, creator: &Pubkey, mint: &Pubkey, listing_id: u64, leader: &Pubkey, amount: u64, salt: [u8; 32]) -> Instruction {
+    let auction = pda::auction(mint, creator, listing_id);
+    instruction(
+        accounts::RevealBid {
+            auction,
+            bid_vault: pda::bid_vault(&auction),
+            mint_key: *mint,
+            creator: *creator,
+            bidder: *bidder,
+            sealed_bid: pda::sealed_bid(&auction, bidder),
+            previous_sealed_bid: pda::sealed_bid(&auction, leader),
+        },
+        ix::RevealBid { amount, salt },
+        vec![],
+    )
+}
+
+
+pub fn withdraw_sealed_bid(bidder: &Pubkey, creator: &Pubkey, auction: &Pubkey) -> Instruction {
+    instruction(
+        accounts::WithdrawSealedBid {
+            sealed_bid: pda::sealed_bid(auction, bidder),
+            bidder: *bidder,
+            creator: *creator,
+        },
+        ix::WithdrawSealedBid {},
+        vec![],
+    )
+}
+
+
+pub fn create_token_order(creator: &Pubkey, mint: &Pubkey, payment_mint: &Pubkey, treasury: &Pubkey, memo: String, price: u64, listing_id: u64, expires_at: Option<i64>) -> Instruction {
+    let order = pda::order(mint, creator, listing_id);
+    instruction(
+        accounts::CreateTokenOrder {
+            order,
+            order_token_account: pda::token_account(&order, mint),
+            mint_key: *mint,
+            payment_mint: *payment_mint,
+            creator: *creator,
+            creator_token_account: pda::token_account(creator, mint),
+            creator_payment_account: pda::token_account(creator, payment_mint),
+            marketplace: pda::marketplace(),
+            treasury_payment_account: pda::token_account(treasury, payment_mint),
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+            rent: sysvar::rent::ID,
+        },
+        ix::CreateTokenOrder { memo, price, listing_id, expires_at },
+        vec![],
+    )
+}
+
+
+// The buyer's associated token account for the mint must already exist.
+pub fn fill_token_order(buyer: &Pubkey, creator: &Pubkey, mint: &Pubkey, payment_mint: &Pubkey, listing_id: u64, creators: &[Pubkey]) -> Instruction {
+    let order = pda::order(mint, creator, listing_id);
+    instruction(
+        accounts::FillTokenOrder {
+            order,
+            order_token_account: pda::token_account(&order, mint),
+            mint_key: *mint,
+            creator: *creator,
+            creator_payment_account: pda::token_account(creator, payment_mint),
+            buyer: *buyer,
+            buyer_token_account: pda::token_account(buyer, mint),
+            buyer_payment_account: pda::token_account(buyer, payment_mint),
+            metadata: pda::metadata(mint),
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+        },
+        ix::FillTokenOrder {},
+        creator_payment_accounts(creators, payment_mint),
+    )
+}
+
+
+pub fn update_token_order(creator: &Pubkey, mint: &Pubkey, payment_mint: &Pubkey, listing_id: u64, treasury: &Pubkey, memo: String, price: u64) -> Instruction {
+    instruction(
+        accounts::UpdateTokenOrder {
+            order: pda::order(mint, creator, listing_id),
+            mint_key: *mint,
+            creator: *creator,
+            creator_payment_account: pda::token_account(creator, payment_mint),
+            marketplace: pda::marketplace(),
+            treasury_payment_account: pda::token_account(treasury, payment_mint),
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+        },
+        ix::UpdateTokenOrder { memo, price },
+        vec![],
+    )
+}
+
+
+pub fn create_token_auction(creator: &Pubkey, mint: &Pubkey, payment_mint: &Pubkey, treasury: &Pubkey, args: ix::CreateTokenAuction) -> Instruction {
+    let auction = pda::auction(mint, creator, args.listing_id);
+    instruction(
+        accounts::CreateTokenAuction {
+            auction,
+            auction_token_account: pda::token_account(&auction, mint),
+            auction_payment_account: pda::token_account(&auction, payment_mint),
+            mint_key: *mint,
+            payment_mint: *payment_mint,
+            creator: *creator,
+            creator_token_account: pda::token_account(creator, mint),
+            creator_payment_account: pda::token_account(creator, payment_mint),
+            marketplace: pda::marketplace(),
+            treasury_payment_account: pda::token_account(treasury, payment_mint),
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+            rent: sysvar::rent::ID,
+        },
+        args,
+        vec![],
+    )
+}
+
+
+pub fn token_bid(bidder: &Pubkey, creator: &Pubkey, mint: &Pubkey, payment_mint: &Pubkey, listing_id: u64, leader: &Pubkey, price: u64) -> Instruction {
+    let auction = pda::auction(mint, creator, listing_id);
+    instruction(
+        accounts::TokenBid {
+            auction,
+            auction_payment_account: pda::token_account(&auction, payment_mint),
+            mint_key: *mint,
+            payment_mint: *payment_mint,
+            creator: *creator,
+            bidder: *bidder,
+            bidder_payment_account: pda::token_account(bidder, payment_mint),
+            refund_receiver_payment_account: pda::token_account(leader, payment_mint),
+            token_program: spl_token::ID,
+        },
+        ix::TokenBid { price },
+        vec![],
+    )
+}
+
+
+// The winner's associated token account for the payment mint must already exist; the one
+// for the nft is created by `settler` if the auction sells. `settler` is paid the crank fee
+// into their associated token account for the payment mint.
+pub fn resolve_token_auction(settler: &Pubkey, creator: &Pubkey, mint: &Pubkey, payment_mint: &Pubkey, listing_id: u64, winner: &Pubkey, treasury: &Pubkey, creators: &[Pubkey]) -> Instruction {
+    let auction = pda::auction(mint, creator, listing_id);
+    instruction(
+        accounts::ResolveTokenAuction {
+            auction,
+            auction_token_account: pda::token_account(&auction, mint),
+            auction_payment_account: pda::token_account(&auction, payment_mint),
+            mint_key: *mint,
+            payment_mint: *payment_mint,
+            creator: *creator,
+            creator_token_account: pda::token_account(creator, mint),
+            creator_payment_account: pda::token_account(creator, payment_mint),
+            refund_receiver: *winner,
+            refund_receiver_token_account: pda::token_account(winner, mint),
+            refund_receiver_payment_account: pda::token_account(winner, payment_mint),
+            metadata: pda::metadata(mint),
+            marketplace: pda::marketplace(),
+            treasury_payment_account: pda::token_account(treasury, payment_mint),
+            settler: *settler,
+            settler_payment_account: pda::token_account(settler, payment_mint),
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+            rent: sysvar::rent::ID,
+        },
+        ix::ResolveTokenAuction {},
+        creator_payment_accounts(creators, payment_mint),
+    )
+}
+
+
+// `leader`'s associated token account for the payment mint is refunded when an auction is
+// cancelled after it started.
+pub fn cancel_token_auction(creator: &Pubkey, mint: &Pubkey, payment_mint: &Pubkey, listing_id: u64, leader: &Pubkey, treasury: &Pubkey) -> Instruction {
+    let auction = pda::auction(mint, creator, listing_id);
+    instruction(
+        accounts::CancelTokenAuction {
+            auction,
+            auction_token_account: pda::token_account(&auction, mint),
+            auction_payment_account: pda::token_account(&auction, payment_mint),
+            mint_key: *mint,
+            payment_mint: *payment_mint,
+            creator: *creator,
+            creator_token_account: pda::token_account(creator, mint),
+            creator_payment_account: pda::token_account(creator, payment_mint),
+            refund_receiver_payment_account: pda::token_account(leader, payment_mint),
+            marketplace: pda::marketplace(),
+            treasury_payment_account: pda::token_account(treasury, payment_mint),
+            token_program: spl_token::ID,
+        },
+        ix::CancelTokenAuction {},
+        vec![],
+    )
+}
+
+
+pub fn create_dutch_auction(creator: &Pubkey, mint: &Pubkey, treasury: &Pubkey, memo: String, start_price: u64, floor_price: u64, start_time: i64, end_time: i64, listing_id: u64) -> Instruction {
+    let dutch_auction = pda::dutch_auction(mint, creator, listing_id);
+    instruction(
+        accounts::CreateDutchAuction {
+            dutch_auction,
+            auction_token_account: pda::token_account(&dutch_auction, mint),
+            mint_key: *mint,
+            creator: *creator,
+            creator_token_account: pda::token_account(creator, mint),
+            marketplace: pda::marketplace(),
+            treasury_account: *treasury,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+            rent: sysvar::rent::ID,
+        },
+        ix::CreateDutchAuction { memo, start_price, floor_price, start_time, end_time, listing_id },
+        vec![],
+    )
+}
+
+
+// The buyer's associated token account for the mint must already exist.
+pub fn buy_dutch(buyer: &Pubkey, creator: &Pubkey, mint: &Pubkey, listing_id: u64, max_price: u64, creators: &[Pubkey]) -> Instruction {
+    let dutch_auction = pda::dutch_auction(mint, creator, listing_id);
+    instruction(
+        accounts::BuyDutch {
+            dutch_auction,
+            auction_token_account: pda::token_account(&dutch_auction, mint),
+            mint_key: *mint,
+            creator: *creator,
+            buyer: *buyer,
+            buyer_token_account: pda::token_account(buyer, mint),
+            metadata: pda::metadata(mint),
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+        },
+        ix::BuyDutch { max_price },
+        creator_accounts(creators),
+    )
+}
+
+
+pub fn cancel_dutch_auction(creator: &Pubkey, mint: &Pubkey, listing_id: u64) -> Instruction {
+    let dutch_auction = pda::dutch_auction(mint, creator, listing_id);
+    instruction(
+        accounts::CancelDutchAuction {
+            dutch_auction,
+            auction_token_account: pda::token_account(&dutch_auction, mint),
+            mint_key: *mint,
+            creator: *creator,
+            creator_token_account: pda::token_account(creator, mint),
+            token_program: spl_token::ID,
+        },
+        ix::CancelDutchAuction {},
+        vec![],
+    )
+}
+
+
+// `admin` must be the program's upgrade authority.
+pub fn initialize_marketplace(admin: &Pubkey, treasury: Pubkey, fee_basis_points: u16, max_royalty_basis_points: u16) -> Instruction {
+    instruction(
+        accounts::InitializeMarketplace {
+            marketplace: pda::marketplace(),
+            admin: *admin,
+            program: unicus_ts::ID,
+            program_data: pda::program_data(),
+            system_program: system_program::ID,
+        },
+        ix::InitializeMarketplace { treasury, fee_basis_points, max_royalty_basis_points },
+        vec![],
+    )
+}
+
+
+fn update_marketplace_accounts(admin: &Pubkey) -> accounts::UpdateMarketplace {
+    accounts::UpdateMarketplace {
+        marketplace: pda::marketplace(),
+        admin: *admin,
+    }
+}
+
+
+pub fn set_treasury(admin: &Pubkey, treasury: Pubkey) -> Instruction {
+    instruction(update_marketplace_accounts(admin), ix::SetTreasury { treasury }, vec![])
+}
+
+
+pub fn set_fee(admin: &Pubkey, fee_basis_points: u16) -> Instruction {
+    instruction(update_marketplace_accounts(admin), ix::SetFee { fee_basis_points }, vec![])
+}
+
+
+pub fn set_crank_fee(admin: &Pubkey, crank_fee_basis_points: u16) -> Instruction {
+    instruction(update_marketplace_accounts(admin), ix::SetCrankFee { crank_fee_basis_points }, vec![])
+}
+
+
+pub fn set_max_royalty(admin: &Pubkey, max_royalty_basis_points: u16) -> Instruction {
+    instruction(update_marketplace_accounts(admin), ix::SetMaxRoyalty { max_royalty_basis_points }, vec![])
+}
+
+
+pub fn migrate_royalty(update_authority: &Pubkey, mint: &Pubkey) -> Instruction {
+    instruction(
+        accounts::MigrateRoyalty {
+            metadata: pda::metadata(mint),
+            mint_key: *mint,
+            update_authority: *update_authority,
+            royalty_version: pda::royalty_version(mint),
+            token_metadata_program: mpl_token_metadata::ID,
+            system_program: system_program::ID,
+        },
+        ix::MigrateRoyalty {},
+        vec![],
+    )
+}
+
+
+pub fn make_offer(buyer: &Pubkey, mint: &Pubkey, price: u64) -> Instruction {
+    instruction(
+        accounts::MakeOffer {
+            offer: pda::offer(mint, buyer),
+            mint_key: *mint,
+            buyer: *buyer,
+            system_program: system_program::ID,
+        },
+        ix::MakeOffer { price },
+        vec![],
+    )
+}
+
+
+pub fn cancel_offer(buyer: &Pubkey, mint: &Pubkey) -> Instruction {
+    instruction(
+        accounts::CancelOffer {
+            offer: pda::offer(mint, buyer),
+            mint_key: *mint,
+            buyer: *buyer,
+        },
+        ix::CancelOffer {},
+        vec![],
+    )
+}
+
+
+// The buyer's associated token account for the mint must already exist.
+pub fn accept_offer(seller: &Pubkey, buyer: &Pubkey, mint: &Pubkey, treasury: &Pubkey, creators: &[Pubkey]) -> Instruction {
+    instruction(
+        accounts::AcceptOffer {
+            offer: pda::offer(mint, buyer),
+            mint_key: *mint,
+            buyer: *buyer,
+            buyer_token_account: pda::token_account(buyer, mint),
+            seller: *seller,
+            seller_token_account: pda::token_account(seller, mint),
+            metadata: pda::metadata(mint),
+            marketplace: pda::marketplace(),
+            treasury_account: *treasury,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+        },
+        ix::AcceptOffer {},
+        creator_accounts(creators),
+    )
+}
+
+
+pub fn make_collection_offer(buyer: &Pubkey, collection: &Pubkey, price: u64, quantity: u64) -> Instruction {
+    instruction(
+        accounts::MakeCollectionOffer {
+            collection_offer: pda::collection_offer(collection, buyer),
+            collection_mint: *collection,
+            buyer: *buyer,
+            system_program: system_program::ID,
+        },
+        ix::MakeCollectionOffer { price, quantity },
+        vec![],
+    )
+}
+
+
+pub fn cancel_collection_offer(buyer: &Pubkey, collection: &Pubkey) -> Instruction {
+    instruction(
+        accounts::CancelCollectionOffer {
+            collection_offer: pda::collection_offer(collection, buyer),
+            buyer: *buyer,
+        },
+        ix::CancelCollectionOffer {},
+        vec![],
+    )
+}
+
+
+// Returns the rent of a fully bought collection offer to its buyer; anyone may send it.
+pub fn close_collection_offer(buyer: &Pubkey, collection: &Pubkey) -> Instruction {
+    instruction(
+        accounts::CloseCollectionOffer {
+            collection_offer: pda::collection_offer(collection, buyer),
+            buyer: *buyer,
+        },
+        ix::CloseCollectionOffer {},
+        vec![],
+    )
+}
+
+
+pub fn accept_collection_offer(seller: &Pubkey, buyer: &Pubkey, collection: &Pubkey, mint: &Pubkey, treasury: &Pubkey, creators: &[Pubkey]) -> Instruction {
+    instruction(
+        accounts::AcceptCollectionOffer {
+            collection_offer: pda::collection_offer(collection, buyer),
+            mint_key: *mint,
+            buyer: *buyer,
+            buyer_token_account: pda::token_account(buyer, mint),
+            seller: *seller,
+            seller_token_account: pda::token_account(seller, mint),
+            metadata: pda::metadata(mint),
+            marketplace: pda::marketplace(),
+            treasury_account: *treasury,
+            system_program: system_program::ID,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+            rent: sysvar::rent::ID,
+        },
+        ix::AcceptCollectionOffer {},
+        creator_accounts(creators),
+    )
+}
@@ -0,0 +1,28 @@
+//
+// Client side helpers for the unicus_ts program: PDA derivation, instruction builders
+// and decoding of program accounts and errors.
+//
+
+use anchor_lang::AccountDeserialize;
+
+pub mod error;
+pub mod instruction;
+pub mod pda;
+
+pub use error::UnicusError;
+pub use unicus_ts::instruction as args;
+pub use unicus_ts::{
+    Auction, AuctionPhase, BidRefund, BidVault, CollectionOffer, CreatorShare, DutchAuction,
+    MarketError, Marketplace, MintError, Offer, Order, RoyaltyVersion, SealedBid,
+    MAX_EXTENSION_DURATION, RESERVE_REVEAL_WINDOW, ROYALTY_VERSION_BASIS_POINTS,
+};
+
+pub use unicus_ts::ID as PROGRAM_ID;
+
+
+// Decodes a program account's data, checking its discriminator.
+#[allow(clippy::result_large_err)]
+pub fn decode_account<T: AccountDeserialize>(data: &[u8]) -> anchor_lang::Result<T> {
+    let mut data = data;
+    T::try_deserialize(&mut data)
+}
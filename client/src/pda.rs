@@ -0,0 +1,149 @@
+//
+// Addresses of the program's accounts, derived with the same seeds as the program.
+//
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::bpf_loader_upgradeable;
+use anchor_spl::associated_token::get_associated_token_address;
+
+pub fn marketplace() -> Pubkey {
+    Pubkey::find_program_address(&[b"marketplace"], &unicus_ts::ID).0
+}
+
+pub fn order(mint: &Pubkey, creator: &Pubkey, listing_id: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"order", mint.as_ref(), creator.as_ref(), &listing_id.to_le_bytes()],
+        &unicus_ts::ID,
+    )
+    .0
+}
+
+pub fn auction(mint: &Pubkey, creator: &Pubkey, listing_id: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"auction", mint.as_ref(), creator.as_ref(), &listing_id.to_le_bytes()],
+        &unicus_ts::ID,
+    )
+    .0
+}
+
+pub fn dutch_auction(mint: &Pubkey, creator: &Pubkey, listing_id: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"dutch_auction", mint.as_ref(), creator.as_ref(), &listing_id.to_le_bytes()],
+        &unicus_ts::ID,
+    )
+    .0
+}
+
+pub fn bid_vault(auction: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"bid_vault", auction.as_ref()], &unicus_ts::ID).0
+}
+
+pub fn bid_refund(auction: &Pubkey, bidder: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"bid_refund", auction.as_ref(), bidder.as_ref()],
+        &unicus_ts::ID,
+    )
+    .0
+}
+
+pub fn sealed_bid(auction: &Pubkey, bidder: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"sealed_bid", auction.as_ref(), bidder.as_ref()],
+        &unicus_ts::ID,
+    )
+    .0
+}
+
+pub fn offer(mint: &Pubkey, buyer: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"offer", mint.as_ref(), buyer.as_ref()], &unicus_ts::ID).0
+}
+
+pub fn collection_offer(collection: &Pubkey, buyer: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"collection_offer", collection.as_ref(), buyer.as_ref()],
+        &unicus_ts::ID,
+    )
+    .0
+}
+
+pub fn royalty_version(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"royalty_version", mint.as_ref()], &unicus_ts::ID).0
+}
+
+pub fn metadata(mint: &Pubkey) -> Pubkey {
+    mpl_token_metadata::pda::find_metadata_account(mint).0
+}
+
+pub fn program_data() -> Pubkey {
+    Pubkey::find_program_address(&[unicus_ts::ID.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+pub fn token_account(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    get_associated_token_address(owner, mint)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(count: usize) -> Vec<Pubkey> {
+        (0..count).map(|_| Pubkey::new_unique()).collect()
+    }
+
+    #[test]
+    fn listings_are_seeded_by_mint_creator_and_listing_id() {
+        let [mint, creator, other]: [Pubkey; 3] = keys(3).try_into().unwrap();
+        let listings = [
+            auction(&mint, &creator, 0),
+            auction(&mint, &creator, 1),
+            auction(&mint, &other, 0),
+            auction(&other, &creator, 0),
+            order(&mint, &creator, 0),
+            dutch_auction(&mint, &creator, 0),
+        ];
+        for (i, listing) in listings.iter().enumerate() {
+            assert!(!listing.is_on_curve());
+            assert!(listings[i + 1..].iter().all(|other| other != listing));
+        }
+        assert_eq!(auction(&mint, &creator, 1), auction(&mint, &creator, 1));
+    }
+
+    #[test]
+    fn bid_accounts_are_seeded_by_auction_and_bidder() {
+        let [auction, bidder, other]: [Pubkey; 3] = keys(3).try_into().unwrap();
+        assert_eq!(
+            bid_vault(&auction),
+            Pubkey::create_program_address(&[b"bid_vault", auction.as_ref(), &[bump(&[b"bid_vault", auction.as_ref()])]], &unicus_ts::ID).unwrap()
+        );
+        assert_ne!(bid_vault(&auction), bid_vault(&other));
+        assert_ne!(bid_refund(&auction, &bidder), bid_refund(&auction, &other));
+        assert_ne!(bid_refund(&auction, &bidder), sealed_bid(&auction, &bidder));
+        assert_ne!(offer(&auction, &bidder), collection_offer(&auction, &bidder));
+    }
+
+    #[test]
+    fn external_accounts_use_their_programs_seeds() {
+        let [owner, mint]: [Pubkey; 2] = keys(2).try_into().unwrap();
+        assert_eq!(
+            token_account(&owner, &mint),
+            Pubkey::find_program_address(
+                &[owner.as_ref(), anchor_spl::token::ID.as_ref(), mint.as_ref()],
+                &spl_associated_token_account::ID,
+            )
+            .0
+        );
+        assert_eq!(
+            metadata(&mint),
+            Pubkey::find_program_address(
+                &[b"metadata", mpl_token_metadata::ID.as_ref(), mint.as_ref()],
+                &mpl_token_metadata::ID,
+            )
+            .0
+        );
+    }
+
+    fn bump(seeds: &[&[u8]]) -> u8 {
+        Pubkey::find_program_address(seeds, &unicus_ts::ID).1
+    }
+}
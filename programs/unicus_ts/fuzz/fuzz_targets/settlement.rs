@@ -0,0 +1,193 @@
+//
+// Fuzzes the settlement arithmetic of `fill_order`, `auction_resolve` and `accept_offer`
+// over arbitrary prices, royalties, marketplace fees and crank fees.
+//
+// Every settlement must conserve lamports across the accounts it touches, pay creators
+// and the treasury exactly their share, and either succeed or fail with `MathOverflow`
+// without moving anything.
+//
+// Runs on the program test harness, so it needs the same `.so` files as the tests. From
+// the repository root:
+//
+//   anchor build
+//   programs/unicus_ts/tests/fixtures/build-token-metadata.sh
+//   cp programs/unicus_ts/tests/fixtures/mpl_token_metadata.so target/deploy/
+//   cd programs/unicus_ts/fuzz
+//   BPF_OUT_DIR=../../../target/deploy cargo hfuzz run settlement
+//
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+
+use std::collections::BTreeMap;
+
+use anchor_lang::prelude::Pubkey;
+use arbitrary::Arbitrary;
+use common::*;
+use honggfuzz::fuzz;
+use solana_program_test::tokio::runtime::Runtime;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::signature::Signer;
+use unicus_client::{instruction, pda, MarketError};
+
+// The harness' payer starts with a million SOL, enough to fund a buyer and seller at this price.
+const MAX_PRICE: u64 = 100_000 * LAMPORTS_PER_SOL;
+const MAX_BASIS_POINTS: u16 = 10000;
+const DURATION: i64 = 3600;
+
+
+#[derive(Arbitrary, Debug)]
+enum Flow {
+    FillOrder,
+    AuctionResolve,
+    AcceptOffer,
+}
+
+
+#[derive(Arbitrary, Debug)]
+struct Settlement {
+    flow: Flow,
+    price: u64,
+    royalty_basis_points: u16,
+    fee_basis_points: u16,
+    crank_fee_basis_points: u16,
+}
+
+
+fn main() {
+    let runtime = Runtime::new().unwrap();
+    loop {
+        fuzz!(|settlement: Settlement| {
+            runtime.block_on(settle(settlement));
+        });
+    }
+}
+
+
+async fn settle(settlement: Settlement) {
+    let price = settlement.price % (MAX_PRICE + 1);
+    let royalty_basis_points = settlement.royalty_basis_points % (MAX_BASIS_POINTS + 1);
+    let fee_basis_points = settlement.fee_basis_points % (MAX_BASIS_POINTS + 1);
+    let crank_fee_basis_points = settlement.crank_fee_basis_points % (MAX_BASIS_POINTS + 1);
+
+    let harness = Harness::start().await;
+    let admin = harness.admin.pubkey();
+    harness
+        .send(
+            &[
+                instruction::set_fee(&admin, fee_basis_points),
+                instruction::set_crank_fee(&admin, crank_fee_basis_points),
+                instruction::set_max_royalty(&admin, royalty_basis_points),
+            ],
+            &[&harness.admin],
+        )
+        .await
+        .unwrap();
+
+    let funds = price / LAMPORTS_PER_SOL + 2;
+    let artist = harness.user(1).await;
+    let seller = harness.user(funds).await;
+    let buyer = harness.user(funds).await;
+    let settler = harness.user(1).await;
+    let mint = harness.mint_nft(&artist, royalty_basis_points).await;
+    harness.transfer_nft(&artist, &mint, &seller.pubkey()).await;
+    harness.create_token_account(&buyer.pubkey(), &mint).await;
+
+    let royalty = share(price, royalty_basis_points);
+    let fee = share(price, fee_basis_points);
+    let (settle, signer) = match settlement.flow {
+        Flow::FillOrder => {
+            harness
+                .send(
+                    &[instruction::create_order(&seller.pubkey(), &mint, &harness.treasury, "Fuzzed order.".to_string(), price, 0, None)],
+                    &[&seller],
+                )
+                .await
+                .unwrap();
+            (instruction::fill_order(&buyer.pubkey(), &seller.pubkey(), &mint, 0, &[artist.pubkey()]), &buyer)
+        }
+        Flow::AuctionResolve => {
+            let now = harness.now().await;
+            harness
+                .send(
+                    &[instruction::create_auction(&seller.pubkey(), &mint, &harness.treasury, auction_args(price, now - 60, now + DURATION, 0))],
+                    &[&seller],
+                )
+                .await
+                .unwrap();
+            harness
+                .send(&[instruction::bid(&buyer.pubkey(), &seller.pubkey(), &mint, 0, &seller.pubkey(), price)], &[&buyer])
+                .await
+                .unwrap();
+            harness.warp_to(now + DURATION).await;
+            (instruction::auction_resolve(&settler.pubkey(), &seller.pubkey(), &mint, 0, &buyer.pubkey(), &harness.treasury, &[artist.pubkey()]), &settler)
+        }
+        Flow::AcceptOffer => {
+            harness.send(&[instruction::make_offer(&buyer.pubkey(), &mint, price)], &[&buyer]).await.unwrap();
+            (instruction::accept_offer(&seller.pubkey(), &buyer.pubkey(), &mint, &harness.treasury, &[artist.pubkey()]), &seller)
+        }
+    };
+
+    let before = balances(&harness, &settle).await;
+    let result = harness.send(std::slice::from_ref(&settle), &[signer]).await;
+    let after = balances(&harness, &settle).await;
+    assert_eq!(total(&after), total(&before), "{:?} does not conserve lamports", settlement.flow);
+
+    let gained = |account: &Pubkey| after[account] as i128 - before[account] as i128;
+    match settlement.flow {
+        Flow::AcceptOffer if fee + royalty > price => {
+            assert_error(result, MarketError::MathOverflow);
+            assert_eq!(after, before);
+            assert_eq!(harness.token_balance(&pda::token_account(&seller.pubkey(), &mint)).await, 1);
+            return;
+        }
+        Flow::AcceptOffer => {
+            result.unwrap();
+            assert_eq!(gained(&seller.pubkey()), (price - fee - royalty) as i128);
+            assert_eq!(gained(&harness.treasury), fee as i128);
+        }
+        Flow::FillOrder => {
+            result.unwrap();
+            assert_eq!(gained(&buyer.pubkey()), -(price as i128));
+        }
+        Flow::AuctionResolve => {
+            result.unwrap();
+            let crank_fee = share(fee, crank_fee_basis_points);
+            assert_eq!(gained(&settler.pubkey()), crank_fee as i128);
+
+            // The seller gets the winning bid less royalties, and the rent of every account
+            // the auction closes besides the crank fee held in it.
+            let auction = pda::auction(&mint, &seller.pubkey(), 0);
+            let closed: u128 = [auction, pda::bid_vault(&auction), pda::token_account(&auction, &mint)]
+                .iter()
+                .map(|account| before[account] as u128)
+                .sum();
+            assert_eq!(gained(&seller.pubkey()), closed as i128 - crank_fee as i128 - royalty as i128);
+            assert_eq!(gained(&harness.treasury), 0);
+        }
+    }
+    assert_eq!(gained(&artist.pubkey()), royalty as i128);
+    assert_eq!(harness.token_balance(&pda::token_account(&buyer.pubkey(), &mint)).await, 1);
+}
+
+
+// `amount` times `basis_points` over 10000, the way the program charges fees and royalties.
+fn share(amount: u64, basis_points: u16) -> u64 {
+    (amount as u128 * basis_points as u128 / MAX_BASIS_POINTS as u128) as u64
+}
+
+
+// Lamports held by every account `instruction` touches.
+async fn balances(harness: &Harness, instruction: &Instruction) -> BTreeMap<Pubkey, u64> {
+    let mut balances = BTreeMap::new();
+    for account in &instruction.accounts {
+        balances.insert(account.pubkey, harness.balance(&account.pubkey).await);
+    }
+    balances
+}
+
+
+fn total(balances: &BTreeMap<Pubkey, u64>) -> u128 {
+    balances.values().map(|&lamports| lamports as u128).sum()
+}
@@ -1,10 +1,17 @@
+// Anchor's `Result` carries a large error, handlers take their arguments flat as the
+// instruction does, and the handlers check CPI results with `if let Err(_)`.
+#![allow(clippy::result_large_err, clippy::too_many_arguments)]
+#![allow(clippy::redundant_pattern_matching, clippy::needless_return)]
+
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::program::invoke;
+use anchor_lang::solana_program::hash::hashv;
 use anchor_spl::token::mint_to;
 use anchor_spl::token::{MintTo, Token, TokenAccount, CloseAccount, Mint, Transfer};
 use anchor_spl::token::{transfer, close_account};
-use anchor_spl::associated_token::AssociatedToken;
-use mpl_token_metadata::instruction::{create_metadata_accounts_v2};
+use anchor_spl::associated_token::{create, get_associated_token_address, AssociatedToken, Create};
+use mpl_token_metadata::instruction::{create_metadata_accounts_v2, update_metadata_accounts_v2};
+use mpl_token_metadata::state::{Metadata, TokenMetadataAccount};
 
 
 declare_id!("5d1RwrEGymHuXAkHQTo7CAkBDhrVDsxUJs8b6jgtSFie");
@@ -16,16 +23,23 @@ pub mod unicus_ts {
 
     pub fn mint_nft(
     ctx: Context<MintNFT>,
-    creator_key: Pubkey,
+    creator_shares: Vec<CreatorShare>,
     name: String,
     symbol: String,
     uri: String,
     royalty: u16,
     ) -> Result<()> {
     
-    if royalty > 10 {
+    if royalty > ctx.accounts.marketplace.max_royalty_basis_points {
         return Err(error!(MintError::RoyaltyExceeded));
     }
+
+    if creator_shares.is_empty()
+        || creator_shares.len() > mpl_token_metadata::state::MAX_CREATOR_LIMIT
+        || creator_shares.iter().map(|creator| creator.share as u16).sum::<u16>() != 100
+    {
+        return Err(error!(MintError::InvalidCreatorShares));
+    }
     
     //mpl_token_metadata::state::Metadata::from_account_info(a)
     msg!("Nft token minting:");
@@ -53,18 +67,14 @@ pub mod unicus_ts {
         ctx.accounts.system_program.to_account_info(),
         ctx.accounts.rent.to_account_info(),
     ];
-    let creators = vec![
-        mpl_token_metadata::state::Creator {         
-            address: creator_key,
-            verified: false,
-            share: 100,
-        },
-    /*     mpl_token_metadata::state::Creator {
-            address: ctx.accounts.mint_authority.key(),
+    let creators = creator_shares
+        .iter()
+        .map(|creator| mpl_token_metadata::state::Creator {
+            address: creator.address,
             verified: false,
-            share: 0,
-        }, */
-    ];
+            share: creator.share,
+        })
+        .collect();
     let result = invoke(
         &create_metadata_accounts_v2(
             ctx.accounts.token_metadata_program.key(),
@@ -89,18 +99,37 @@ pub mod unicus_ts {
         return Err(error!(MintError::MetadataCreateFailed));
     }
     msg!("Metadata account created !!!");
+
+    let royalty_version = &mut ctx.accounts.royalty_version;
+    royalty_version.version = ROYALTY_VERSION_BASIS_POINTS;
+    royalty_version.bump = *ctx.bumps.get("royalty_version").unwrap();
+
+    emit!(NftMinted {
+        mint: ctx.accounts.mint.key(),
+        owner: ctx.accounts.payer.key(),
+        royalty,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
     Ok(())
 }
 
 
-pub fn create_order(ctx: Context<CreateOrder>, memo: String, price: u64) -> Result<()> {
+pub fn create_order(ctx: Context<CreateOrder>, memo: String, price: u64, listing_id: u64, expires_at: Option<i64>) -> Result<()> {
     let order = &mut ctx.accounts.order;
 
+    if let Some(expires_at) = expires_at {
+        if Clock::get()?.unix_timestamp >= expires_at {
+            return Err(error!(MarketError::InvalidExpiry));
+        }
+    }
+
+    let fee = ctx.accounts.marketplace.fee(price)?;
     anchor_lang::solana_program::program::invoke(
         &anchor_lang::solana_program::system_instruction::transfer(
             &ctx.accounts.creator.to_account_info().key(),
             &ctx.accounts.treasury_account.to_account_info().key(),
-            (price * 2)/100
+            fee
         ),
         &[
             ctx.accounts.creator.to_account_info(),
@@ -111,8 +140,11 @@ pub fn create_order(ctx: Context<CreateOrder>, memo: String, price: u64) -> Resu
 
     order.creator = ctx.accounts.creator.key();
     order.mint_key = ctx.accounts.mint_key.key();
+    order.listing_id = listing_id;
     order.memo = memo;
     order.price = price;
+    order.payment_mint = None;
+    order.expires_at = expires_at;
     order.bump = *ctx.bumps.get("order").unwrap();
 
     //
@@ -130,6 +162,17 @@ pub fn create_order(ctx: Context<CreateOrder>, memo: String, price: u64) -> Resu
         return Err(error!(MarketError::TokenTransferFailed));
     }
 
+    emit!(OrderCreated {
+        order: order.key(),
+        mint: order.mint_key,
+        creator: order.creator,
+        price,
+        payment_mint: None,
+        fee,
+        expires_at,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
     return Ok(());
 }
 
@@ -143,9 +186,13 @@ pub fn cancel_order(ctx: Context<CancelOrder>) -> Result<()> {
         to: ctx.accounts.creator_token_account.to_account_info(),
         authority: ctx.accounts.order.to_account_info(),
     };
+    let creator_key = ctx.accounts.order.creator;
+    let listing_id = ctx.accounts.order.listing_id.to_le_bytes();
     let seeds = &[
         b"order",
         ctx.accounts.mint_key.to_account_info().key.as_ref(),
+        creator_key.as_ref(),
+        &listing_id,
         &[ctx.accounts.order.bump]
     ];
     let signer = &[&seeds[..]];
@@ -170,14 +217,120 @@ pub fn cancel_order(ctx: Context<CancelOrder>) -> Result<()> {
         return Err(error!(MarketError::TokenCloseFailed));
     }
 
+    emit!(OrderCancelled {
+        order: ctx.accounts.order.key(),
+        mint: ctx.accounts.mint_key.key(),
+        creator: creator_key,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
     return Ok(());
 }
 
-pub fn fill_order(ctx: Context<FillOrder>, seller_fee_basis_points: u16) -> Result<()> {
+
+// Anyone may close an expired order; the nft goes back to the seller's associated token account,
+// created at the closer's expense if missing, and the rent to the seller.
+pub fn close_expired_order(ctx: Context<CloseExpiredOrder>) -> Result<()> {
+    if !ctx.accounts.order.is_expired(Clock::get()?.unix_timestamp) {
+        return Err(error!(MarketError::OrderNotExpired));
+    }
+
+    //
+    // Transfer nft from order token account back into creator's token account.
+    //
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.order_token_account.to_account_info(),
+        to: ctx.accounts.creator_token_account.to_account_info(),
+        authority: ctx.accounts.order.to_account_info(),
+    };
+    let creator_key = ctx.accounts.order.creator;
+    let listing_id = ctx.accounts.order.listing_id.to_le_bytes();
+    let seeds = &[
+        b"order",
+        ctx.accounts.mint_key.to_account_info().key.as_ref(),
+        creator_key.as_ref(),
+        &listing_id,
+        &[ctx.accounts.order.bump]
+    ];
+    let signer = &[&seeds[..]];
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    let result = transfer(cpi_ctx, 1);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenTransferFailed2));
+    }
+
+    //
+    // Close order token account.
+    //
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_accounts = CloseAccount {
+        account: ctx.accounts.order_token_account.to_account_info(),
+        destination: ctx.accounts.creator.to_account_info(),
+        authority: ctx.accounts.order.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    let result = close_account(cpi_ctx);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenCloseFailed));
+    }
+
+    emit!(OrderCancelled {
+        order: ctx.accounts.order.key(),
+        mint: ctx.accounts.mint_key.key(),
+        creator: creator_key,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+// Price increases pay the listing fee on the difference; decreases are not refunded.
+pub fn update_order(ctx: Context<UpdateOrder>, memo: String, price: u64) -> Result<()> {
+    let order = &mut ctx.accounts.order;
+
+    let marketplace = &ctx.accounts.marketplace;
+    let fee = marketplace.fee(price)?.saturating_sub(marketplace.fee(order.price)?);
+    if fee > 0 {
+        anchor_lang::solana_program::program::invoke(
+            &anchor_lang::solana_program::system_instruction::transfer(
+                &ctx.accounts.creator.key(),
+                &ctx.accounts.treasury_account.key(),
+                fee
+            ),
+            &[
+                ctx.accounts.creator.to_account_info(),
+                ctx.accounts.treasury_account.to_account_info(),
+                ctx.accounts.system_program.to_account_info()
+            ]
+        )?;
+    }
+
+    order.memo = memo;
+    order.price = price;
+
+    emit!(OrderUpdated {
+        order: order.key(),
+        mint: order.mint_key,
+        creator: order.creator,
+        price,
+        payment_mint: None,
+        fee,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+pub fn fill_order<'info>(ctx: Context<'_, '_, '_, 'info, FillOrder<'info>>) -> Result<()> {
     let order = &mut ctx.accounts.order;
     let buyer = &mut ctx.accounts.buyer;
     let creator = &mut ctx.accounts.creator;
-    let minter_account = &ctx.accounts.minter_account;
+
+    if order.is_expired(Clock::get()?.unix_timestamp) {
+        return Err(error!(MarketError::OrderExpired));
+    }
 
     //
     // Check buyer's balance against order's price.
@@ -186,29 +339,32 @@ pub fn fill_order(ctx: Context<FillOrder>, seller_fee_basis_points: u16) -> Resu
         return Err(error!(MarketError::InsufficientMoney));
     }
 
-    //let metadata:Metadata = Metadata::from_account_info(&ctx.accounts.metadata)?;
-    let royalty_points = seller_fee_basis_points as u64;
-    let royalty = (order.price * royalty_points)/100;
+    let metadata: Metadata = Metadata::from_account_info(&ctx.accounts.metadata)?;
+    if metadata.mint != ctx.accounts.mint_key.key() {
+        return Err(error!(MarketError::InvalidMetadata));
+    }
+    let royalties = creator_royalties(&metadata, order.price, None, ctx.remaining_accounts)?;
+    let royalty: u64 = royalties.iter().map(|(_, amount)| amount).sum();
     msg!("royalty {}", royalty);
-    //let minter = metadata.update_authority;
-    //msg!("minter {}", minter.to_string());
 
-    let price = order.price - royalty as u64;
+    let price = order.price.checked_sub(royalty).ok_or_else(|| error!(MarketError::MathOverflow))?;
     msg!("price {}", price);
     //
-    // Transfer Royalty to minter
-    anchor_lang::solana_program::program::invoke(
-        &anchor_lang::solana_program::system_instruction::transfer(
-            &buyer.to_account_info().key(),
-            &minter_account.to_account_info().key(),
-            royalty
-        ),
-        &[
-            buyer.to_account_info(),
-            minter_account.to_account_info(),
-            ctx.accounts.system_program.to_account_info()
-        ]
-    )?;
+    // Transfer royalty to each creator
+    for (creator_account, amount) in royalties {
+        anchor_lang::solana_program::program::invoke(
+            &anchor_lang::solana_program::system_instruction::transfer(
+                &buyer.to_account_info().key(),
+                &creator_account.key(),
+                amount
+            ),
+            &[
+                buyer.to_account_info(),
+                creator_account,
+                ctx.accounts.system_program.to_account_info()
+            ]
+        )?;
+    }
 
 
 
@@ -232,9 +388,13 @@ pub fn fill_order(ctx: Context<FillOrder>, seller_fee_basis_points: u16) -> Resu
     //
     // Transfer order token account's token into buyer token account.
     //
+    let creator_key = order.creator;
+    let listing_id = order.listing_id.to_le_bytes();
     let seeds = &[
         b"order",
         ctx.accounts.mint_key.key.as_ref(),
+        creator_key.as_ref(),
+        &listing_id,
         &[order.bump]
     ];
     let signer = &[&seeds[..]];
@@ -265,105 +425,146 @@ pub fn fill_order(ctx: Context<FillOrder>, seller_fee_basis_points: u16) -> Resu
         return Err(error!(MarketError::TokenCloseFailed));
     }
 
+    emit!(OrderFilled {
+        order: ctx.accounts.order.key(),
+        mint: ctx.accounts.mint_key.key(),
+        seller: creator_key,
+        buyer: ctx.accounts.buyer.key(),
+        price: ctx.accounts.order.price,
+        payment_mint: ctx.accounts.order.payment_mint,
+        royalty,
+        fee: 0,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
     
     Ok(())
 }
 
 
-pub fn auction_resolve(ctx: Context<AuctionResolve>, seller_fee_basis_points: u16) -> Result<()> {
+// Settlement is permissionless: every account is checked against the auction, the
+// winner's token account is created if missing, and whoever settles is paid the part of
+// the listing fee that was held back for it.
+pub fn auction_resolve(ctx: Context<AuctionResolve>) -> Result<()> {
     let auction = &ctx.accounts.auction;
-    //let minter_account = &ctx.accounts.minter_account;
 
 
-    if (Clock::get()?.unix_timestamp as u128) < auction.end_time {
+    if Clock::get()?.unix_timestamp < auction.end_time {
         return Err(error!(MarketError::AuctionNotEnded));
     }
 
-    if auction.refund_receiver != auction.creator { 
+    if Clock::get()?.unix_timestamp < auction.reveal_end_time {
+        return Err(error!(MarketError::RevealNotEnded));
+    }
 
-        let royalty_points = seller_fee_basis_points as u64;
-        let royalty = (auction.price * royalty_points)/100;
-        msg!("royalty {}", royalty);
+    let creator_key = auction.creator;
+    let listing_id = auction.listing_id.to_le_bytes();
+    let seeds = &[
+        b"auction",
+        ctx.accounts.mint_key.key.as_ref(),
+        creator_key.as_ref(),
+        &listing_id,
+        &[auction.bump],
+    ];
+    let signer = &[&seeds[..]];
 
+    //
+    // A hidden reserve can still be revealed for a while after the auction ends, and
+    // the auction can not be settled around it until then.
+    //
+    let has_bid = auction.refund_receiver != auction.creator;
+    if has_bid && auction.awaiting_reserve(Clock::get()?.unix_timestamp) {
+        return Err(error!(MarketError::ReserveNotRevealed));
+    }
 
-        // Transfer royalty to minter account
+    //
+    // Return the leading bid if it did not meet the reserve price.
+    //
+    if has_bid && !auction.reserve_met() {
+        msg!("reserve price not met");
+        let price = auction.price;
+        withdraw_bid(&mut ctx.accounts.bid_vault, &ctx.accounts.refund_receiver, price)?;
+    }
 
-        **ctx.accounts.auction.to_account_info().try_borrow_mut_lamports()?  -= royalty;
-        **ctx.accounts.minter_account.try_borrow_mut_lamports()? += royalty;
+    let sold = has_bid && auction.reserve_met();
+    let (nft_receiver, sale_price, royalty) = if sold {
 
+        let metadata: Metadata = Metadata::from_account_info(&ctx.accounts.metadata)?;
+        if metadata.mint != ctx.accounts.mint_key.key() {
+            return Err(error!(MarketError::InvalidMetadata));
+        }
 
-        let price = auction.price - royalty as u64;
-        msg!("price {}", price);
-        
         //
-        // Transfer auction token account's token into winner token account.
+        // Second price auctions charge the runner-up's bid and hand the difference back.
         //
-        let seeds = &[
-            b"auction",
-            ctx.accounts.mint_key.key.as_ref(),
-            &[auction.bump],
-        ];
-        let signer = &[&seeds[..]];
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.auction_token_account.to_account_info(),
-            to: ctx.accounts.refund_receiver_token_account.to_account_info(),
-            authority: auction.to_account_info(),
-        };
-        let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        let result = transfer(cpi_context, 1);
-        if let Err(_) = result {
-            return Err(error!(MarketError::TokenTransferFailed3));
+        let clearing_price = auction.clearing_price();
+        if clearing_price < auction.price {
+            withdraw_bid(&mut ctx.accounts.bid_vault, &ctx.accounts.refund_receiver, auction.price - clearing_price)?;
         }
 
+        let royalties = creator_royalties(&metadata, clearing_price, None, ctx.remaining_accounts)?;
+        let royalty: u64 = royalties.iter().map(|(_, amount)| amount).sum();
+        msg!("royalty {}", royalty);
+
 
+        // Transfer royalty from bid vault to each creator account
 
-        // Transfer bid price to creator account
+        for (creator_account, amount) in royalties {
+            withdraw_bid(&mut ctx.accounts.bid_vault, &creator_account, amount)?;
+        }
 
-        **ctx.accounts.auction.to_account_info().try_borrow_mut_lamports()?  -= price;
-        **ctx.accounts.creator.try_borrow_mut_lamports()? += price;
 
-        
+        // Transfer the rest of the winning bid to creator account
 
-    }
+        let price = ctx.accounts.bid_vault.amount;
+        msg!("price {}", price);
+        withdraw_bid(&mut ctx.accounts.bid_vault, &ctx.accounts.creator, price)?;
+
+        //
+        // The winner may not hold a token account for this mint yet, so the settler pays for it.
+        //
+        if ctx.accounts.refund_receiver_token_account.data_is_empty() {
+            let cpi_program = ctx.accounts.associated_token_program.to_account_info();
+            let cpi_accounts = Create {
+                payer: ctx.accounts.settler.to_account_info(),
+                associated_token: ctx.accounts.refund_receiver_token_account.to_account_info(),
+                authority: ctx.accounts.refund_receiver.to_account_info(),
+                mint: ctx.accounts.mint_key.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+            };
+            create(CpiContext::new(cpi_program, cpi_accounts))?;
+        }
 
+        (ctx.accounts.refund_receiver_token_account.to_account_info(), clearing_price, royalty)
+    } else {
+        (ctx.accounts.creator_token_account.to_account_info(), 0, 0)
+    };
 
-    
     //
-    // Transfer nft from auction token account back into creator's token account.
+    // Transfer auction token account's token into winner's, or back into creator's token account.
     //
     let cpi_program = ctx.accounts.token_program.to_account_info();
     let cpi_accounts = Transfer {
         from: ctx.accounts.auction_token_account.to_account_info(),
-        to: ctx.accounts.creator_token_account.to_account_info(),
-        authority: auction.to_account_info(),
+        to: nft_receiver,
+        authority: ctx.accounts.auction.to_account_info(),
     };
-    let seeds = &[
-        b"auction",
-        ctx.accounts.mint_key.to_account_info().key.as_ref(),
-        &[auction.bump]
-    ];
-    let signer = &[&seeds[..]];
-    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-    let result = transfer(cpi_ctx, 1);
+    let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    let result = transfer(cpi_context, 1);
     if let Err(_) = result {
-        return Err(error!(MarketError::TokenTransferFailed2));
+        return Err(error!(MarketError::TokenTransferFailed3));
     }
     
     //
     // Close auction token account.
     //
-    let seeds = &[
-        b"auction",
-        ctx.accounts.mint_key.key.as_ref(),
-        &[auction.bump],
-    ];
-    let signer = &[&seeds[..]];
     let cpi_program = ctx.accounts.token_program.to_account_info();
     let cpi_accounts = CloseAccount {
         account: ctx.accounts.auction_token_account.to_account_info(),
         destination: ctx.accounts.creator.to_account_info(),
-        authority: auction.to_account_info(),
+        authority: ctx.accounts.auction.to_account_info(),
     };
     let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
     let result = close_account(cpi_context);
@@ -371,6 +572,29 @@ pub fn auction_resolve(ctx: Context<AuctionResolve>, seller_fee_basis_points: u1
         return Err(error!(MarketError::TokenCloseFailed));
     }
 
+    let fee = release_listing_fee(&mut ctx.accounts.auction, &ctx.accounts.treasury_account)?;
+
+    //
+    // Pay the settler's crank fee out of the auction account before it is closed.
+    //
+    let crank_fee = ctx.accounts.auction.crank_fee;
+    if crank_fee > 0 {
+        move_lamports(&ctx.accounts.auction.to_account_info(), &ctx.accounts.settler.to_account_info(), crank_fee)?;
+    }
+
+    emit!(AuctionResolved {
+        auction: ctx.accounts.auction.key(),
+        mint: ctx.accounts.mint_key.key(),
+        creator: creator_key,
+        winner: if sold { Some(ctx.accounts.refund_receiver.key()) } else { None },
+        price: sale_price,
+        payment_mint: None,
+        royalty,
+        fee,
+        crank_fee,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
     Ok(())
 }
 
@@ -383,48 +607,67 @@ pub fn bid(ctx: Context<Bid>, price: u64) -> Result<()> {
     let bidder = &mut ctx.accounts.bidder;
 
     //
-    // Check bidder's balance against auction's price.
+    // Check bidder's price against the lowest bid that takes the lead.
     //
-    if price < auction.price {
+    if price < auction.min_bid()? {
         return Err(error!(MarketError::InsufficientMoney));
     }
 
-    if (Clock::get()?.unix_timestamp as u128) > auction.end_time {
+    if Clock::get()?.unix_timestamp < auction.start_time {
+        return Err(error!(MarketError::AuctionNotStarted));
+    }
+
+    if Clock::get()?.unix_timestamp > auction.end_time {
         return Err(error!(MarketError::AuctionEnded));
     }
 
 
-    //if refund_receiver exist return the money
+    //if refund_receiver exist return the money from the bid vault
     if auction.refund_receiver != auction.creator {
-
-        
-        **ctx.accounts.auction.to_account_info().try_borrow_mut_lamports()?  -= auction.price;
-        **ctx.accounts.refund_receiver.try_borrow_mut_lamports()? += auction.price;
-
-
+        withdraw_bid(&mut ctx.accounts.bid_vault, &ctx.accounts.refund_receiver, auction.price)?;
     }
 
-    // Transfer bid price to auction  account
+    // Transfer bid price to bid vault account
 
     anchor_lang::solana_program::program::invoke(
     &anchor_lang::solana_program::system_instruction::transfer(
         &bidder.to_account_info().key(),
-        &auction.to_account_info().key(),
+        &ctx.accounts.bid_vault.key(),
         price
     ),
     &[
         bidder.to_account_info(),
-        auction.to_account_info(),
+        ctx.accounts.bid_vault.to_account_info(),
         ctx.accounts.system_program.to_account_info()
     ]
     )?;
+    ctx.accounts.bid_vault.amount = ctx.accounts.bid_vault.amount.checked_add(price).ok_or_else(|| error!(MarketError::MathOverflow))?;
 
 
     //update the auction info
 
     let auction = &mut ctx.accounts.auction;
     auction.refund_receiver = ctx.accounts.bidder.key();
-    auction.price = price;  
+    auction.price = price;
+
+    emit!(BidPlaced {
+        auction: auction.key(),
+        mint: auction.mint_key,
+        bidder: auction.refund_receiver,
+        price,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    //
+    // A bid close to the end pushes the auction's end back.
+    //
+    if auction.extend(Clock::get()?.unix_timestamp)? {
+        emit!(AuctionExtended {
+            auction: auction.key(),
+            end_time: auction.end_time,
+            extensions: auction.extensions,
+        });
+    }
 
     Ok(())
 
@@ -432,31 +675,177 @@ pub fn bid(ctx: Context<Bid>, price: u64) -> Result<()> {
 }
 
 
-pub fn cancel_auction(ctx: Context<CancelAuction>) -> Result<()> {
+// Same as `bid`, but the outbid amount is credited to the previous bidder's `BidRefund`
+// account instead of being pushed to their wallet. The previous bidder withdraws it
+// with `claim_refund`.
+pub fn deferred_bid<'info>(ctx: Context<'_, '_, '_, 'info, DeferredBid<'info>>, price: u64) -> Result<()> {
     let auction = &ctx.accounts.auction;
 
+    if price < auction.min_bid()? {
+        return Err(error!(MarketError::InsufficientMoney));
+    }
+
+    if Clock::get()?.unix_timestamp < auction.start_time {
+        return Err(error!(MarketError::AuctionNotStarted));
+    }
+
+    if Clock::get()?.unix_timestamp > auction.end_time {
+        return Err(error!(MarketError::AuctionEnded));
+    }
+
+    let bidder_refund = &mut ctx.accounts.bidder_refund;
+    bidder_refund.auction = auction.key();
+    bidder_refund.bidder = ctx.accounts.bidder.key();
+    bidder_refund.bump = *ctx.bumps.get("bidder_refund").unwrap();
 
-    /*
-    if ctx.accounts.refund_receiver.key() != Pubkey::default() || auction.refund_receiver {
-        return Err(error!(MarketError::InvalidReceiver));
-    } */
 
     //
-    // Check if the auction is ended.
+    // Credit the outbid amount to the previous bidder's refund account.
     //
-    if (Clock::get()?.unix_timestamp as u128) > auction.end_time {
-        return Err(error!(MarketError::AuctionEnded));
+    let previous_bidder = auction.refund_receiver;
+    let outbid_amount = auction.price;
+    if previous_bidder == ctx.accounts.bidder.key() {
+        let bidder_refund = ctx.accounts.bidder_refund.to_account_info();
+        withdraw_bid(&mut ctx.accounts.bid_vault, &bidder_refund, outbid_amount)?;
+        ctx.accounts.bidder_refund.amount = ctx.accounts.bidder_refund.amount.checked_add(outbid_amount).ok_or_else(|| error!(MarketError::MathOverflow))?;
+    } else if previous_bidder != auction.creator {
+        let (refund_key, _) = Pubkey::find_program_address(
+            &[b"bid_refund", auction.key().as_ref(), previous_bidder.as_ref()],
+            ctx.program_id,
+        );
+        if ctx.accounts.previous_bid_refund.key() != refund_key {
+            return Err(error!(MarketError::InvalidReceiver));
+        }
+
+        if ctx.accounts.previous_bid_refund.owner == ctx.program_id {
+            let mut previous_bid_refund: Account<BidRefund> = Account::try_from(&ctx.accounts.previous_bid_refund)?;
+            withdraw_bid(&mut ctx.accounts.bid_vault, &ctx.accounts.previous_bid_refund, outbid_amount)?;
+            previous_bid_refund.amount = previous_bid_refund.amount.checked_add(outbid_amount).ok_or_else(|| error!(MarketError::MathOverflow))?;
+            previous_bid_refund.exit(ctx.program_id)?;
+        } else {
+            //
+            // The previous bidder has no refund account, because they bid with `bid` or
+            // already claimed it, so the outbid amount goes back to their wallet.
+            //
+            withdraw_bid(&mut ctx.accounts.bid_vault, &ctx.accounts.previous_bidder, outbid_amount)?;
+        }
     }
 
-    if auction.refund_receiver != auction.creator {
+    // Transfer bid price to bid vault account
+
+    anchor_lang::solana_program::program::invoke(
+    &anchor_lang::solana_program::system_instruction::transfer(
+        &ctx.accounts.bidder.key(),
+        &ctx.accounts.bid_vault.key(),
+        price
+    ),
+    &[
+        ctx.accounts.bidder.to_account_info(),
+        ctx.accounts.bid_vault.to_account_info(),
+        ctx.accounts.system_program.to_account_info()
+    ]
+    )?;
+    ctx.accounts.bid_vault.amount = ctx.accounts.bid_vault.amount.checked_add(price).ok_or_else(|| error!(MarketError::MathOverflow))?;
+
+
+    //update the auction info
+
+    let auction = &mut ctx.accounts.auction;
+    auction.refund_receiver = ctx.accounts.bidder.key();
+    auction.price = price;
+
+    emit!(BidPlaced {
+        auction: auction.key(),
+        mint: auction.mint_key,
+        bidder: auction.refund_receiver,
+        price,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    //
+    // A bid close to the end pushes the auction's end back.
+    //
+    if auction.extend(Clock::get()?.unix_timestamp)? {
+        emit!(AuctionExtended {
+            auction: auction.key(),
+            end_time: auction.end_time,
+            extensions: auction.extensions,
+        });
+    }
+
+    Ok(())
+}
+
+
+// Publishes a hidden reserve price. It can be revealed until `RESERVE_REVEAL_WINDOW`
+// seconds after the auction ends; a reserve that is still hidden by then is never met.
+pub fn reveal_reserve(ctx: Context<RevealReserve>, reserve_price: u64, salt: [u8; 32]) -> Result<()> {
+    let auction = &mut ctx.accounts.auction;
+
+    if !auction.awaiting_reserve(Clock::get()?.unix_timestamp) {
+        return Err(error!(MarketError::ReserveRevealEnded));
+    }
+
+    let reserve_hash = hashv(&[&reserve_price.to_le_bytes(), &salt]).to_bytes();
+    if auction.reserve_hash != Some(reserve_hash) {
+        return Err(error!(MarketError::InvalidReserve));
+    }
+
+    auction.reserve_price = reserve_price;
+    auction.reserve_hash = None;
+
+    emit!(ReserveRevealed {
+        auction: auction.key(),
+        mint: auction.mint_key,
+        reserve_price,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+pub fn claim_refund(_ctx: Context<ClaimRefund>) -> Result<()> {
+    //
+    // Credited refunds and rent are returned to the bidder when the refund account is closed.
+    //
+    Ok(())
+}
+
 
+pub fn cancel_auction(ctx: Context<CancelAuction>) -> Result<()> {
+    let auction = &ctx.accounts.auction;
 
-        **ctx.accounts.auction.to_account_info().try_borrow_mut_lamports()?  -= auction.price;
-        **ctx.accounts.refund_receiver.try_borrow_mut_lamports()? += auction.price;
+    //
+    // Check if the auction is ended.
+    //
+    if Clock::get()?.unix_timestamp > auction.end_time {
+        return Err(error!(MarketError::AuctionEnded));
+    }
 
+    //
+    // Sealed bids are not visible on the auction, so those can only be cancelled before they start.
+    //
+    let started = Clock::get()?.unix_timestamp >= auction.start_time;
+    if auction.is_sealed() && started {
+        return Err(error!(MarketError::AuctionStarted));
+    }
 
+    //
+    // Cancelling is free before the auction starts: nobody can have bid yet, and the
+    // listing fee held back at creation is returned with the auction's rent. Once it has
+    // started the leading bid is refunded and the listing fee goes to the treasury.
+    //
+    if started {
+        if auction.refund_receiver != auction.creator {
+            let price = auction.price;
+            withdraw_bid(&mut ctx.accounts.bid_vault, &ctx.accounts.refund_receiver, price)?;
+        }
+        release_listing_fee(&mut ctx.accounts.auction, &ctx.accounts.treasury_account)?;
     }
 
+    let auction = &ctx.accounts.auction;
+
     //
     // Transfer nft from auction token account back into creator's token account.
     //
@@ -466,9 +855,13 @@ pub fn cancel_auction(ctx: Context<CancelAuction>) -> Result<()> {
         to: ctx.accounts.creator_token_account.to_account_info(),
         authority: auction.to_account_info(),
     };
+    let creator_key = auction.creator;
+    let listing_id = auction.listing_id.to_le_bytes();
     let seeds = &[
         b"auction",
         ctx.accounts.mint_key.to_account_info().key.as_ref(),
+        creator_key.as_ref(),
+        &listing_id,
         &[auction.bump]
     ];
     let signer = &[&seeds[..]];
@@ -493,40 +886,108 @@ pub fn cancel_auction(ctx: Context<CancelAuction>) -> Result<()> {
         return Err(error!(MarketError::TokenCloseFailed));
     }
 
+    emit!(AuctionCancelled {
+        auction: auction.key(),
+        mint: ctx.accounts.mint_key.key(),
+        creator: creator_key,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
     return Ok(());
 
 }
 
 
-pub fn create_auction(ctx: Context<CreateAuction>, memo: String, price: u64, start_time: u128, end_time: u128 ) -> Result<()> {
+pub fn create_auction(ctx: Context<CreateAuction>, memo: String, price: u64, start_time: i64, end_time: i64, listing_id: u64, min_increment_bps: u16, min_increment: u64, reserve_price: u64, reserve_hash: Option<[u8; 32]>, extension_window: u64, extension_duration: u64, max_extensions: u16, buy_now_price: Option<u64>) -> Result<()> {
     let auction = &mut ctx.accounts.auction;
 
-    if (Clock::get()?.unix_timestamp as u128) > end_time {
+    if Clock::get()?.unix_timestamp > end_time {
         return Err(error!(MarketError::InvalidEndTIme));
     }
 
+    if start_time >= end_time {
+        return Err(error!(MarketError::InvalidStartTime));
+    }
+
+    if matches!(buy_now_price, Some(buy_now_price) if buy_now_price < price) {
+        return Err(error!(MarketError::InvalidBuyNowPrice));
+    }
+
+    check_extensions(start_time, end_time, extension_window, extension_duration)?;
+
+    // A reserve is either public or committed to by hash, never both.
+    if reserve_hash.is_some() && reserve_price > 0 {
+        return Err(error!(MarketError::InvalidReserve));
+    }
+
+    let fee = ctx.accounts.marketplace.fee(price)?;
+    let crank_fee = ctx.accounts.marketplace.crank_fee(fee)?;
+
+    //
+    // A scheduled auction holds its listing fee until it starts, so cancelling it
+    // before then costs nothing. The rest go straight to the treasury.
+    //
+    let scheduled = Clock::get()?.unix_timestamp < start_time;
+    let listing_fee = fee.checked_sub(crank_fee).ok_or_else(|| error!(MarketError::MathOverflow))?;
     anchor_lang::solana_program::program::invoke(
         &anchor_lang::solana_program::system_instruction::transfer(
             &ctx.accounts.creator.to_account_info().key(),
-            &ctx.accounts.treasury_account.to_account_info().key(),
-            (price * 2)/100
+            &if scheduled { auction.key() } else { ctx.accounts.treasury_account.key() },
+            listing_fee
         ),
         &[
             ctx.accounts.creator.to_account_info(),
-            ctx.accounts.treasury_account.to_account_info(),
+            if scheduled { auction.to_account_info() } else { ctx.accounts.treasury_account.to_account_info() },
             ctx.accounts.system_program.to_account_info()
         ]
         )?;
 
+    //
+    // Hold the crank fee in the auction account until someone settles it. A cancelled
+    // auction never needs settling, so the creator gets it back on close.
+    //
+    if crank_fee > 0 {
+        anchor_lang::solana_program::program::invoke(
+            &anchor_lang::solana_program::system_instruction::transfer(
+                &ctx.accounts.creator.key(),
+                &auction.key(),
+                crank_fee
+            ),
+            &[
+                ctx.accounts.creator.to_account_info(),
+                auction.to_account_info(),
+                ctx.accounts.system_program.to_account_info()
+            ]
+        )?;
+    }
+
     auction.creator = ctx.accounts.creator.key();
     auction.mint_key = ctx.accounts.mint_key.key();
+    auction.listing_id = listing_id;
     auction.refund_receiver = ctx.accounts.creator.key();
     auction.memo = memo;
     auction.price = price;
+    auction.payment_mint = None;
     auction.start_time = start_time;
     auction.end_time = end_time;
+    auction.min_increment_bps = min_increment_bps;
+    auction.min_increment = min_increment;
+    auction.reserve_price = reserve_price;
+    auction.reserve_hash = reserve_hash;
+    auction.extension_window = extension_window;
+    auction.extension_duration = extension_duration;
+    auction.max_extensions = max_extensions;
+    auction.extensions = 0;
+    auction.buy_now_price = buy_now_price;
+    auction.crank_fee = crank_fee;
+    auction.listing_fee = if scheduled { listing_fee } else { 0 };
     auction.bump = *ctx.bumps.get("auction").unwrap();
 
+    let bid_vault = &mut ctx.accounts.bid_vault;
+    bid_vault.auction = auction.key();
+    bid_vault.amount = 0;
+    bid_vault.bump = *ctx.bumps.get("bid_vault").unwrap();
+
     // transfer nft from creator's token account into auction's token account.
     //
 
@@ -542,419 +1003,4034 @@ pub fn create_auction(ctx: Context<CreateAuction>, memo: String, price: u64, sta
         return Err(error!(MarketError::TokenTransferFailed));
     }
 
+    emit!(AuctionCreated {
+        auction: auction.key(),
+        mint: auction.mint_key,
+        creator: auction.creator,
+        price,
+        payment_mint: None,
+        fee,
+        start_time,
+        end_time,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
     return Ok(());
 } 
 
 
-}
-
-
+// Ends an open auction at its buy-now price, refunding whoever was leading.
+pub fn buy_now<'info>(ctx: Context<'_, '_, '_, 'info, BuyNow<'info>>) -> Result<()> {
+    let auction = &ctx.accounts.auction;
+    let buyer = &ctx.accounts.buyer;
+
+    match auction.phase(Clock::get()?.unix_timestamp) {
+        AuctionPhase::Pending => return Err(error!(MarketError::AuctionNotStarted)),
+        AuctionPhase::Bidding => {}
+        _ => return Err(error!(MarketError::AuctionEnded)),
+    }
+
+    //
+    // Buy-now goes away once bidding reaches it.
+    //
+    let price = match auction.buy_now_price {
+        Some(buy_now_price) if auction.refund_receiver == auction.creator || auction.price < buy_now_price => buy_now_price,
+        _ => return Err(error!(MarketError::BuyNowUnavailable)),
+    };
+
+    if auction.refund_receiver != auction.creator {
+        let refund = auction.price;
+        withdraw_bid(&mut ctx.accounts.bid_vault, &ctx.accounts.refund_receiver, refund)?;
+    }
+
+    let metadata: Metadata = Metadata::from_account_info(&ctx.accounts.metadata)?;
+    if metadata.mint != ctx.accounts.mint_key.key() {
+        return Err(error!(MarketError::InvalidMetadata));
+    }
+    let royalties = creator_royalties(&metadata, price, None, ctx.remaining_accounts)?;
+    let royalty: u64 = royalties.iter().map(|(_, amount)| amount).sum();
+    msg!("royalty {}", royalty);
+
+    //
+    // Transfer royalty to each creator
+    //
+    for (creator_account, amount) in royalties {
+        anchor_lang::solana_program::program::invoke(
+            &anchor_lang::solana_program::system_instruction::transfer(
+                &buyer.key(),
+                &creator_account.key(),
+                amount
+            ),
+            &[
+                buyer.to_account_info(),
+                creator_account,
+                ctx.accounts.system_program.to_account_info()
+            ]
+        )?;
+    }
+
+    //
+    // Transfer the rest of the buy-now price from buyer into creator.
+    //
+    anchor_lang::solana_program::program::invoke(
+        &anchor_lang::solana_program::system_instruction::transfer(
+            &buyer.key(),
+            &ctx.accounts.creator.key(),
+            price.checked_sub(royalty).ok_or_else(|| error!(MarketError::MathOverflow))?
+        ),
+        &[
+            buyer.to_account_info(),
+            ctx.accounts.creator.to_account_info(),
+            ctx.accounts.system_program.to_account_info()
+        ]
+    )?;
+
+    let creator_key = auction.creator;
+    let listing_id = auction.listing_id.to_le_bytes();
+    let seeds = &[
+        b"auction",
+        ctx.accounts.mint_key.key.as_ref(),
+        creator_key.as_ref(),
+        &listing_id,
+        &[auction.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    //
+    // Transfer auction token account's token into buyer token account.
+    //
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.auction_token_account.to_account_info(),
+        to: ctx.accounts.buyer_token_account.to_account_info(),
+        authority: auction.to_account_info(),
+    };
+    let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    let result = transfer(cpi_context, 1);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenTransferFailed3));
+    }
+
+    //
+    // Close auction token account.
+    //
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_accounts = CloseAccount {
+        account: ctx.accounts.auction_token_account.to_account_info(),
+        destination: ctx.accounts.creator.to_account_info(),
+        authority: auction.to_account_info(),
+    };
+    let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    let result = close_account(cpi_context);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenCloseFailed));
+    }
+
+    let fee = release_listing_fee(&mut ctx.accounts.auction, &ctx.accounts.treasury_account)?;
+
+    emit!(AuctionResolved {
+        auction: ctx.accounts.auction.key(),
+        mint: ctx.accounts.mint_key.key(),
+        creator: creator_key,
+        winner: Some(ctx.accounts.buyer.key()),
+        price,
+        payment_mint: None,
+        royalty,
+        fee,
+        crank_fee: 0,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+// Creates an auction whose bids are sealed: bidders `commit_bid` a hash and a deposit
+// until `end_time`, `reveal_bid` until `reveal_end_time`, and `auction_resolve` then
+// settles to the highest revealed bid, or to the runner-up's bid when `second_price` is set.
+pub fn create_sealed_auction<'info>(ctx: Context<'_, '_, '_, 'info, CreateAuction<'info>>, memo: String, price: u64, start_time: i64, end_time: i64, listing_id: u64, reveal_end_time: i64, second_price: bool, min_deposit: u64, slash_bps: u16) -> Result<()> {
+    if reveal_end_time <= end_time {
+        return Err(error!(MarketError::InvalidRevealTime));
+    }
+
+    if slash_bps > 10000 {
+        return Err(error!(MarketError::InvalidSlash));
+    }
+
+    let bumps = ctx.bumps.clone();
+    create_auction(
+        Context::new(ctx.program_id, &mut *ctx.accounts, ctx.remaining_accounts, bumps),
+        memo, price, start_time, end_time, listing_id, 0, 0, 0, None, 0, 0, 0, None
+    )?;
+
+    let auction = &mut ctx.accounts.auction;
+    auction.reveal_end_time = reveal_end_time;
+    auction.second_price = second_price;
+    auction.min_deposit = min_deposit;
+    auction.slash_bps = slash_bps;
+
+    Ok(())
+}
+
+
+pub fn commit_bid(ctx: Context<CommitBid>, commitment: [u8; 32], deposit: u64) -> Result<()> {
+    let auction = &ctx.accounts.auction;
+
+    if auction.phase(Clock::get()?.unix_timestamp) != AuctionPhase::Bidding {
+        return Err(error!(MarketError::NotBiddingPhase));
+    }
+
+    if deposit < auction.min_deposit {
+        return Err(error!(MarketError::InsufficientMoney));
+    }
+
+    let sealed_bid = &mut ctx.accounts.sealed_bid;
+    sealed_bid.auction = auction.key();
+    sealed_bid.bidder = ctx.accounts.bidder.key();
+    sealed_bid.creator = auction.creator;
+    sealed_bid.commitment = commitment;
+    sealed_bid.deposit = deposit;
+    sealed_bid.revealed = false;
+    sealed_bid.amount = 0;
+    sealed_bid.reveal_end_time = auction.reveal_end_time;
+    sealed_bid.slash_bps = auction.slash_bps;
+    sealed_bid.bump = *ctx.bumps.get("sealed_bid").unwrap();
+
+    //
+    // Escrow the deposit, which hides the bid amount as long as it covers it.
+    //
+    anchor_lang::solana_program::program::invoke(
+        &anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.bidder.key(),
+            &sealed_bid.key(),
+            deposit
+        ),
+        &[
+            ctx.accounts.bidder.to_account_info(),
+            sealed_bid.to_account_info(),
+            ctx.accounts.system_program.to_account_info()
+        ]
+    )?;
+
+    Ok(())
+}
+
+
+pub fn reveal_bid<'info>(ctx: Context<'_, '_, '_, 'info, RevealBid<'info>>, amount: u64, salt: [u8; 32]) -> Result<()> {
+    let auction = &ctx.accounts.auction;
+
+    if auction.phase(Clock::get()?.unix_timestamp) != AuctionPhase::Reveal {
+        return Err(error!(MarketError::NotRevealPhase));
+    }
+
+    let sealed_bid = &mut ctx.accounts.sealed_bid;
+    if sealed_bid.revealed {
+        return Err(error!(MarketError::AlreadyRevealed));
+    }
+
+    let commitment = hashv(&[&amount.to_le_bytes(), &salt]).to_bytes();
+    if sealed_bid.commitment != commitment {
+        return Err(error!(MarketError::InvalidCommitment));
+    }
+
+    if amount > sealed_bid.deposit {
+        return Err(error!(MarketError::InsufficientMoney));
+    }
+
+    sealed_bid.revealed = true;
+    sealed_bid.amount = amount;
+
+    let has_bid = auction.refund_receiver != auction.creator;
+    let takes_lead = if has_bid { amount > auction.price } else { amount >= auction.price };
+    if !takes_lead {
+        if has_bid && amount > auction.second_bid {
+            ctx.accounts.auction.second_bid = amount;
+        }
+        return Ok(());
+    }
+
+    //
+    // Hand the previous leader's bid back to their sealed bid account.
+    //
+    if has_bid {
+        let (previous_key, _) = Pubkey::find_program_address(
+            &[b"sealed_bid", auction.key().as_ref(), auction.refund_receiver.as_ref()],
+            ctx.program_id,
+        );
+        if ctx.accounts.previous_sealed_bid.key() != previous_key {
+            return Err(error!(MarketError::InvalidReceiver));
+        }
+
+        let mut previous_sealed_bid: Account<SealedBid> = Account::try_from(&ctx.accounts.previous_sealed_bid)?;
+        withdraw_bid(&mut ctx.accounts.bid_vault, &ctx.accounts.previous_sealed_bid, auction.price)?;
+        previous_sealed_bid.deposit = previous_sealed_bid.deposit.checked_add(auction.price).ok_or_else(|| error!(MarketError::MathOverflow))?;
+        previous_sealed_bid.exit(ctx.program_id)?;
+    }
+
+    //
+    // Move the revealed bid out of the deposit into the bid vault.
+    //
+    ctx.accounts.sealed_bid.deposit = ctx.accounts.sealed_bid.deposit.checked_sub(amount).ok_or_else(|| error!(MarketError::MathOverflow))?;
+    move_lamports(&ctx.accounts.sealed_bid.to_account_info(), &ctx.accounts.bid_vault.to_account_info(), amount)?;
+    ctx.accounts.bid_vault.amount = ctx.accounts.bid_vault.amount.checked_add(amount).ok_or_else(|| error!(MarketError::MathOverflow))?;
+
+    let auction = &mut ctx.accounts.auction;
+    auction.second_bid = auction.price;
+    auction.refund_receiver = ctx.accounts.bidder.key();
+    auction.price = amount;
+
+    emit!(BidPlaced {
+        auction: auction.key(),
+        mint: auction.mint_key,
+        bidder: auction.refund_receiver,
+        price: amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+// Returns a sealed bid's deposit once the reveal window is over. Bids that were never
+// revealed lose `slash_bps` of their deposit to the auction's creator.
+pub fn withdraw_sealed_bid(ctx: Context<WithdrawSealedBid>) -> Result<()> {
+    let sealed_bid = &mut ctx.accounts.sealed_bid;
+
+    if Clock::get()?.unix_timestamp < sealed_bid.reveal_end_time {
+        return Err(error!(MarketError::RevealNotEnded));
+    }
+
+    if !sealed_bid.revealed {
+        let slash = proportion(sealed_bid.deposit, sealed_bid.slash_bps as u64, MAX_BASIS_POINTS as u64)?;
+        msg!("slashed {}", slash);
+        sealed_bid.deposit = sealed_bid.deposit.checked_sub(slash).ok_or_else(|| error!(MarketError::MathOverflow))?;
+        move_lamports(&sealed_bid.to_account_info(), &ctx.accounts.creator, slash)?;
+    }
+
+    //
+    // The rest of the deposit and rent are returned to the bidder when the account is closed.
+    //
+    Ok(())
+}
+
+
+pub fn create_token_order(ctx: Context<CreateTokenOrder>, memo: String, price: u64, listing_id: u64, expires_at: Option<i64>) -> Result<()> {
+    let order = &mut ctx.accounts.order;
+
+    if let Some(expires_at) = expires_at {
+        if Clock::get()?.unix_timestamp >= expires_at {
+            return Err(error!(MarketError::InvalidExpiry));
+        }
+    }
+
+    //
+    // Pay listing fee from creator's payment token account into treasury's payment token account.
+    //
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.creator_payment_account.to_account_info(),
+        to: ctx.accounts.treasury_payment_account.to_account_info(),
+        authority: ctx.accounts.creator.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    let fee = ctx.accounts.marketplace.fee(price)?;
+    transfer(cpi_ctx, fee)?;
+
+    order.creator = ctx.accounts.creator.key();
+    order.mint_key = ctx.accounts.mint_key.key();
+    order.listing_id = listing_id;
+    order.memo = memo;
+    order.price = price;
+    order.payment_mint = Some(ctx.accounts.payment_mint.key());
+    order.expires_at = expires_at;
+    order.bump = *ctx.bumps.get("order").unwrap();
+
+    //
+    // transfer nft from creator's token account into order's token account.
+    //
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.creator_token_account.to_account_info(),
+        to: ctx.accounts.order_token_account.to_account_info(),
+        authority: ctx.accounts.creator.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    let result = transfer(cpi_ctx, 1);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenTransferFailed));
+    }
+
+    emit!(OrderCreated {
+        order: order.key(),
+        mint: order.mint_key,
+        creator: order.creator,
+        price,
+        payment_mint: order.payment_mint,
+        fee,
+        expires_at,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+pub fn fill_token_order<'info>(ctx: Context<'_, '_, '_, 'info, FillTokenOrder<'info>>) -> Result<()> {
+    let order = &ctx.accounts.order;
+
+    if order.is_expired(Clock::get()?.unix_timestamp) {
+        return Err(error!(MarketError::OrderExpired));
+    }
+
+    //
+    // Check buyer's token balance against order's price.
+    //
+    if ctx.accounts.buyer_payment_account.amount < order.price {
+        return Err(error!(MarketError::InsufficientMoney));
+    }
+
+    let metadata: Metadata = Metadata::from_account_info(&ctx.accounts.metadata)?;
+    if metadata.mint != ctx.accounts.mint_key.key() {
+        return Err(error!(MarketError::InvalidMetadata));
+    }
+    let royalties = creator_royalties(&metadata, order.price, order.payment_mint, ctx.remaining_accounts)?;
+    let royalty: u64 = royalties.iter().map(|(_, amount)| amount).sum();
+    msg!("royalty {}", royalty);
+
+    let price = order.price.checked_sub(royalty).ok_or_else(|| error!(MarketError::MathOverflow))?;
+    msg!("price {}", price);
+
+    //
+    // Transfer royalty to each creator's payment token account.
+    //
+    for (creator_account, amount) in royalties {
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.buyer_payment_account.to_account_info(),
+            to: creator_account,
+            authority: ctx.accounts.buyer.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        transfer(cpi_ctx, amount)?;
+    }
+
+    //
+    // Transfer order's money from buyer's payment token account into creator's.
+    //
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.buyer_payment_account.to_account_info(),
+        to: ctx.accounts.creator_payment_account.to_account_info(),
+        authority: ctx.accounts.buyer.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    transfer(cpi_ctx, price)?;
+
+    //
+    // Transfer order token account's token into buyer token account.
+    //
+    let creator_key = order.creator;
+    let listing_id = order.listing_id.to_le_bytes();
+    let seeds = &[
+        b"order",
+        ctx.accounts.mint_key.key.as_ref(),
+        creator_key.as_ref(),
+        &listing_id,
+        &[order.bump]
+    ];
+    let signer = &[&seeds[..]];
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.order_token_account.to_account_info(),
+        to: ctx.accounts.buyer_token_account.to_account_info(),
+        authority: ctx.accounts.order.to_account_info()
+    };
+    let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    let result = transfer(cpi_context, 1);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenTransferFailed3));
+    }
+
+    //
+    // Close order token account.
+    //
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_accounts = CloseAccount {
+        account: ctx.accounts.order_token_account.to_account_info(),
+        destination: ctx.accounts.creator.to_account_info(),
+        authority: ctx.accounts.order.to_account_info(),
+    };
+    let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    let result = close_account(cpi_context);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenCloseFailed));
+    }
+
+    emit!(OrderFilled {
+        order: ctx.accounts.order.key(),
+        mint: ctx.accounts.mint_key.key(),
+        seller: creator_key,
+        buyer: ctx.accounts.buyer.key(),
+        price: ctx.accounts.order.price,
+        payment_mint: ctx.accounts.order.payment_mint,
+        royalty,
+        fee: 0,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+// Price increases pay the listing fee on the difference; decreases are not refunded.
+pub fn update_token_order(ctx: Context<UpdateTokenOrder>, memo: String, price: u64) -> Result<()> {
+    let order = &mut ctx.accounts.order;
+
+    let marketplace = &ctx.accounts.marketplace;
+    let fee = marketplace.fee(price)?.saturating_sub(marketplace.fee(order.price)?);
+    if fee > 0 {
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.creator_payment_account.to_account_info(),
+            to: ctx.accounts.treasury_payment_account.to_account_info(),
+            authority: ctx.accounts.creator.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        transfer(cpi_ctx, fee)?;
+    }
+
+    order.memo = memo;
+    order.price = price;
+
+    emit!(OrderUpdated {
+        order: order.key(),
+        mint: order.mint_key,
+        creator: order.creator,
+        price,
+        payment_mint: order.payment_mint,
+        fee,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+pub fn create_token_auction(ctx: Context<CreateTokenAuction>, memo: String, price: u64, start_time: i64, end_time: i64, listing_id: u64, min_increment_bps: u16, min_increment: u64, reserve_price: u64, reserve_hash: Option<[u8; 32]>, extension_window: u64, extension_duration: u64, max_extensions: u16) -> Result<()> {
+    let auction = &mut ctx.accounts.auction;
+
+    if Clock::get()?.unix_timestamp > end_time {
+        return Err(error!(MarketError::InvalidEndTIme));
+    }
+
+    if start_time >= end_time {
+        return Err(error!(MarketError::InvalidStartTime));
+    }
+
+    check_extensions(start_time, end_time, extension_window, extension_duration)?;
+
+    // A reserve is either public or committed to by hash, never both.
+    if reserve_hash.is_some() && reserve_price > 0 {
+        return Err(error!(MarketError::InvalidReserve));
+    }
+
+    //
+    // Pay listing fee from creator's payment token account into treasury's payment token account,
+    // or into the auction's escrow until a scheduled auction starts.
+    //
+    let fee = ctx.accounts.marketplace.fee(price)?;
+    let crank_fee = ctx.accounts.marketplace.crank_fee(fee)?;
+    let listing_fee = fee.checked_sub(crank_fee).ok_or(MarketError::MathOverflow)?;
+    let scheduled = Clock::get()?.unix_timestamp < start_time;
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.creator_payment_account.to_account_info(),
+        to: if scheduled {
+            ctx.accounts.auction_payment_account.to_account_info()
+        } else {
+            ctx.accounts.treasury_payment_account.to_account_info()
+        },
+        authority: ctx.accounts.creator.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    transfer(cpi_ctx, listing_fee)?;
+
+    //
+    // Hold the crank fee in the auction's escrow until someone settles it. A cancelled
+    // auction never needs settling, so the creator gets it back on close.
+    //
+    if crank_fee > 0 {
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.creator_payment_account.to_account_info(),
+            to: ctx.accounts.auction_payment_account.to_account_info(),
+            authority: ctx.accounts.creator.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        transfer(cpi_ctx, crank_fee)?;
+    }
+
+    auction.creator = ctx.accounts.creator.key();
+    auction.mint_key = ctx.accounts.mint_key.key();
+    auction.listing_id = listing_id;
+    auction.refund_receiver = ctx.accounts.creator.key();
+    auction.memo = memo;
+    auction.price = price;
+    auction.payment_mint = Some(ctx.accounts.payment_mint.key());
+    auction.start_time = start_time;
+    auction.end_time = end_time;
+    auction.min_increment_bps = min_increment_bps;
+    auction.min_increment = min_increment;
+    auction.reserve_price = reserve_price;
+    auction.reserve_hash = reserve_hash;
+    auction.extension_window = extension_window;
+    auction.extension_duration = extension_duration;
+    auction.max_extensions = max_extensions;
+    auction.extensions = 0;
+    auction.buy_now_price = None;
+    auction.crank_fee = crank_fee;
+    auction.listing_fee = if scheduled { listing_fee } else { 0 };
+    auction.bump = *ctx.bumps.get("auction").unwrap();
+
+    //
+    // transfer nft from creator's token account into auction's token account.
+    //
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.creator_token_account.to_account_info(),
+        to: ctx.accounts.auction_token_account.to_account_info(),
+        authority: ctx.accounts.creator.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    let result = transfer(cpi_ctx, 1);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenTransferFailed));
+    }
+
+    emit!(AuctionCreated {
+        auction: auction.key(),
+        mint: auction.mint_key,
+        creator: auction.creator,
+        price,
+        payment_mint: auction.payment_mint,
+        fee,
+        start_time,
+        end_time,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+pub fn token_bid(ctx: Context<TokenBid>, price: u64) -> Result<()> {
+    let auction = &ctx.accounts.auction;
+
+    if price < auction.min_bid()? {
+        return Err(error!(MarketError::InsufficientMoney));
+    }
+
+    if Clock::get()?.unix_timestamp < auction.start_time {
+        return Err(error!(MarketError::AuctionNotStarted));
+    }
+
+    if Clock::get()?.unix_timestamp > auction.end_time {
+        return Err(error!(MarketError::AuctionEnded));
+    }
+
+    let creator_key = auction.creator;
+    let listing_id = auction.listing_id.to_le_bytes();
+    let seeds = &[
+        b"auction",
+        ctx.accounts.mint_key.key.as_ref(),
+        creator_key.as_ref(),
+        &listing_id,
+        &[auction.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    //
+    // If refund_receiver exist return the tokens from the auction's escrow.
+    //
+    if auction.refund_receiver != auction.creator {
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.auction_payment_account.to_account_info(),
+            to: ctx.accounts.refund_receiver_payment_account.to_account_info(),
+            authority: auction.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer(cpi_ctx, auction.price)?;
+    }
+
+    //
+    // Escrow bid price from bidder's payment token account into auction's.
+    //
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.bidder_payment_account.to_account_info(),
+        to: ctx.accounts.auction_payment_account.to_account_info(),
+        authority: ctx.accounts.bidder.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    transfer(cpi_ctx, price)?;
+
+    //update the auction info
+
+    let auction = &mut ctx.accounts.auction;
+    auction.refund_receiver = ctx.accounts.bidder.key();
+    auction.price = price;
+
+    emit!(BidPlaced {
+        auction: auction.key(),
+        mint: auction.mint_key,
+        bidder: auction.refund_receiver,
+        price,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    //
+    // A bid close to the end pushes the auction's end back.
+    //
+    if auction.extend(Clock::get()?.unix_timestamp)? {
+        emit!(AuctionExtended {
+            auction: auction.key(),
+            end_time: auction.end_time,
+            extensions: auction.extensions,
+        });
+    }
+
+    Ok(())
+}
+
+
+pub fn resolve_token_auction<'info>(ctx: Context<'_, '_, '_, 'info, ResolveTokenAuction<'info>>) -> Result<()> {
+    let auction = &ctx.accounts.auction;
+
+    if Clock::get()?.unix_timestamp < auction.end_time {
+        return Err(error!(MarketError::AuctionNotEnded));
+    }
+
+    let creator_key = auction.creator;
+    let listing_id = auction.listing_id.to_le_bytes();
+    let seeds = &[
+        b"auction",
+        ctx.accounts.mint_key.key.as_ref(),
+        creator_key.as_ref(),
+        &listing_id,
+        &[auction.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let has_bid = auction.refund_receiver != auction.creator;
+    if has_bid && auction.awaiting_reserve(Clock::get()?.unix_timestamp) {
+        return Err(error!(MarketError::ReserveNotRevealed));
+    }
+
+    //
+    // Return the leading bid if it did not meet the reserve price.
+    //
+    if has_bid && !auction.reserve_met() {
+        msg!("reserve price not met");
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.auction_payment_account.to_account_info(),
+            to: ctx.accounts.refund_receiver_payment_account.to_account_info(),
+            authority: auction.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer(cpi_ctx, auction.price)?;
+    }
+
+    let sold = has_bid && auction.reserve_met();
+    let (nft_receiver, sale_price, royalty) = if sold {
+        let metadata: Metadata = Metadata::from_account_info(&ctx.accounts.metadata)?;
+        if metadata.mint != ctx.accounts.mint_key.key() {
+            return Err(error!(MarketError::InvalidMetadata));
+        }
+        let royalties = creator_royalties(&metadata, auction.price, auction.payment_mint, ctx.remaining_accounts)?;
+        let royalty: u64 = royalties.iter().map(|(_, amount)| amount).sum();
+        msg!("royalty {}", royalty);
+
+        //
+        // Transfer royalty from auction's escrow to each creator's payment token account.
+        //
+        for (creator_account, amount) in royalties {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.auction_payment_account.to_account_info(),
+                to: creator_account,
+                authority: auction.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            transfer(cpi_ctx, amount)?;
+        }
+
+        //
+        // The winner may not hold a token account for this mint yet, so the settler pays for it.
+        //
+        if ctx.accounts.refund_receiver_token_account.data_is_empty() {
+            let cpi_program = ctx.accounts.associated_token_program.to_account_info();
+            let cpi_accounts = Create {
+                payer: ctx.accounts.settler.to_account_info(),
+                associated_token: ctx.accounts.refund_receiver_token_account.to_account_info(),
+                authority: ctx.accounts.refund_receiver.to_account_info(),
+                mint: ctx.accounts.mint_key.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+            };
+            create(CpiContext::new(cpi_program, cpi_accounts))?;
+        }
+
+        (ctx.accounts.refund_receiver_token_account.to_account_info(), auction.price, royalty)
+    } else {
+        (ctx.accounts.creator_token_account.to_account_info(), 0, 0)
+    };
+
+    //
+    // Pay the listing fee a scheduled auction held back over to the treasury.
+    //
+    if auction.listing_fee > 0 {
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.auction_payment_account.to_account_info(),
+            to: ctx.accounts.treasury_payment_account.to_account_info(),
+            authority: auction.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer(cpi_ctx, auction.listing_fee)?;
+    }
+
+    //
+    // Pay the settler's crank fee out of the escrow.
+    //
+    if auction.crank_fee > 0 {
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.auction_payment_account.to_account_info(),
+            to: ctx.accounts.settler_payment_account.to_account_info(),
+            authority: auction.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer(cpi_ctx, auction.crank_fee)?;
+    }
+
+    //
+    // Whatever is left in the escrow is the creator's.
+    //
+    ctx.accounts.auction_payment_account.reload()?;
+    let price = ctx.accounts.auction_payment_account.amount;
+    msg!("price {}", price);
+    if price > 0 {
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.auction_payment_account.to_account_info(),
+            to: ctx.accounts.creator_payment_account.to_account_info(),
+            authority: auction.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer(cpi_ctx, price)?;
+    }
+
+    //
+    // Transfer auction token account's token into winner's, or back into creator's token account.
+    //
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.auction_token_account.to_account_info(),
+        to: nft_receiver,
+        authority: auction.to_account_info(),
+    };
+    let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    let result = transfer(cpi_context, 1);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenTransferFailed3));
+    }
+
+    //
+    // Close auction token accounts.
+    //
+    for account in [
+        ctx.accounts.auction_token_account.to_account_info(),
+        ctx.accounts.auction_payment_account.to_account_info(),
+    ] {
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_accounts = CloseAccount {
+            account,
+            destination: ctx.accounts.creator.to_account_info(),
+            authority: auction.to_account_info(),
+        };
+        let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        let result = close_account(cpi_context);
+        if let Err(_) = result {
+            return Err(error!(MarketError::TokenCloseFailed));
+        }
+    }
+
+    emit!(AuctionResolved {
+        auction: auction.key(),
+        mint: ctx.accounts.mint_key.key(),
+        creator: creator_key,
+        winner: if sold { Some(auction.refund_receiver) } else { None },
+        price: sale_price,
+        payment_mint: auction.payment_mint,
+        royalty,
+        fee: auction.listing_fee,
+        crank_fee: auction.crank_fee,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+pub fn cancel_token_auction(ctx: Context<CancelTokenAuction>) -> Result<()> {
+    let auction = &ctx.accounts.auction;
+
+    //
+    // Check if the auction is ended.
+    //
+    if Clock::get()?.unix_timestamp > auction.end_time {
+        return Err(error!(MarketError::AuctionEnded));
+    }
+
+    let creator_key = auction.creator;
+    let listing_id = auction.listing_id.to_le_bytes();
+    let seeds = &[
+        b"auction",
+        ctx.accounts.mint_key.to_account_info().key.as_ref(),
+        creator_key.as_ref(),
+        &listing_id,
+        &[auction.bump]
+    ];
+    let signer = &[&seeds[..]];
+
+    //
+    // Cancelling is free before the auction starts: nobody can have bid yet, and the
+    // listing fee held in the escrow is swept back below. Once it has started the
+    // leading bid is refunded and the listing fee goes to the treasury.
+    //
+    if Clock::get()?.unix_timestamp >= auction.start_time {
+        if auction.refund_receiver != auction.creator {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.auction_payment_account.to_account_info(),
+                to: ctx.accounts.refund_receiver_payment_account.to_account_info(),
+                authority: auction.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            transfer(cpi_ctx, auction.price)?;
+        }
+
+        if auction.listing_fee > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.auction_payment_account.to_account_info(),
+                to: ctx.accounts.treasury_payment_account.to_account_info(),
+                authority: auction.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            transfer(cpi_ctx, auction.listing_fee)?;
+        }
+    }
+
+    //
+    // Anything else in the escrow goes back to the creator so it can be closed.
+    //
+    ctx.accounts.auction_payment_account.reload()?;
+    let leftover = ctx.accounts.auction_payment_account.amount;
+    if leftover > 0 {
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.auction_payment_account.to_account_info(),
+            to: ctx.accounts.creator_payment_account.to_account_info(),
+            authority: auction.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        transfer(cpi_ctx, leftover)?;
+    }
+
+    //
+    // Transfer nft from auction token account back into creator's token account.
+    //
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.auction_token_account.to_account_info(),
+        to: ctx.accounts.creator_token_account.to_account_info(),
+        authority: auction.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    let result = transfer(cpi_ctx, 1);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenTransferFailed2));
+    }
+
+    //
+    // Close auction token accounts.
+    //
+    for account in [
+        ctx.accounts.auction_token_account.to_account_info(),
+        ctx.accounts.auction_payment_account.to_account_info(),
+    ] {
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_accounts = CloseAccount {
+            account,
+            destination: ctx.accounts.creator.to_account_info(),
+            authority: auction.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        let result = close_account(cpi_ctx);
+        if let Err(_) = result {
+            return Err(error!(MarketError::TokenCloseFailed));
+        }
+    }
+
+    emit!(AuctionCancelled {
+        auction: auction.key(),
+        mint: ctx.accounts.mint_key.key(),
+        creator: creator_key,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+pub fn create_dutch_auction(ctx: Context<CreateDutchAuction>, memo: String, start_price: u64, floor_price: u64, start_time: i64, end_time: i64, listing_id: u64) -> Result<()> {
+    let dutch_auction = &mut ctx.accounts.dutch_auction;
+
+    if Clock::get()?.unix_timestamp > end_time {
+        return Err(error!(MarketError::InvalidEndTIme));
+    }
+
+    if start_time >= end_time {
+        return Err(error!(MarketError::InvalidStartTime));
+    }
+
+    if floor_price > start_price {
+        return Err(error!(MarketError::InvalidFloorPrice));
+    }
+
+    //
+    // Pay listing fee on the floor price, the least the auction can sell for.
+    //
+    let fee = ctx.accounts.marketplace.fee(floor_price)?;
+    anchor_lang::solana_program::program::invoke(
+        &anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.creator.to_account_info().key(),
+            &ctx.accounts.treasury_account.to_account_info().key(),
+            fee
+        ),
+        &[
+            ctx.accounts.creator.to_account_info(),
+            ctx.accounts.treasury_account.to_account_info(),
+            ctx.accounts.system_program.to_account_info()
+        ]
+    )?;
+
+    dutch_auction.creator = ctx.accounts.creator.key();
+    dutch_auction.mint_key = ctx.accounts.mint_key.key();
+    dutch_auction.listing_id = listing_id;
+    dutch_auction.memo = memo;
+    dutch_auction.start_price = start_price;
+    dutch_auction.floor_price = floor_price;
+    dutch_auction.start_time = start_time;
+    dutch_auction.end_time = end_time;
+    dutch_auction.bump = *ctx.bumps.get("dutch_auction").unwrap();
+
+    //
+    // transfer nft from creator's token account into dutch auction's token account.
+    //
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.creator_token_account.to_account_info(),
+        to: ctx.accounts.auction_token_account.to_account_info(),
+        authority: ctx.accounts.creator.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    let result = transfer(cpi_ctx, 1);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenTransferFailed));
+    }
+
+    emit!(AuctionCreated {
+        auction: dutch_auction.key(),
+        mint: dutch_auction.mint_key,
+        creator: dutch_auction.creator,
+        price: start_price,
+        payment_mint: None,
+        fee,
+        start_time,
+        end_time,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+// Buys the nft at the current price, as long as it is not above `max_price`.
+pub fn buy_dutch<'info>(ctx: Context<'_, '_, '_, 'info, BuyDutch<'info>>, max_price: u64) -> Result<()> {
+    let dutch_auction = &ctx.accounts.dutch_auction;
+    let buyer = &ctx.accounts.buyer;
+    let now = Clock::get()?.unix_timestamp;
+
+    if now < dutch_auction.start_time {
+        return Err(error!(MarketError::AuctionNotStarted));
+    }
+
+    let price = dutch_auction.current_price(now)?;
+    msg!("current price {}", price);
+    if max_price < price {
+        return Err(error!(MarketError::InsufficientMoney));
+    }
+
+    let metadata: Metadata = Metadata::from_account_info(&ctx.accounts.metadata)?;
+    if metadata.mint != ctx.accounts.mint_key.key() {
+        return Err(error!(MarketError::InvalidMetadata));
+    }
+    let royalties = creator_royalties(&metadata, price, None, ctx.remaining_accounts)?;
+    let royalty: u64 = royalties.iter().map(|(_, amount)| amount).sum();
+    msg!("royalty {}", royalty);
+
+    //
+    // Transfer royalty to each creator
+    //
+    for (creator_account, amount) in royalties {
+        anchor_lang::solana_program::program::invoke(
+            &anchor_lang::solana_program::system_instruction::transfer(
+                &buyer.key(),
+                &creator_account.key(),
+                amount
+            ),
+            &[
+                buyer.to_account_info(),
+                creator_account,
+                ctx.accounts.system_program.to_account_info()
+            ]
+        )?;
+    }
+
+    //
+    // Transfer the rest of the price from buyer into creator.
+    //
+    anchor_lang::solana_program::program::invoke(
+        &anchor_lang::solana_program::system_instruction::transfer(
+            &buyer.key(),
+            &ctx.accounts.creator.key(),
+            price.checked_sub(royalty).ok_or_else(|| error!(MarketError::MathOverflow))?
+        ),
+        &[
+            buyer.to_account_info(),
+            ctx.accounts.creator.to_account_info(),
+            ctx.accounts.system_program.to_account_info()
+        ]
+    )?;
+
+    let creator_key = dutch_auction.creator;
+    let listing_id = dutch_auction.listing_id.to_le_bytes();
+    let seeds = &[
+        b"dutch_auction",
+        ctx.accounts.mint_key.key.as_ref(),
+        creator_key.as_ref(),
+        &listing_id,
+        &[dutch_auction.bump]
+    ];
+    let signer = &[&seeds[..]];
+
+    //
+    // Transfer dutch auction token account's token into buyer token account.
+    //
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.auction_token_account.to_account_info(),
+        to: ctx.accounts.buyer_token_account.to_account_info(),
+        authority: dutch_auction.to_account_info()
+    };
+    let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    let result = transfer(cpi_context, 1);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenTransferFailed3));
+    }
+
+    //
+    // Close dutch auction token account.
+    //
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_accounts = CloseAccount {
+        account: ctx.accounts.auction_token_account.to_account_info(),
+        destination: ctx.accounts.creator.to_account_info(),
+        authority: dutch_auction.to_account_info(),
+    };
+    let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    let result = close_account(cpi_context);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenCloseFailed));
+    }
+
+    emit!(AuctionResolved {
+        auction: dutch_auction.key(),
+        mint: ctx.accounts.mint_key.key(),
+        creator: creator_key,
+        winner: Some(buyer.key()),
+        price,
+        payment_mint: None,
+        royalty,
+        fee: 0,
+        crank_fee: 0,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+pub fn cancel_dutch_auction(ctx: Context<CancelDutchAuction>) -> Result<()> {
+    let dutch_auction = &ctx.accounts.dutch_auction;
+
+    let creator_key = dutch_auction.creator;
+    let listing_id = dutch_auction.listing_id.to_le_bytes();
+    let seeds = &[
+        b"dutch_auction",
+        ctx.accounts.mint_key.to_account_info().key.as_ref(),
+        creator_key.as_ref(),
+        &listing_id,
+        &[dutch_auction.bump]
+    ];
+    let signer = &[&seeds[..]];
+
+    //
+    // Transfer nft from dutch auction token account back into creator's token account.
+    //
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.auction_token_account.to_account_info(),
+        to: ctx.accounts.creator_token_account.to_account_info(),
+        authority: dutch_auction.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    let result = transfer(cpi_ctx, 1);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenTransferFailed2));
+    }
+
+    //
+    // Close dutch auction token account.
+    //
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_accounts = CloseAccount {
+        account: ctx.accounts.auction_token_account.to_account_info(),
+        destination: ctx.accounts.creator.to_account_info(),
+        authority: dutch_auction.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    let result = close_account(cpi_ctx);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenCloseFailed));
+    }
+
+    emit!(AuctionCancelled {
+        auction: dutch_auction.key(),
+        mint: ctx.accounts.mint_key.key(),
+        creator: creator_key,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+pub fn initialize_marketplace(ctx: Context<InitializeMarketplace>, treasury: Pubkey, fee_basis_points: u16, max_royalty_basis_points: u16) -> Result<()> {
+    if fee_basis_points > MAX_BASIS_POINTS {
+        return Err(error!(MarketError::InvalidFee));
+    }
+    if max_royalty_basis_points > MAX_BASIS_POINTS {
+        return Err(error!(MintError::RoyaltyExceeded));
+    }
+
+    let marketplace = &mut ctx.accounts.marketplace;
+    marketplace.admin = ctx.accounts.admin.key();
+    marketplace.treasury = treasury;
+    marketplace.fee_basis_points = fee_basis_points;
+    marketplace.max_royalty_basis_points = max_royalty_basis_points;
+    marketplace.crank_fee_basis_points = 0;
+    marketplace.bump = *ctx.bumps.get("marketplace").unwrap();
+
+    Ok(())
+}
+
+
+pub fn set_treasury(ctx: Context<UpdateMarketplace>, treasury: Pubkey) -> Result<()> {
+    ctx.accounts.marketplace.treasury = treasury;
+    Ok(())
+}
+
+
+pub fn set_fee(ctx: Context<UpdateMarketplace>, fee_basis_points: u16) -> Result<()> {
+    if fee_basis_points > MAX_BASIS_POINTS {
+        return Err(error!(MarketError::InvalidFee));
+    }
+
+    ctx.accounts.marketplace.fee_basis_points = fee_basis_points;
+    Ok(())
+}
+
+
+// Share of the listing fee, in basis points of the fee, paid to auction settlers.
+pub fn set_crank_fee(ctx: Context<UpdateMarketplace>, crank_fee_basis_points: u16) -> Result<()> {
+    if crank_fee_basis_points > MAX_BASIS_POINTS {
+        return Err(error!(MarketError::InvalidFee));
+    }
+
+    ctx.accounts.marketplace.crank_fee_basis_points = crank_fee_basis_points;
+    Ok(())
+}
+
+
+pub fn set_max_royalty(ctx: Context<UpdateMarketplace>, max_royalty_basis_points: u16) -> Result<()> {
+    if max_royalty_basis_points > MAX_BASIS_POINTS {
+        return Err(error!(MintError::RoyaltyExceeded));
+    }
+
+    ctx.accounts.marketplace.max_royalty_basis_points = max_royalty_basis_points;
+    Ok(())
+}
+
+
+// NFTs minted before royalties were basis points stored a percent (at most 10)
+// in `seller_fee_basis_points` and have no `RoyaltyVersion` yet. Rewrites it as basis
+// points and records that it did, signed by the update authority.
+pub fn migrate_royalty(ctx: Context<MigrateRoyalty>) -> Result<()> {
+    let metadata: Metadata = Metadata::from_account_info(&ctx.accounts.metadata)?;
+    if metadata.mint != ctx.accounts.mint_key.key() {
+        return Err(error!(MarketError::InvalidMetadata));
+    }
+    if metadata.update_authority != ctx.accounts.update_authority.key() {
+        return Err(error!(MarketError::Unauthorized));
+    }
+
+    if ctx.accounts.royalty_version.version == ROYALTY_VERSION_BASIS_POINTS {
+        return Err(error!(MintError::RoyaltyAlreadyMigrated));
+    }
+
+    let royalty = metadata.data.seller_fee_basis_points;
+    if royalty > LEGACY_MAX_ROYALTY_PERCENT {
+        return Err(error!(MintError::RoyaltyExceeded));
+    }
+
+    let data = mpl_token_metadata::state::DataV2 {
+        name: metadata.data.name.trim_matches(char::from(0)).to_string(),
+        symbol: metadata.data.symbol.trim_matches(char::from(0)).to_string(),
+        uri: metadata.data.uri.trim_matches(char::from(0)).to_string(),
+        seller_fee_basis_points: royalty * 100,
+        creators: metadata.data.creators,
+        collection: metadata.collection,
+        uses: metadata.uses,
+    };
+    let result = invoke(
+        &update_metadata_accounts_v2(
+            ctx.accounts.token_metadata_program.key(),
+            ctx.accounts.metadata.key(),
+            ctx.accounts.update_authority.key(),
+            None,
+            Some(data),
+            None,
+            None,
+        ),
+        &[
+            ctx.accounts.metadata.to_account_info(),
+            ctx.accounts.update_authority.to_account_info(),
+            ctx.accounts.token_metadata_program.to_account_info(),
+        ]
+    );
+    if let Err(_) = result {
+        return Err(error!(MintError::MetadataUpdateFailed));
+    }
+    msg!("royalty migrated to {} basis points", royalty * 100);
+
+    let royalty_version = &mut ctx.accounts.royalty_version;
+    royalty_version.version = ROYALTY_VERSION_BASIS_POINTS;
+    royalty_version.bump = *ctx.bumps.get("royalty_version").unwrap();
+
+    Ok(())
+}
+
+
+pub fn make_offer(ctx: Context<MakeOffer>, price: u64) -> Result<()> {
+    let offer = &mut ctx.accounts.offer;
+
+    if price == 0 {
+        return Err(error!(MarketError::InvalidPrice));
+    }
+
+    offer.buyer = ctx.accounts.buyer.key();
+    offer.mint_key = ctx.accounts.mint_key.key();
+    offer.price = price;
+    offer.bump = *ctx.bumps.get("offer").unwrap();
+
+    //
+    // Escrow offer's money from buyer into offer account.
+    //
+    anchor_lang::solana_program::program::invoke(
+        &anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.buyer.key(),
+            &offer.key(),
+            price
+        ),
+        &[
+            ctx.accounts.buyer.to_account_info(),
+            offer.to_account_info(),
+            ctx.accounts.system_program.to_account_info()
+        ]
+    )?;
+
+    emit!(OfferMade {
+        offer: offer.key(),
+        mint: offer.mint_key,
+        buyer: offer.buyer,
+        price,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+    //
+    // Escrowed money and rent are returned to the buyer when the offer account is closed.
+    //
+    emit!(OfferCancelled {
+        offer: ctx.accounts.offer.key(),
+        mint: ctx.accounts.mint_key.key(),
+        buyer: ctx.accounts.buyer.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+pub fn accept_offer<'info>(ctx: Context<'_, '_, '_, 'info, AcceptOffer<'info>>) -> Result<()> {
+    let offer = &ctx.accounts.offer;
+
+    let metadata: Metadata = Metadata::from_account_info(&ctx.accounts.metadata)?;
+    if metadata.mint != ctx.accounts.mint_key.key() {
+        return Err(error!(MarketError::InvalidMetadata));
+    }
+    let royalties = creator_royalties(&metadata, offer.price, None, ctx.remaining_accounts)?;
+    let royalty: u64 = royalties.iter().map(|(_, amount)| amount).sum();
+    msg!("royalty {}", royalty);
+
+    let fee = ctx.accounts.marketplace.fee(offer.price)?;
+    let price = offer.price.checked_sub(royalty).and_then(|price| price.checked_sub(fee)).ok_or_else(|| error!(MarketError::MathOverflow))?;
+    msg!("price {}", price);
+
+    //
+    // Pay royalty, marketplace fee and seller out of the offer's escrow.
+    //
+    for (creator_account, amount) in royalties {
+        move_lamports(&offer.to_account_info(), &creator_account, amount)?;
+    }
+
+    move_lamports(&offer.to_account_info(), &ctx.accounts.treasury_account, fee)?;
+    move_lamports(&offer.to_account_info(), &ctx.accounts.seller.to_account_info(), price)?;
+
+    //
+    // Transfer nft from seller's token account into buyer token account.
+    //
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.seller_token_account.to_account_info(),
+        to: ctx.accounts.buyer_token_account.to_account_info(),
+        authority: ctx.accounts.seller.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    let result = transfer(cpi_ctx, 1);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenTransferFailed3));
+    }
+
+    emit!(OrderFilled {
+        order: offer.key(),
+        mint: ctx.accounts.mint_key.key(),
+        seller: ctx.accounts.seller.key(),
+        buyer: offer.buyer,
+        price: offer.price,
+        payment_mint: None,
+        royalty,
+        fee,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+pub fn make_collection_offer(ctx: Context<MakeCollectionOffer>, price: u64, quantity: u64) -> Result<()> {
+    let collection_offer = &mut ctx.accounts.collection_offer;
+
+    if quantity == 0 {
+        return Err(error!(MarketError::InvalidQuantity));
+    }
+
+    collection_offer.buyer = ctx.accounts.buyer.key();
+    collection_offer.collection = ctx.accounts.collection_mint.key();
+    collection_offer.price = price;
+    collection_offer.quantity = quantity;
+    collection_offer.bump = *ctx.bumps.get("collection_offer").unwrap();
+
+    //
+    // Escrow money for every item from buyer into collection offer account.
+    //
+    anchor_lang::solana_program::program::invoke(
+        &anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.buyer.key(),
+            &collection_offer.key(),
+            price.checked_mul(quantity).ok_or_else(|| error!(MarketError::MathOverflow))?
+        ),
+        &[
+            ctx.accounts.buyer.to_account_info(),
+            collection_offer.to_account_info(),
+            ctx.accounts.system_program.to_account_info()
+        ]
+    )?;
+
+    Ok(())
+}
+
+
+pub fn cancel_collection_offer(_ctx: Context<CancelCollectionOffer>) -> Result<()> {
+    //
+    // Escrowed money for the remaining items and rent are returned to the buyer when the account is closed.
+    //
+    Ok(())
+}
+
+
+pub fn accept_collection_offer<'info>(ctx: Context<'_, '_, '_, 'info, AcceptCollectionOffer<'info>>) -> Result<()> {
+    let collection_offer = &ctx.accounts.collection_offer;
+
+    let metadata: Metadata = Metadata::from_account_info(&ctx.accounts.metadata)?;
+    if metadata.mint != ctx.accounts.mint_key.key() {
+        return Err(error!(MarketError::InvalidMetadata));
+    }
+    match &metadata.collection {
+        Some(collection) if collection.verified && collection.key == collection_offer.collection => {}
+        _ => return Err(error!(MarketError::InvalidCollection)),
+    }
+
+    let royalties = creator_royalties(&metadata, collection_offer.price, None, ctx.remaining_accounts)?;
+    let royalty: u64 = royalties.iter().map(|(_, amount)| amount).sum();
+    msg!("royalty {}", royalty);
+
+    let fee = ctx.accounts.marketplace.fee(collection_offer.price)?;
+    let price = collection_offer.price.checked_sub(royalty).and_then(|price| price.checked_sub(fee)).ok_or_else(|| error!(MarketError::MathOverflow))?;
+    msg!("price {}", price);
+
+    //
+    // Pay royalty, marketplace fee and seller out of the collection offer's escrow.
+    //
+    for (creator_account, amount) in royalties {
+        move_lamports(&collection_offer.to_account_info(), &creator_account, amount)?;
+    }
+
+    move_lamports(&collection_offer.to_account_info(), &ctx.accounts.treasury_account, fee)?;
+    move_lamports(&collection_offer.to_account_info(), &ctx.accounts.seller.to_account_info(), price)?;
+
+    //
+    // Transfer nft from seller's token account into buyer token account.
+    //
+    let cpi_accounts = Transfer {
+        from: ctx.accounts.seller_token_account.to_account_info(),
+        to: ctx.accounts.buyer_token_account.to_account_info(),
+        authority: ctx.accounts.seller.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    let result = transfer(cpi_ctx, 1);
+    if let Err(_) = result {
+        return Err(error!(MarketError::TokenTransferFailed3));
+    }
+
+    //
+    // Once every item has been bought the offer only holds its rent, which
+    // `close_collection_offer` returns to the buyer.
+    //
+    let collection_offer = &mut ctx.accounts.collection_offer;
+    collection_offer.quantity = collection_offer.quantity.checked_sub(1).ok_or_else(|| error!(MarketError::MathOverflow))?;
+
+    emit!(OrderFilled {
+        order: collection_offer.key(),
+        mint: ctx.accounts.mint_key.key(),
+        seller: ctx.accounts.seller.key(),
+        buyer: collection_offer.buyer,
+        price: collection_offer.price,
+        payment_mint: None,
+        royalty,
+        fee,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+// Anyone may close a collection offer that has been filled; the rent goes back to the buyer.
+pub fn close_collection_offer(_ctx: Context<CloseCollectionOffer>) -> Result<()> {
+    Ok(())
+}
+
+
+}
+
+
+
+
+#[derive(Accounts)]
+pub struct MintNFT<'info> {
+    #[account(mut)]
+    pub mint_authority: Signer<'info>,
+
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub mint: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub token_account: UncheckedAccount<'info>,
+
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub payer: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub rent: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        init,
+        payer = mint_authority,
+        space = RoyaltyVersion::space(),
+        seeds = [b"royalty_version", mint.key().as_ref()],
+        bump
+    )]
+    pub royalty_version: Account<'info, RoyaltyVersion>,
+}
+
+
+#[derive(Accounts)]
+#[instruction(memo: String, price: u64, listing_id: u64, expires_at: Option<i64>)]
+pub struct CreateOrder<'info> {
+    #[account(
+        init,
+        payer = creator,
+        space = Order::space(&memo),
+        seeds = [
+            b"order",
+            mint_key.key().as_ref(),
+            creator.key().as_ref(),
+            listing_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub order: Account<'info, Order>,
+
+    #[account(
+        init,
+        payer = creator,
+        associated_token::mint = mint_key,
+        associated_token::authority = order
+    )]
+    pub order_token_account: Account<'info, TokenAccount>,
+
+    #[account(constraint=mint_key.supply == 1 && mint_key.decimals == 0 @ MarketError::InvalidNft)]
+    pub mint_key: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(
+        mut, 
+        constraint=creator_token_account.owner == creator.key(),
+        constraint=creator_token_account.mint == mint_key.key(),
+        constraint=creator_token_account.amount == 1 @ MarketError::TokenNotHeld
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: This account's address is only used.
+    #[account(
+        mut,
+        constraint=treasury_account.key() == marketplace.treasury @ MarketError::InvalidTreasury
+    )]
+    pub treasury_account: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"order",
+            mint_key.key().as_ref(),
+            creator.key().as_ref(),
+            order.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        close = creator
+    )]
+    pub order: Account<'info, Order>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_key,
+        associated_token::authority = order,
+    )]
+    pub order_token_account: Account<'info, TokenAccount>,
+
+    pub mint_key: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint=creator_token_account.owner == creator.key(),
+        constraint=creator_token_account.mint == mint_key.key()
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+
+#[derive(Accounts)]
+pub struct CloseExpiredOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"order",
+            mint_key.key().as_ref(),
+            creator.key().as_ref(),
+            order.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        close = creator
+    )]
+    pub order: Account<'info, Order>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_key,
+        associated_token::authority = order,
+    )]
+    pub order_token_account: Account<'info, TokenAccount>,
+
+    pub mint_key: Account<'info, Mint>,
+
+    /// CHECK: This account's address is only used.
+    #[account(mut)]
+    pub creator: AccountInfo<'info>,
+
+    // The creator may have closed their token account since listing, so whoever closes the order pays for a new one.
+    #[account(
+        init_if_needed,
+        payer = closer,
+        associated_token::mint = mint_key,
+        associated_token::authority = creator,
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub closer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+
+#[derive(Accounts)]
+#[instruction(memo: String)]
+pub struct UpdateOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"order",
+            mint_key.key().as_ref(),
+            creator.key().as_ref(),
+            order.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        constraint = order.payment_mint.is_none() @ MarketError::InvalidPaymentMint,
+        realloc = Order::space(&memo),
+        realloc::payer = creator,
+        realloc::zero = false
+    )]
+    pub order: Account<'info, Order>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: This account's address is only used.
+    #[account(
+        mut,
+        constraint=treasury_account.key() == marketplace.treasury @ MarketError::InvalidTreasury
+    )]
+    pub treasury_account: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+
+#[derive(Accounts)]
+pub struct FillOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"order",
+            mint_key.key.as_ref(),
+            creator.key().as_ref(),
+            order.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        constraint = order.payment_mint.is_none() @ MarketError::InvalidPaymentMint,
+        close = creator
+    )]
+    pub order: Account<'info, Order>,
+
+    #[account(
+        mut,
+        associated_token::mint = order.mint_key,
+        associated_token::authority = order,
+    )]
+    pub order_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    /// CHECK: This account's address is only used.
+    #[account(mut)]
+    pub creator: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint=buyer_token_account.owner == buyer.key(),
+        constraint=buyer_token_account.mint == mint_key.key(),
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Deserialized as Metaplex metadata after its PDA derivation is checked.
+    #[account(
+        address = mpl_token_metadata::pda::find_metadata_account(mint_key.key).0 @ MarketError::InvalidMetadata
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+
+
+#[derive(Accounts)]
+pub struct AuctionResolve<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"auction",
+            mint_key.key.as_ref(),
+            creator.key().as_ref(),
+            auction.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        constraint = auction.payment_mint.is_none() @ MarketError::InvalidPaymentMint,
+        close = creator
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"bid_vault",
+            auction.key().as_ref(),
+        ],
+        bump = bid_vault.bump,
+        has_one = auction,
+        close = creator
+    )]
+    pub bid_vault: Account<'info, BidVault>,
+    #[account(
+        mut, 
+        associated_token::mint = auction.mint_key,
+        associated_token::authority = auction,
+    )]
+    pub auction_token_account: Account<'info, TokenAccount>,
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    /// CHECK: This account's address is only used.
+    #[account(mut)]
+    pub creator: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint=creator_token_account.owner == creator.key(),
+        constraint=creator_token_account.mint == mint_key.key()
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Checked against the auction's current highest bidder.
+    #[account(
+        mut,
+        address = auction.refund_receiver @ MarketError::InvalidReceiver
+    )]
+    pub refund_receiver: AccountInfo<'info>,
+
+    /// CHECK: The winner's associated token account, only created by the settler if the auction sells.
+    #[account(
+        mut,
+        address = get_associated_token_address(refund_receiver.key, mint_key.key) @ MarketError::InvalidReceiver
+    )]
+    pub refund_receiver_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Deserialized as Metaplex metadata after its PDA derivation is checked.
+    #[account(
+        address = mpl_token_metadata::pda::find_metadata_account(mint_key.key).0 @ MarketError::InvalidMetadata
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: This account's address is only used.
+    #[account(
+        mut,
+        constraint=treasury_account.key() == marketplace.treasury @ MarketError::InvalidTreasury
+    )]
+    pub treasury_account: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub settler: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+
+#[derive(Accounts)]
+pub struct Bid<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"auction",
+            mint_key.key.as_ref(),
+            creator.key().as_ref(),
+            auction.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        constraint = auction.payment_mint.is_none() @ MarketError::InvalidPaymentMint,
+        constraint = !auction.is_sealed() @ MarketError::SealedAuction
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"bid_vault",
+            auction.key().as_ref(),
+        ],
+        bump = bid_vault.bump,
+        has_one = auction
+    )]
+    pub bid_vault: Account<'info, BidVault>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    /// CHECK: This account's address is only used.
+    #[account(mut)]
+    pub creator: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    /// CHECK: Checked against the auction's current highest bidder.
+    #[account(
+        mut,
+        address = auction.refund_receiver @ MarketError::InvalidReceiver
+    )]
+    pub refund_receiver: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+
+#[derive(Accounts)]
+pub struct DeferredBid<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"auction",
+            mint_key.key.as_ref(),
+            creator.key().as_ref(),
+            auction.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        constraint = auction.payment_mint.is_none() @ MarketError::InvalidPaymentMint,
+        constraint = !auction.is_sealed() @ MarketError::SealedAuction
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"bid_vault",
+            auction.key().as_ref(),
+        ],
+        bump = bid_vault.bump,
+        has_one = auction
+    )]
+    pub bid_vault: Account<'info, BidVault>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    /// CHECK: This account's address is only used.
+    pub creator: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        space = BidRefund::space(),
+        seeds = [
+            b"bid_refund",
+            auction.key().as_ref(),
+            bidder.key().as_ref(),
+        ],
+        bump
+    )]
+    pub bidder_refund: Account<'info, BidRefund>,
+
+    /// CHECK: The previous bidder's refund account, checked in the handler. Ignored when
+    /// the auction has no bid yet or the bidder is raising their own bid.
+    #[account(mut)]
+    pub previous_bid_refund: UncheckedAccount<'info>,
+
+    /// CHECK: The previous bidder, refunded directly when they have no refund account.
+    #[account(mut, address = auction.refund_receiver @ MarketError::InvalidReceiver)]
+    pub previous_bidder: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+
+#[derive(Accounts)]
+pub struct RevealReserve<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"auction",
+            mint_key.key.as_ref(),
+            creator.key().as_ref(),
+            auction.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator
+    )]
+    pub auction: Account<'info, Auction>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    /// CHECK: This account's address is only used.
+    pub creator: AccountInfo<'info>,
+}
+
+
+#[derive(Accounts)]
+pub struct ClaimRefund<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"bid_refund",
+            bid_refund.auction.as_ref(),
+            bidder.key().as_ref(),
+        ],
+        bump = bid_refund.bump,
+        has_one = bidder,
+        close = bidder
+    )]
+    pub bid_refund: Account<'info, BidRefund>,
+
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+}
+
+
+#[derive(Accounts)]
+pub struct CancelAuction<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"auction",
+            mint_key.key().as_ref(),
+            creator.key().as_ref(),
+            auction.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        constraint = auction.payment_mint.is_none() @ MarketError::InvalidPaymentMint,
+        close = creator
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"bid_vault",
+            auction.key().as_ref(),
+        ],
+        bump = bid_vault.bump,
+        has_one = auction,
+        close = creator
+    )]
+    pub bid_vault: Account<'info, BidVault>,
+
+    #[account(
+        mut, 
+        associated_token::mint = mint_key,
+        associated_token::authority = auction,
+    )]
+    pub auction_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint=creator_token_account.owner == creator.key(),
+        constraint=creator_token_account.mint == mint_key.key()
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Checked against the auction's current highest bidder.
+    #[account(
+        mut,
+        address = auction.refund_receiver @ MarketError::InvalidReceiver
+    )]
+    pub refund_receiver: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: This account's address is only used.
+    #[account(
+        mut,
+        constraint=treasury_account.key() == marketplace.treasury @ MarketError::InvalidTreasury
+    )]
+    pub treasury_account: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+
+}
+
+
+#[derive(Accounts)]
+#[instruction(memo: String, price: u64, start_time: i64, end_time: i64, listing_id: u64)]
+pub struct CreateAuction<'info> {
+    #[account(
+        init,
+        payer = creator,
+        space = Auction::space(&memo),
+        seeds = [
+            b"auction",
+            mint_key.key().as_ref(),
+            creator.key().as_ref(),
+            listing_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        init,
+        payer = creator,
+        space = BidVault::space(),
+        seeds = [
+            b"bid_vault",
+            auction.key().as_ref(),
+        ],
+        bump
+    )]
+    pub bid_vault: Account<'info, BidVault>,
+
+    #[account(
+        init,
+        payer = creator,
+        associated_token::mint = mint_key,
+        associated_token::authority = auction
+    )]
+    pub auction_token_account: Account<'info, TokenAccount>,
+
+    #[account(constraint=mint_key.supply == 1 && mint_key.decimals == 0 @ MarketError::InvalidNft)]
+    pub mint_key: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(
+        mut, 
+        constraint=creator_token_account.owner == creator.key(),
+        constraint=creator_token_account.mint == mint_key.key(),
+        constraint=creator_token_account.amount == 1 @ MarketError::TokenNotHeld
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: This account's address is only used.
+    #[account(
+        mut,
+        constraint=treasury_account.key() == marketplace.treasury @ MarketError::InvalidTreasury
+    )]
+    pub treasury_account: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+
+#[derive(Accounts)]
+pub struct BuyNow<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"auction",
+            mint_key.key.as_ref(),
+            creator.key().as_ref(),
+            auction.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        constraint = auction.payment_mint.is_none() @ MarketError::InvalidPaymentMint,
+        constraint = !auction.is_sealed() @ MarketError::SealedAuction,
+        close = creator
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"bid_vault",
+            auction.key().as_ref(),
+        ],
+        bump = bid_vault.bump,
+        has_one = auction,
+        close = creator
+    )]
+    pub bid_vault: Account<'info, BidVault>,
+
+    #[account(
+        mut,
+        associated_token::mint = auction.mint_key,
+        associated_token::authority = auction,
+    )]
+    pub auction_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    /// CHECK: This account's address is only used.
+    #[account(mut)]
+    pub creator: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint=buyer_token_account.owner == buyer.key(),
+        constraint=buyer_token_account.mint == mint_key.key(),
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Checked against the auction's current highest bidder.
+    #[account(
+        mut,
+        address = auction.refund_receiver @ MarketError::InvalidReceiver
+    )]
+    pub refund_receiver: AccountInfo<'info>,
+
+    /// CHECK: Deserialized as Metaplex metadata after its PDA derivation is checked.
+    #[account(
+        address = mpl_token_metadata::pda::find_metadata_account(mint_key.key).0 @ MarketError::InvalidMetadata
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: This account's address is only used.
+    #[account(
+        mut,
+        constraint=treasury_account.key() == marketplace.treasury @ MarketError::InvalidTreasury
+    )]
+    pub treasury_account: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+
+#[derive(Accounts)]
+pub struct CommitBid<'info> {
+    #[account(
+        seeds = [
+            b"auction",
+            mint_key.key.as_ref(),
+            creator.key().as_ref(),
+            auction.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        constraint = auction.is_sealed() @ MarketError::NotSealedAuction
+    )]
+    pub auction: Account<'info, Auction>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    /// CHECK: This account's address is only used.
+    pub creator: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    #[account(
+        init,
+        payer = bidder,
+        space = SealedBid::space(),
+        seeds = [
+            b"sealed_bid",
+            auction.key().as_ref(),
+            bidder.key().as_ref(),
+        ],
+        bump
+    )]
+    pub sealed_bid: Account<'info, SealedBid>,
+
+    pub system_program: Program<'info, System>,
+}
+
+
+#[derive(Accounts)]
+pub struct RevealBid<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"auction",
+            mint_key.key.as_ref(),
+            creator.key().as_ref(),
+            auction.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        constraint = auction.is_sealed() @ MarketError::NotSealedAuction
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"bid_vault",
+            auction.key().as_ref(),
+        ],
+        bump = bid_vault.bump,
+        has_one = auction
+    )]
+    pub bid_vault: Account<'info, BidVault>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    /// CHECK: This account's address is only used.
+    pub creator: AccountInfo<'info>,
+
+    pub bidder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"sealed_bid",
+            auction.key().as_ref(),
+            bidder.key().as_ref(),
+        ],
+        bump = sealed_bid.bump,
+        has_one = bidder
+    )]
+    pub sealed_bid: Account<'info, SealedBid>,
+
+    /// CHECK: The current leader's sealed bid account, checked in the handler. Ignored
+    /// when nobody leads yet or the revealed bid does not take the lead.
+    #[account(mut)]
+    pub previous_sealed_bid: UncheckedAccount<'info>,
+}
+
+
+#[derive(Accounts)]
+pub struct WithdrawSealedBid<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sealed_bid",
+            sealed_bid.auction.as_ref(),
+            bidder.key().as_ref(),
+        ],
+        bump = sealed_bid.bump,
+        has_one = bidder,
+        has_one = creator,
+        close = bidder
+    )]
+    pub sealed_bid: Account<'info, SealedBid>,
+
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    /// CHECK: This account's address is only used.
+    #[account(mut)]
+    pub creator: AccountInfo<'info>,
+}
+
+
+#[derive(Accounts)]
+#[instruction(memo: String, price: u64, listing_id: u64)]
+pub struct CreateTokenOrder<'info> {
+    #[account(
+        init,
+        payer = creator,
+        space = Order::space(&memo),
+        seeds = [
+            b"order",
+            mint_key.key().as_ref(),
+            creator.key().as_ref(),
+            listing_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub order: Account<'info, Order>,
+
+    #[account(
+        init,
+        payer = creator,
+        associated_token::mint = mint_key,
+        associated_token::authority = order
+    )]
+    pub order_token_account: Account<'info, TokenAccount>,
+
+    #[account(constraint=mint_key.supply == 1 && mint_key.decimals == 0 @ MarketError::InvalidNft)]
+    pub mint_key: Account<'info, Mint>,
+
+    pub payment_mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint=creator_token_account.owner == creator.key(),
+        constraint=creator_token_account.mint == mint_key.key(),
+        constraint=creator_token_account.amount == 1 @ MarketError::TokenNotHeld
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint=creator_payment_account.owner == creator.key(),
+        constraint=creator_payment_account.mint == payment_mint.key() @ MarketError::InvalidPaymentMint
+    )]
+    pub creator_payment_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        mut,
+        constraint=treasury_payment_account.owner == marketplace.treasury @ MarketError::InvalidTreasury,
+        constraint=treasury_payment_account.mint == payment_mint.key() @ MarketError::InvalidPaymentMint
+    )]
+    pub treasury_payment_account: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+
+#[derive(Accounts)]
+pub struct FillTokenOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"order",
+            mint_key.key.as_ref(),
+            creator.key().as_ref(),
+            order.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        close = creator
+    )]
+    pub order: Account<'info, Order>,
+
+    #[account(
+        mut,
+        associated_token::mint = order.mint_key,
+        associated_token::authority = order,
+    )]
+    pub order_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    /// CHECK: This account's address is only used.
+    #[account(mut)]
+    pub creator: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint=creator_payment_account.owner == creator.key(),
+        constraint=Some(creator_payment_account.mint) == order.payment_mint @ MarketError::InvalidPaymentMint
+    )]
+    pub creator_payment_account: Account<'info, TokenAccount>,
+
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint=buyer_token_account.owner == buyer.key(),
+        constraint=buyer_token_account.mint == mint_key.key(),
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint=buyer_payment_account.owner == buyer.key(),
+        constraint=Some(buyer_payment_account.mint) == order.payment_mint @ MarketError::InvalidPaymentMint
+    )]
+    pub buyer_payment_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Deserialized as Metaplex metadata after its PDA derivation is checked.
+    #[account(
+        address = mpl_token_metadata::pda::find_metadata_account(mint_key.key).0 @ MarketError::InvalidMetadata
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
 
 
 #[derive(Accounts)]
-pub struct MintNFT<'info> {
+#[instruction(memo: String)]
+pub struct UpdateTokenOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"order",
+            mint_key.key().as_ref(),
+            creator.key().as_ref(),
+            order.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        realloc = Order::space(&memo),
+        realloc::payer = creator,
+        realloc::zero = false
+    )]
+    pub order: Account<'info, Order>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint=creator_payment_account.owner == creator.key(),
+        constraint=Some(creator_payment_account.mint) == order.payment_mint @ MarketError::InvalidPaymentMint
+    )]
+    pub creator_payment_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        mut,
+        constraint=treasury_payment_account.owner == marketplace.treasury @ MarketError::InvalidTreasury,
+        constraint=Some(treasury_payment_account.mint) == order.payment_mint @ MarketError::InvalidPaymentMint
+    )]
+    pub treasury_payment_account: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+
+#[derive(Accounts)]
+#[instruction(memo: String, price: u64, start_time: i64, end_time: i64, listing_id: u64)]
+pub struct CreateTokenAuction<'info> {
+    #[account(
+        init,
+        payer = creator,
+        space = Auction::space(&memo),
+        seeds = [
+            b"auction",
+            mint_key.key().as_ref(),
+            creator.key().as_ref(),
+            listing_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        init,
+        payer = creator,
+        associated_token::mint = mint_key,
+        associated_token::authority = auction
+    )]
+    pub auction_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = creator,
+        associated_token::mint = payment_mint,
+        associated_token::authority = auction
+    )]
+    pub auction_payment_account: Account<'info, TokenAccount>,
+
+    #[account(constraint=mint_key.supply == 1 && mint_key.decimals == 0 @ MarketError::InvalidNft)]
+    pub mint_key: Account<'info, Mint>,
+
+    pub payment_mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint=creator_token_account.owner == creator.key(),
+        constraint=creator_token_account.mint == mint_key.key(),
+        constraint=creator_token_account.amount == 1 @ MarketError::TokenNotHeld
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint=creator_payment_account.owner == creator.key(),
+        constraint=creator_payment_account.mint == payment_mint.key() @ MarketError::InvalidPaymentMint
+    )]
+    pub creator_payment_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        mut,
+        constraint=treasury_payment_account.owner == marketplace.treasury @ MarketError::InvalidTreasury,
+        constraint=treasury_payment_account.mint == payment_mint.key() @ MarketError::InvalidPaymentMint
+    )]
+    pub treasury_payment_account: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+
+#[derive(Accounts)]
+pub struct TokenBid<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"auction",
+            mint_key.key.as_ref(),
+            creator.key().as_ref(),
+            auction.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = auction,
+    )]
+    pub auction_payment_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    #[account(constraint=Some(payment_mint.key()) == auction.payment_mint @ MarketError::InvalidPaymentMint)]
+    pub payment_mint: Account<'info, Mint>,
+
+    /// CHECK: This account's address is only used.
+    pub creator: AccountInfo<'info>,
+
+    pub bidder: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint=bidder_payment_account.owner == bidder.key(),
+        constraint=bidder_payment_account.mint == payment_mint.key() @ MarketError::InvalidPaymentMint
+    )]
+    pub bidder_payment_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint=refund_receiver_payment_account.owner == auction.refund_receiver @ MarketError::InvalidReceiver,
+        constraint=refund_receiver_payment_account.mint == payment_mint.key() @ MarketError::InvalidPaymentMint
+    )]
+    pub refund_receiver_payment_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+
+#[derive(Accounts)]
+pub struct ResolveTokenAuction<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"auction",
+            mint_key.key.as_ref(),
+            creator.key().as_ref(),
+            auction.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        close = creator
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        mut,
+        associated_token::mint = auction.mint_key,
+        associated_token::authority = auction,
+    )]
+    pub auction_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = auction,
+    )]
+    pub auction_payment_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    #[account(constraint=Some(payment_mint.key()) == auction.payment_mint @ MarketError::InvalidPaymentMint)]
+    pub payment_mint: Account<'info, Mint>,
+
+    /// CHECK: This account's address is only used.
+    #[account(mut)]
+    pub creator: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint=creator_token_account.owner == creator.key(),
+        constraint=creator_token_account.mint == mint_key.key()
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint=creator_payment_account.owner == creator.key(),
+        constraint=creator_payment_account.mint == payment_mint.key() @ MarketError::InvalidPaymentMint
+    )]
+    pub creator_payment_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Checked against the auction's current highest bidder.
+    #[account(
+        address = auction.refund_receiver @ MarketError::InvalidReceiver
+    )]
+    pub refund_receiver: AccountInfo<'info>,
+
+    /// CHECK: The winner's associated token account, only created by the settler if the auction sells.
+    #[account(
+        mut,
+        address = get_associated_token_address(refund_receiver.key, mint_key.key) @ MarketError::InvalidReceiver
+    )]
+    pub refund_receiver_token_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint=refund_receiver_payment_account.owner == auction.refund_receiver @ MarketError::InvalidReceiver,
+        constraint=refund_receiver_payment_account.mint == payment_mint.key() @ MarketError::InvalidPaymentMint
+    )]
+    pub refund_receiver_payment_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Deserialized as Metaplex metadata after its PDA derivation is checked.
+    #[account(
+        address = mpl_token_metadata::pda::find_metadata_account(mint_key.key).0 @ MarketError::InvalidMetadata
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        mut,
+        constraint=treasury_payment_account.owner == marketplace.treasury @ MarketError::InvalidTreasury,
+        constraint=treasury_payment_account.mint == payment_mint.key() @ MarketError::InvalidPaymentMint
+    )]
+    pub treasury_payment_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub settler: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint=settler_payment_account.owner == settler.key(),
+        constraint=settler_payment_account.mint == payment_mint.key() @ MarketError::InvalidPaymentMint
+    )]
+    pub settler_payment_account: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+
+#[derive(Accounts)]
+pub struct CancelTokenAuction<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"auction",
+            mint_key.key().as_ref(),
+            creator.key().as_ref(),
+            auction.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        has_one = creator,
+        close = creator
+    )]
+    pub auction: Account<'info, Auction>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_key,
+        associated_token::authority = auction,
+    )]
+    pub auction_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = auction,
+    )]
+    pub auction_payment_account: Account<'info, TokenAccount>,
+
+    pub mint_key: Account<'info, Mint>,
+
+    #[account(constraint=Some(payment_mint.key()) == auction.payment_mint @ MarketError::InvalidPaymentMint)]
+    pub payment_mint: Account<'info, Mint>,
+
     #[account(mut)]
-    pub mint_authority: Signer<'info>,
+    pub creator: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint=creator_token_account.owner == creator.key(),
+        constraint=creator_token_account.mint == mint_key.key()
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint=creator_payment_account.owner == creator.key(),
+        constraint=creator_payment_account.mint == payment_mint.key() @ MarketError::InvalidPaymentMint
+    )]
+    pub creator_payment_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint=refund_receiver_payment_account.owner == auction.refund_receiver @ MarketError::InvalidReceiver,
+        constraint=refund_receiver_payment_account.mint == payment_mint.key() @ MarketError::InvalidPaymentMint
+    )]
+    pub refund_receiver_payment_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    #[account(
+        mut,
+        constraint=treasury_payment_account.owner == marketplace.treasury @ MarketError::InvalidTreasury,
+        constraint=treasury_payment_account.mint == payment_mint.key() @ MarketError::InvalidPaymentMint
+    )]
+    pub treasury_payment_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+
+#[derive(Accounts)]
+#[instruction(memo: String, start_price: u64, floor_price: u64, start_time: i64, end_time: i64, listing_id: u64)]
+pub struct CreateDutchAuction<'info> {
+    #[account(
+        init,
+        payer = creator,
+        space = DutchAuction::space(&memo),
+        seeds = [
+            b"dutch_auction",
+            mint_key.key().as_ref(),
+            creator.key().as_ref(),
+            listing_id.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub dutch_auction: Account<'info, DutchAuction>,
+
+    #[account(
+        init,
+        payer = creator,
+        associated_token::mint = mint_key,
+        associated_token::authority = dutch_auction
+    )]
+    pub auction_token_account: Account<'info, TokenAccount>,
+
+    #[account(constraint=mint_key.supply == 1 && mint_key.decimals == 0 @ MarketError::InvalidNft)]
+    pub mint_key: Account<'info, Mint>,
 
-    /// CHECK: This is not dangerous because we don't read or write from this account
     #[account(mut)]
-    pub mint: UncheckedAccount<'info>,
+    pub creator: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint=creator_token_account.owner == creator.key(),
+        constraint=creator_token_account.mint == mint_key.key(),
+        constraint=creator_token_account.amount == 1 @ MarketError::TokenNotHeld
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// CHECK: This account's address is only used.
+    #[account(
+        mut,
+        constraint=treasury_account.key() == marketplace.treasury @ MarketError::InvalidTreasury
+    )]
+    pub treasury_account: AccountInfo<'info>,
 
+    pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+
+#[derive(Accounts)]
+pub struct BuyDutch<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"dutch_auction",
+            mint_key.key.as_ref(),
+            creator.key().as_ref(),
+            dutch_auction.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump = dutch_auction.bump,
+        has_one = creator,
+        close = creator
+    )]
+    pub dutch_auction: Account<'info, DutchAuction>,
+
+    #[account(
+        mut,
+        associated_token::mint = dutch_auction.mint_key,
+        associated_token::authority = dutch_auction,
+    )]
+    pub auction_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    /// CHECK: This account's address is only used.
+    #[account(mut)]
+    pub creator: AccountInfo<'info>,
 
-    /// CHECK: This is not dangerous because we don't read or write from this account
     #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint=buyer_token_account.owner == buyer.key(),
+        constraint=buyer_token_account.mint == mint_key.key(),
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Deserialized as Metaplex metadata after its PDA derivation is checked.
+    #[account(
+        address = mpl_token_metadata::pda::find_metadata_account(mint_key.key).0 @ MarketError::InvalidMetadata
+    )]
     pub metadata: UncheckedAccount<'info>,
 
-    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+
+#[derive(Accounts)]
+pub struct CancelDutchAuction<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"dutch_auction",
+            mint_key.key().as_ref(),
+            creator.key().as_ref(),
+            dutch_auction.listing_id.to_le_bytes().as_ref(),
+        ],
+        bump = dutch_auction.bump,
+        has_one = creator,
+        close = creator
+    )]
+    pub dutch_auction: Account<'info, DutchAuction>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_key,
+        associated_token::authority = dutch_auction,
+    )]
+    pub auction_token_account: Account<'info, TokenAccount>,
+
+    pub mint_key: Account<'info, Mint>,
+
     #[account(mut)]
-    pub token_account: UncheckedAccount<'info>,
+    pub creator: Signer<'info>,
 
-    /// CHECK: This is not dangerous because we don't read or write from this account
-    pub token_metadata_program: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint=creator_token_account.owner == creator.key(),
+        constraint=creator_token_account.mint == mint_key.key()
+    )]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+
+#[derive(Accounts)]
+pub struct InitializeMarketplace<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = Marketplace::space(),
+        seeds = [b"marketplace"],
+        bump
+    )]
+    pub marketplace: Account<'info, Marketplace>,
 
-    /// CHECK: This is not dangerous because we don't read or write from this account
     #[account(mut)]
-    pub payer: AccountInfo<'info>,
+    pub admin: Signer<'info>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::UnicusTs>,
+
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ MarketError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
 
     pub system_program: Program<'info, System>,
+}
 
-    /// CHECK: This is not dangerous because we don't read or write from this account
-    pub rent: AccountInfo<'info>,
+
+#[derive(Accounts)]
+pub struct UpdateMarketplace<'info> {
+    #[account(
+        mut,
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+        has_one = admin @ MarketError::Unauthorized
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    pub admin: Signer<'info>,
 }
 
 
 #[derive(Accounts)]
-#[instruction(memo: String)]
-pub struct CreateOrder<'info> {
+pub struct MigrateRoyalty<'info> {
+    /// CHECK: Deserialized as Metaplex metadata after its PDA derivation is checked.
+    #[account(
+        mut,
+        address = mpl_token_metadata::pda::find_metadata_account(mint_key.key).0 @ MarketError::InvalidMetadata
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub update_authority: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = update_authority,
+        space = RoyaltyVersion::space(),
+        seeds = [b"royalty_version", mint_key.key().as_ref()],
+        bump
+    )]
+    pub royalty_version: Account<'info, RoyaltyVersion>,
+
+    /// CHECK: This account's address is only used.
+    #[account(address = mpl_token_metadata::id())]
+    pub token_metadata_program: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+
+#[derive(Accounts)]
+pub struct MakeOffer<'info> {
     #[account(
         init,
-        payer = creator,
-        space = Order::space(&memo),
+        payer = buyer,
+        space = Offer::space(),
         seeds = [
-            b"order",
+            b"offer",
             mint_key.key().as_ref(),
+            buyer.key().as_ref(),
         ],
         bump
     )]
-    pub order: Account<'info, Order>,
+    pub offer: Account<'info, Offer>,
+
+    pub mint_key: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"offer",
+            mint_key.key().as_ref(),
+            buyer.key().as_ref(),
+        ],
+        bump = offer.bump,
+        has_one = buyer,
+        close = buyer
+    )]
+    pub offer: Account<'info, Offer>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+}
+
+
+#[derive(Accounts)]
+pub struct AcceptOffer<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"offer",
+            mint_key.key().as_ref(),
+            buyer.key().as_ref(),
+        ],
+        bump = offer.bump,
+        has_one = buyer,
+        close = buyer
+    )]
+    pub offer: Account<'info, Offer>,
+
+    /// CHECK: This account's address is only used.
+    pub mint_key: AccountInfo<'info>,
+
+    /// CHECK: This account's address is only used.
+    #[account(mut)]
+    pub buyer: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint=buyer_token_account.owner == buyer.key(),
+        constraint=buyer_token_account.mint == mint_key.key(),
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub seller: Signer<'info>,
 
     #[account(
-        init,
-        payer = creator,
-        associated_token::mint = mint_key,
-        associated_token::authority = order
+        mut,
+        constraint=seller_token_account.owner == seller.key(),
+        constraint=seller_token_account.mint == mint_key.key()
     )]
-    pub order_token_account: Account<'info, TokenAccount>,
-
-    pub mint_key: Account<'info, Mint>,
+    pub seller_token_account: Account<'info, TokenAccount>,
 
-    #[account(mut)]
-    pub creator: Signer<'info>,
+    /// CHECK: Deserialized as Metaplex metadata after its PDA derivation is checked.
+    #[account(
+        address = mpl_token_metadata::pda::find_metadata_account(mint_key.key).0 @ MarketError::InvalidMetadata
+    )]
+    pub metadata: UncheckedAccount<'info>,
 
     #[account(
-        mut, 
-        constraint=creator_token_account.owner == creator.key(),
-        constraint=creator_token_account.mint == mint_key.key()
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
     )]
-    pub creator_token_account: Account<'info, TokenAccount>,
+    pub marketplace: Account<'info, Marketplace>,
+
     /// CHECK: This account's address is only used.
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint=treasury_account.key() == marketplace.treasury @ MarketError::InvalidTreasury
+    )]
     pub treasury_account: AccountInfo<'info>,
 
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
+
 #[derive(Accounts)]
-pub struct CancelOrder<'info> {
+pub struct MakeCollectionOffer<'info> {
     #[account(
-        mut,
+        init,
+        payer = buyer,
+        space = CollectionOffer::space(),
         seeds = [
-            b"order",
-            mint_key.key().as_ref()
+            b"collection_offer",
+            collection_mint.key().as_ref(),
+            buyer.key().as_ref(),
         ],
-        bump,
-        has_one = creator,
-        close = creator
-    )]
-    pub order: Account<'info, Order>,
-
-    #[account(
-        mut,
-        associated_token::mint = mint_key,
-        associated_token::authority = order,
+        bump
     )]
-    pub order_token_account: Account<'info, TokenAccount>,
+    pub collection_offer: Account<'info, CollectionOffer>,
 
-    pub mint_key: Account<'info, Mint>,
+    pub collection_mint: Account<'info, Mint>,
 
     #[account(mut)]
-    pub creator: Signer<'info>,
+    pub buyer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
 
+#[derive(Accounts)]
+pub struct CancelCollectionOffer<'info> {
     #[account(
         mut,
-        constraint=creator_token_account.owner == creator.key(),
-        constraint=creator_token_account.mint == mint_key.key()
+        seeds = [
+            b"collection_offer",
+            collection_offer.collection.as_ref(),
+            buyer.key().as_ref(),
+        ],
+        bump = collection_offer.bump,
+        has_one = buyer,
+        close = buyer
     )]
-    pub creator_token_account: Account<'info, TokenAccount>,
+    pub collection_offer: Account<'info, CollectionOffer>,
 
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
 }
 
 
 #[derive(Accounts)]
-pub struct FillOrder<'info> {
+pub struct CloseCollectionOffer<'info> {
     #[account(
         mut,
         seeds = [
-            b"order",
-            mint_key.key.as_ref(),
+            b"collection_offer",
+            collection_offer.collection.as_ref(),
+            buyer.key().as_ref(),
         ],
-        bump,
-        has_one = creator,
-        close = creator
+        bump = collection_offer.bump,
+        has_one = buyer,
+        constraint = collection_offer.quantity == 0 @ MarketError::OfferNotFilled,
+        close = buyer
     )]
-    pub order: Account<'info, Order>,
+    pub collection_offer: Account<'info, CollectionOffer>,
+
+    /// CHECK: This account's address is only used.
+    #[account(mut)]
+    pub buyer: AccountInfo<'info>,
+}
 
+
+#[derive(Accounts)]
+pub struct AcceptCollectionOffer<'info> {
     #[account(
         mut,
-        associated_token::mint = order.mint_key,
-        associated_token::authority = order,
+        seeds = [
+            b"collection_offer",
+            collection_offer.collection.as_ref(),
+            buyer.key().as_ref(),
+        ],
+        bump = collection_offer.bump,
+        has_one = buyer,
+        constraint = collection_offer.quantity > 0 @ MarketError::OfferFilled
     )]
-    pub order_token_account: Account<'info, TokenAccount>,
+    pub collection_offer: Account<'info, CollectionOffer>,
 
-    /// CHECK: This account's address is only used.
-    pub mint_key: AccountInfo<'info>,
+    pub mint_key: Account<'info, Mint>,
 
     /// CHECK: This account's address is only used.
     #[account(mut)]
-    pub creator: AccountInfo<'info>,
+    pub buyer: AccountInfo<'info>,
+
+    // The buyer cannot know which mints will be sold to them, so the seller pays for their token account.
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = mint_key,
+        associated_token::authority = buyer
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
 
     #[account(mut)]
-    pub buyer: Signer<'info>,
+    pub seller: Signer<'info>,
 
     #[account(
         mut,
-        constraint=buyer_token_account.owner == buyer.key(),
-        constraint=buyer_token_account.mint == mint_key.key(),
+        constraint=seller_token_account.owner == seller.key(),
+        constraint=seller_token_account.mint == mint_key.key()
     )]
-    pub buyer_token_account: Account<'info, TokenAccount>,
+    pub seller_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Deserialized as Metaplex metadata after its PDA derivation is checked.
+    #[account(
+        address = mpl_token_metadata::pda::find_metadata_account(&mint_key.key()).0 @ MarketError::InvalidMetadata
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"marketplace"],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
 
     /// CHECK: This account's address is only used.
-    #[account(mut)]
-    pub minter_account: AccountInfo<'info>,  
+    #[account(
+        mut,
+        constraint=treasury_account.key() == marketplace.treasury @ MarketError::InvalidTreasury
+    )]
+    pub treasury_account: AccountInfo<'info>,
 
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CreatorShare {
+    pub address: Pubkey,
+    pub share: u8,
+}
+
+
+#[account]
+pub struct Marketplace {
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
+    pub fee_basis_points: u16,
+    pub max_royalty_basis_points: u16,
+    pub crank_fee_basis_points: u16,
+    pub bump: u8,
+}
+
+
+#[account]
+pub struct Order {
+    pub creator: Pubkey,
+    pub mint_key: Pubkey,
+    pub listing_id: u64,
+    pub memo: String,
+    pub price: u64,
+    pub payment_mint: Option<Pubkey>,
+    pub expires_at: Option<i64>,
+    pub bump: u8,
+}
+
+
+#[account]
+pub struct Auction {
+    pub creator: Pubkey,
+    pub mint_key: Pubkey,
+    pub listing_id: u64,
+    pub refund_receiver: Pubkey,
+    pub memo: String,
+    pub price: u64,
+    pub payment_mint: Option<Pubkey>,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub min_increment_bps: u16,
+    pub min_increment: u64,
+    pub reserve_price: u64,
+    pub reserve_hash: Option<[u8; 32]>,
+    pub extension_window: u64,
+    pub extension_duration: u64,
+    pub max_extensions: u16,
+    pub extensions: u16,
+    pub reveal_end_time: i64,
+    pub second_price: bool,
+    pub min_deposit: u64,
+    pub slash_bps: u16,
+    pub second_bid: u64,
+    pub buy_now_price: Option<u64>,
+    pub crank_fee: u64,
+    pub listing_fee: u64,
+    pub bump: u8,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuctionPhase {
+    Pending,
+    Bidding,
+    Reveal,
+    Settlement,
+}
+
+
+// Descending price listing: the price falls linearly from `start_price` at `start_time`
+// to `floor_price` at `end_time`, and the first `buy_dutch` at or above it wins.
+#[account]
+pub struct DutchAuction {
+    pub creator: Pubkey,
+    pub mint_key: Pubkey,
+    pub listing_id: u64,
+    pub memo: String,
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub bump: u8,
+}
+
+
+// A sealed bid's commitment and deposit. `deposit` is what is left in the account
+// besides rent; a revealed bid that takes the lead moves its amount into the bid vault.
+#[account]
+pub struct SealedBid {
+    pub auction: Pubkey,
+    pub bidder: Pubkey,
+    pub creator: Pubkey,
+    pub commitment: [u8; 32],
+    pub deposit: u64,
+    pub revealed: bool,
+    pub amount: u64,
+    pub reveal_end_time: i64,
+    pub slash_bps: u16,
+    pub bump: u8,
+}
+
+
+// Holds the leading bid of a lamport priced auction. `amount` is the escrowed bid;
+// every other lamport in the account is rent and goes back to the creator on close.
+#[account]
+pub struct BidVault {
+    pub auction: Pubkey,
+    pub amount: u64,
+    pub bump: u8,
+}
+
+
+// Refunds credited to a bidder who was outbid through `deferred_bid`.
+#[account]
+pub struct BidRefund {
+    pub auction: Pubkey,
+    pub bidder: Pubkey,
+    pub amount: u64,
+    pub bump: u8,
+}
+
+
+#[account]
+pub struct Offer {
+    pub buyer: Pubkey,
+    pub mint_key: Pubkey,
+    pub price: u64,
+    pub bump: u8,
+}
+
+
+#[account]
+pub struct CollectionOffer {
+    pub buyer: Pubkey,
+    pub collection: Pubkey,
+    pub price: u64,
+    pub quantity: u64,
+    pub bump: u8,
+}
+
+
+// Which unit a mint's `seller_fee_basis_points` is in. `mint_nft` records it for every
+// nft it mints; older nfts have none until `migrate_royalty` converts them.
+#[account]
+pub struct RoyaltyVersion {
+    pub version: u8,
+    pub bump: u8,
+}
+
+
+pub const MAX_BASIS_POINTS: u16 = 10_000;
+
+// How long after an auction ends its creator has to reveal a hidden reserve.
+pub const RESERVE_REVEAL_WINDOW: i64 = 60 * 60;
+
+// Longest a single late bid may push an auction's end back.
+pub const MAX_EXTENSION_DURATION: u64 = 24 * 60 * 60;
+
+// Largest royalty `mint_nft` accepted when it was still a percent.
+pub const LEGACY_MAX_ROYALTY_PERCENT: u16 = 10;
+
+// `RoyaltyVersion::version` of a royalty stored in basis points.
+pub const ROYALTY_VERSION_BASIS_POINTS: u8 = 1;
+
+
+// Royalty owed to each creator on a sale of `price`, read from the mint's metadata.
+// `creator_accounts` must list every creator in the order stored in metadata, or
+// each creator's associated token account for `payment_mint` on token priced sales.
+fn creator_royalties<'info>(
+    metadata: &Metadata,
+    price: u64,
+    payment_mint: Option<Pubkey>,
+    creator_accounts: &[AccountInfo<'info>],
+) -> Result<Vec<(AccountInfo<'info>, u64)>> {
+    let creators = match &metadata.data.creators {
+        Some(creators) => creators,
+        None => return Ok(vec![]),
+    };
+
+    if creators.len() != creator_accounts.len() {
+        return Err(error!(MarketError::InvalidRoyaltyReceiver));
+    }
+
+    let royalty = proportion(price, metadata.data.seller_fee_basis_points as u64, MAX_BASIS_POINTS as u64)?;
+    let mut royalties = vec![];
+    for (creator, creator_account) in creators.iter().zip(creator_accounts) {
+        let receiver = match payment_mint {
+            Some(payment_mint) => get_associated_token_address(&creator.address, &payment_mint),
+            None => creator.address,
+        };
+        if receiver != creator_account.key() {
+            return Err(error!(MarketError::InvalidRoyaltyReceiver));
+        }
+
+        let amount = proportion(royalty, creator.share as u64, 100)?;
+        if amount > 0 {
+            royalties.push((creator_account.clone(), amount));
+        }
+    }
+
+    Ok(royalties)
+}
+
+
+impl Marketplace {
+    pub fn space() -> usize {
+        8 + 32 + 32 + 2 + 2 + 2 + 1
+    }
+
+    // Listing fee charged on `price`, in lamports.
+    pub fn fee(&self, price: u64) -> Result<u64> {
+        proportion(price, self.fee_basis_points as u64, MAX_BASIS_POINTS as u64)
+    }
+
+    // Part of a listing `fee` held back to pay whoever settles the auction.
+    pub fn crank_fee(&self, fee: u64) -> Result<u64> {
+        proportion(fee, self.crank_fee_basis_points as u64, MAX_BASIS_POINTS as u64)
+    }
+}
+
+
+impl Order {
+    pub fn is_expired(&self, now: i64) -> bool {
+        matches!(self.expires_at, Some(expires_at) if now >= expires_at)
+    }
+
+    pub fn space(memo: &str) -> usize {
+        8 + 32 + 32 + 8 +
+        4 + memo.len() + // memo string
+        8 + 1 + 32 + 1 + 8 + 1
+    }
+}
+
+
+impl Auction {
+    // The starting price until the first bid, then the leading bid raised by the
+    // larger of the two increments, and always by at least one.
+    pub fn min_bid(&self) -> Result<u64> {
+        if self.refund_receiver == self.creator {
+            return Ok(self.price);
+        }
+
+        let increment = proportion(self.price, self.min_increment_bps as u64, MAX_BASIS_POINTS as u64)?;
+        self.price.checked_add(increment.max(self.min_increment).max(1)).ok_or_else(|| error!(MarketError::MathOverflow))
+    }
+
+    // Pushes `end_time` back by `extension_duration` when a bid lands within the last
+    // `extension_window` seconds, at most `max_extensions` times. Returns whether it did.
+    pub fn extend(&mut self, now: i64) -> Result<bool> {
+        if self.extension_window == 0 || self.extensions >= self.max_extensions {
+            return Ok(false);
+        }
+
+        let extension_window = i64::try_from(self.extension_window).map_err(|_| error!(MarketError::MathOverflow))?;
+        if now.checked_add(extension_window).ok_or_else(|| error!(MarketError::MathOverflow))? < self.end_time {
+            return Ok(false);
+        }
+
+        let extension_duration = i64::try_from(self.extension_duration).map_err(|_| error!(MarketError::MathOverflow))?;
+        self.end_time = self.end_time.checked_add(extension_duration).ok_or_else(|| error!(MarketError::MathOverflow))?;
+        self.extensions = self.extensions.checked_add(1).ok_or_else(|| error!(MarketError::MathOverflow))?;
+        Ok(true)
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.reveal_end_time > 0
+    }
+
+    // Open auctions go straight from bidding to settlement, sealed ones reveal in between.
+    pub fn phase(&self, now: i64) -> AuctionPhase {
+        if now < self.start_time {
+            AuctionPhase::Pending
+        } else if now <= self.end_time {
+            AuctionPhase::Bidding
+        } else if now < self.reveal_end_time {
+            AuctionPhase::Reveal
+        } else {
+            AuctionPhase::Settlement
+        }
+    }
+
+    // What the winner pays: their own bid, or the runner-up's in a second price auction.
+    pub fn clearing_price(&self) -> u64 {
+        if self.second_price {
+            self.second_bid
+        } else {
+            self.price
+        }
+    }
+
+    // Whether a hidden reserve can still be revealed.
+    pub fn awaiting_reserve(&self, now: i64) -> bool {
+        self.reserve_hash.is_some() && now < self.end_time.saturating_add(RESERVE_REVEAL_WINDOW)
+    }
+
+    // A hidden reserve that was never revealed is not met, so it can not be dodged by
+    // settling before the creator reveals it.
+    pub fn reserve_met(&self) -> bool {
+        self.reserve_hash.is_none() && self.price >= self.reserve_price
+    }
+
+    pub fn space(memo: &str) -> usize {
+        8 + 32 + 8 + 32 + 32 +
+        4 + memo.len() + // memo string
+        8 + 1 + 32 + 8 + 8 +
+        2 + 8 + 8 + 1 + 32 +
+        8 + 8 + 2 + 2 +
+        8 + 1 + 8 + 2 + 8 +
+        1 + 8 + 8 + 8 + 1
+    }
+}
+
+
+impl DutchAuction {
+    pub fn current_price(&self, now: i64) -> Result<u64> {
+        if now <= self.start_time {
+            return Ok(self.start_price);
+        }
+        if now >= self.end_time {
+            return Ok(self.floor_price);
+        }
+
+        let elapsed = now.checked_sub(self.start_time).ok_or_else(|| error!(MarketError::MathOverflow))?;
+        let elapsed = u64::try_from(elapsed).map_err(|_| error!(MarketError::MathOverflow))?;
+        let duration = self.end_time.checked_sub(self.start_time).ok_or_else(|| error!(MarketError::MathOverflow))?;
+        let duration = u64::try_from(duration).map_err(|_| error!(MarketError::MathOverflow))?;
+        let range = self.start_price.checked_sub(self.floor_price).ok_or_else(|| error!(MarketError::MathOverflow))?;
+        let decay = proportion(range, elapsed, duration)?;
+        self.start_price.checked_sub(decay).ok_or_else(|| error!(MarketError::MathOverflow))
+    }
+
+    pub fn space(memo: &str) -> usize {
+        8 + 32 + 32 + 8 +
+        4 + memo.len() + // memo string
+        8 + 8 + 8 + 8 + 1
+    }
+}
+
+
+impl SealedBid {
+    pub fn space() -> usize {
+        8 + 32 + 32 + 32 + 32 + 8 + 1 + 8 + 8 + 2 + 1
+    }
 }
 
 
+impl BidVault {
+    pub fn space() -> usize {
+        8 + 32 + 8 + 1
+    }
+}
 
-#[derive(Accounts)]
-pub struct AuctionResolve<'info> {
-    #[account(
-        mut,
-        seeds = [
-            b"auction",
-            mint_key.key.as_ref(),
-        ],
-        bump,
-        has_one = creator,
-        close = creator
-    )]
-    pub auction: Account<'info, Auction>,
-    #[account(
-        mut, 
-        associated_token::mint = auction.mint_key,
-        associated_token::authority = auction,
-    )]
-    pub auction_token_account: Account<'info, TokenAccount>,
-    /// CHECK: This account's address is only used.
-    pub mint_key: AccountInfo<'info>,
 
-    /// CHECK: This account's address is only used.
-    #[account(mut)]
-    pub creator: AccountInfo<'info>,
+impl BidRefund {
+    pub fn space() -> usize {
+        8 + 32 + 32 + 8 + 1
+    }
+}
 
-    #[account(
-        mut,
-        constraint=creator_token_account.owner == creator.key(),
-        constraint=creator_token_account.mint == mint_key.key()
-    )]
-    pub creator_token_account: Account<'info, TokenAccount>,
 
-    /// CHECK: This account's address is only used.
-    #[account(mut)]
-    pub refund_receiver: AccountInfo<'info>,
+// Pays the listing fee a scheduled auction held back over to the treasury once it can
+// no longer be cancelled for free, and returns how much that was.
+fn release_listing_fee(auction: &mut Account<Auction>, treasury: &AccountInfo) -> Result<u64> {
+    let listing_fee = auction.listing_fee;
+    if listing_fee == 0 {
+        return Ok(0);
+    }
 
-    #[account(
-        mut,
-        constraint=refund_receiver_token_account.owner == refund_receiver.key(),
-        constraint=refund_receiver_token_account.mint == mint_key.key(),
-    )]
-    pub refund_receiver_token_account: Account<'info, TokenAccount>,
-    /// CHECK: This account's address is only used.
-    #[account(mut)]
-    pub minter_account: AccountInfo<'info>, 
+    auction.listing_fee = 0;
+    move_lamports(&auction.to_account_info(), treasury, listing_fee)?;
 
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+    Ok(listing_fee)
 }
 
 
-#[derive(Accounts)]
-pub struct Bid<'info> {
-    #[account(
-        mut,
-        seeds = [
-            b"auction",
-            mint_key.key.as_ref(),
-        ],
-        bump,
-        has_one = creator
-    )]
-    pub auction: Account<'info, Auction>,
+// Moves lamports out of an account owned by this program.
+fn move_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    let from_lamports = from.lamports().checked_sub(amount).ok_or_else(|| error!(MarketError::MathOverflow))?;
+    let to_lamports = to.lamports().checked_add(amount).ok_or_else(|| error!(MarketError::MathOverflow))?;
+    **from.try_borrow_mut_lamports()? = from_lamports;
+    **to.try_borrow_mut_lamports()? = to_lamports;
 
-    /// CHECK: This account's address is only used.
-    pub mint_key: AccountInfo<'info>,
+    Ok(())
+}
 
-    /// CHECK: This account's address is only used.
-    #[account(mut)]
-    pub creator: AccountInfo<'info>,
 
-    #[account(mut)]
-    pub bidder: Signer<'info>,
+// Anti-sniping extensions may not reach past the auction's own length, and each one
+// is capped at `MAX_EXTENSION_DURATION`.
+fn check_extensions(start_time: i64, end_time: i64, extension_window: u64, extension_duration: u64) -> Result<()> {
+    let length = end_time.checked_sub(start_time).ok_or_else(|| error!(MarketError::MathOverflow))?;
+    let length = u64::try_from(length).map_err(|_| error!(MarketError::MathOverflow))?;
+    if extension_window > length || extension_duration > length || extension_duration > MAX_EXTENSION_DURATION {
+        return Err(error!(MarketError::InvalidExtension));
+    }
 
-    /// CHECK: This account's address is only used.
-    #[account(mut)]
-    pub refund_receiver: AccountInfo<'info>,
+    Ok(())
+}
 
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+
+// `amount * numerator / denominator`, widened to u128 so the product can not overflow.
+fn proportion(amount: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    let result = amount as u128 * numerator as u128 / denominator as u128;
+    u64::try_from(result).map_err(|_| error!(MarketError::MathOverflow))
 }
 
-//"refund_receiver.key == &Pubkey::default() || refund_receiver.key == &auction.refund_receiver"
 
+// Moves `amount` of escrowed bid out of the vault, leaving its rent untouched.
+fn withdraw_bid(bid_vault: &mut Account<BidVault>, receiver: &AccountInfo, amount: u64) -> Result<()> {
+    if amount > bid_vault.amount {
+        return Err(error!(MarketError::BidVaultInsufficientFunds));
+    }
 
-#[derive(Accounts)]
-pub struct CancelAuction<'info> {
-    #[account(
-        mut,
-        seeds = [
-            b"auction",
-            mint_key.key().as_ref(),
-        ],
-        bump,
-        has_one = creator,
-        close = creator
-    )]
-    pub auction: Account<'info, Auction>,
+    bid_vault.amount -= amount;
+    move_lamports(&bid_vault.to_account_info(), receiver, amount)
+}
 
-    #[account(
-        mut, 
-        associated_token::mint = mint_key,
-        associated_token::authority = auction,
-    )]
-    pub auction_token_account: Account<'info, TokenAccount>,
 
-    /// CHECK: This account's address is only used.
-    pub mint_key: Account<'info, Mint>,
+impl Offer {
+    pub fn space() -> usize {
+        8 + 32 + 32 + 8 + 1
+    }
+}
 
-    #[account(mut)]
-    pub creator: Signer<'info>,
 
-    #[account(
-        mut,
-        constraint=creator_token_account.owner == creator.key(),
-        constraint=creator_token_account.mint == mint_key.key()
-    )]
-    pub creator_token_account: Account<'info, TokenAccount>,
+impl CollectionOffer {
+    pub fn space() -> usize {
+        8 + 32 + 32 + 8 + 8 + 1
+    }
+}
 
-    /// CHECK: This account's address is only used.
-    #[account(mut)]
-    pub refund_receiver: AccountInfo<'info>,
 
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+impl RoyaltyVersion {
+    pub fn space() -> usize {
+        8 + 1 + 1
+    }
+}
 
 
+#[event]
+pub struct NftMinted {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub royalty: u16,
+    pub timestamp: i64,
 }
 
 
-#[derive(Accounts)]
-#[instruction(memo: String)]
-pub struct CreateAuction<'info> {
-    #[account(
-        init,
-        payer = creator,
-        space = Auction::space(&memo),
-        seeds = [
-            b"auction",
-            mint_key.key().as_ref(),
-        ],
-        bump
-    )]
-    pub auction: Account<'info, Auction>,
+#[event]
+pub struct OrderCreated {
+    pub order: Pubkey,
+    pub mint: Pubkey,
+    pub creator: Pubkey,
+    pub price: u64,
+    pub payment_mint: Option<Pubkey>,
+    pub fee: u64,
+    pub expires_at: Option<i64>,
+    pub timestamp: i64,
+}
 
-    #[account(
-        init,
-        payer = creator,
-        associated_token::mint = mint_key,
-        associated_token::authority = auction
-    )]
-    pub auction_token_account: Account<'info, TokenAccount>,
 
-    pub mint_key: Account<'info, Mint>,
+#[event]
+pub struct OrderCancelled {
+    pub order: Pubkey,
+    pub mint: Pubkey,
+    pub creator: Pubkey,
+    pub timestamp: i64,
+}
 
-    #[account(mut)]
-    pub creator: Signer<'info>,
 
-    #[account(
-        mut, 
-        constraint=creator_token_account.owner == creator.key(),
-        constraint=creator_token_account.mint == mint_key.key()
-    )]
-    pub creator_token_account: Account<'info, TokenAccount>,
+// `fee` is the listing fee paid on a price increase.
+#[event]
+pub struct OrderUpdated {
+    pub order: Pubkey,
+    pub mint: Pubkey,
+    pub creator: Pubkey,
+    pub price: u64,
+    pub payment_mint: Option<Pubkey>,
+    pub fee: u64,
+    pub timestamp: i64,
+}
 
-    /// CHECK: This account's address is only used.
-    #[account(mut)]
-    pub treasury_account: AccountInfo<'info>,
 
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub rent: Sysvar<'info, Rent>,
+// Also emitted when an offer is accepted, with the offer's address as `order`.
+// `fee` is what the sale paid the treasury. Orders pay theirs when listed, see
+// `OrderCreated`; offers pay it out of the escrow when accepted.
+#[event]
+pub struct OrderFilled {
+    pub order: Pubkey,
+    pub mint: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub price: u64,
+    pub payment_mint: Option<Pubkey>,
+    pub royalty: u64,
+    pub fee: u64,
+    pub timestamp: i64,
 }
 
 
-#[account]
-pub struct Order {
+#[event]
+pub struct AuctionCreated {
+    pub auction: Pubkey,
+    pub mint: Pubkey,
     pub creator: Pubkey,
-    pub mint_key: Pubkey,
-    pub memo: String,
     pub price: u64,
-    pub bump: u8,
+    pub payment_mint: Option<Pubkey>,
+    pub fee: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub timestamp: i64,
 }
 
 
-#[account]
-pub struct Auction {
+#[event]
+pub struct BidPlaced {
+    pub auction: Pubkey,
+    pub mint: Pubkey,
+    pub bidder: Pubkey,
+    pub price: u64,
+    pub timestamp: i64,
+}
+
+
+#[event]
+pub struct AuctionCancelled {
+    pub auction: Pubkey,
+    pub mint: Pubkey,
     pub creator: Pubkey,
-    pub mint_key: Pubkey,
-    pub refund_receiver: Pubkey,
-    pub memo: String,
+    pub timestamp: i64,
+}
+
+
+// `winner` is empty and `price` zero when the nft goes back to the creator unsold.
+// `fee` is the listing fee a scheduled auction held back and now paid the treasury, the
+// rest having been paid at creation, and `crank_fee` is what the settler was paid.
+#[event]
+pub struct AuctionResolved {
+    pub auction: Pubkey,
+    pub mint: Pubkey,
+    pub creator: Pubkey,
+    pub winner: Option<Pubkey>,
     pub price: u64,
-    pub start_time: u128,
-    pub end_time: u128,
-    pub bump: u8,
+    pub payment_mint: Option<Pubkey>,
+    pub royalty: u64,
+    pub fee: u64,
+    pub crank_fee: u64,
+    pub timestamp: i64,
 }
 
 
-impl Order {
-    pub fn space(memo: &str) -> usize {
-        8 + 32 + 32 +
-        4 + memo.len() + // memo string
-        8 + 1
-    }
+#[event]
+pub struct AuctionExtended {
+    pub auction: Pubkey,
+    pub end_time: i64,
+    pub extensions: u16,
 }
 
 
-impl Auction {
-    pub fn space(memo: &str) -> usize {
-        8 + 32 + 32 + 32 +
-        4 + memo.len() + // memo string
-        8 + 16 + 16 + 1
-    }
+#[event]
+pub struct ReserveRevealed {
+    pub auction: Pubkey,
+    pub mint: Pubkey,
+    pub reserve_price: u64,
+    pub timestamp: i64,
 }
 
 
+#[event]
+pub struct OfferMade {
+    pub offer: Pubkey,
+    pub mint: Pubkey,
+    pub buyer: Pubkey,
+    pub price: u64,
+    pub timestamp: i64,
+}
 
 
+#[event]
+pub struct OfferCancelled {
+    pub offer: Pubkey,
+    pub mint: Pubkey,
+    pub buyer: Pubkey,
+    pub timestamp: i64,
+}
 
 
 
 
 
-#[error_code]
-pub enum MintError {
-    #[msg("Mint failed!")]
-    MintFailed,
 
-    #[msg("Metadata account create failed!")]
-    MetadataCreateFailed,
 
-    #[msg("Royalty cannot be more than 10")]
-    RoyaltyExceeded,
-}
 
 
 #[error_code]
@@ -988,5 +5064,142 @@ pub enum MarketError {
 
     #[msg("Invalid Refund Receiver address")]
     InvalidReceiver,
+
+    #[msg("Treasury account does not match the marketplace treasury!")]
+    InvalidTreasury,
+
+    #[msg("Fee cannot be more than 10000 basis points")]
+    InvalidFee,
+
+    #[msg("Signer is not the marketplace admin!")]
+    Unauthorized,
+
+    #[msg("Metadata account does not belong to this mint!")]
+    InvalidMetadata,
+
+    #[msg("Royalty receiver does not match the creator in metadata!")]
+    InvalidRoyaltyReceiver,
+
+    #[msg("Offer quantity must be at least 1")]
+    InvalidQuantity,
+
+    #[msg("Nft is not a verified member of the offer's collection!")]
+    InvalidCollection,
+
+    #[msg("Payment mint does not match the listing!")]
+    InvalidPaymentMint,
+
+    #[msg("Only nfts with a supply of 1 and no decimals can be listed!")]
+    InvalidNft,
+
+    #[msg("Creator does not hold this nft, it may already be escrowed by another listing!")]
+    TokenNotHeld,
+
+    #[msg("Order expiry must be in the future...!")]
+    InvalidExpiry,
+
+    #[msg("Order has expired...!")]
+    OrderExpired,
+
+    #[msg("Order has not expired yet...!")]
+    OrderNotExpired,
+
+    #[msg("Bid vault does not hold enough escrowed lamports!")]
+    BidVaultInsufficientFunds,
+
+    #[msg("Reserve price does not match the auction's reserve!")]
+    InvalidReserve,
+
+    #[msg("Auction start time must be before its end time!")]
+    InvalidStartTime,
+
+    #[msg("Auction has not started yet...!")]
+    AuctionNotStarted,
+
+    #[msg("Floor price must not be above the start price!")]
+    InvalidFloorPrice,
+
+    #[msg("Reveal window must end after the bidding phase!")]
+    InvalidRevealTime,
+
+    #[msg("Slash can not exceed 10000 basis points!")]
+    InvalidSlash,
+
+    #[msg("Sealed-bid auctions only take committed bids!")]
+    SealedAuction,
+
+    #[msg("Auction does not take sealed bids!")]
+    NotSealedAuction,
+
+    #[msg("Auction is not taking bids...!")]
+    NotBiddingPhase,
+
+    #[msg("Auction is not in its reveal window...!")]
+    NotRevealPhase,
+
+    #[msg("Bid is already revealed!")]
+    AlreadyRevealed,
+
+    #[msg("Revealed bid does not match its commitment!")]
+    InvalidCommitment,
+
+    #[msg("Reveal window has not ended yet...!")]
+    RevealNotEnded,
+
+    #[msg("Auction has already started and can not be cancelled!")]
+    AuctionStarted,
+
+    #[msg("Buy-now price must not be below the starting price!")]
+    InvalidBuyNowPrice,
+
+    #[msg("Auction can not be bought now!")]
+    BuyNowUnavailable,
+
+    #[msg("Amount overflows or exceeds the price!")]
+    MathOverflow,
+
+    #[msg("Collection offer has already been filled!")]
+    OfferFilled,
+
+    #[msg("Collection offer still has items to buy!")]
+    OfferNotFilled,
+
+    #[msg("Hidden reserve price has not been revealed yet...!")]
+    ReserveNotRevealed,
+
+    #[msg("Reserve price can no longer be revealed!")]
+    ReserveRevealEnded,
+
+    #[msg("Auction extension is longer than allowed!")]
+    InvalidExtension,
+
+    #[msg("Price must be above zero!")]
+    InvalidPrice,
+}
+
+
+//
+// Numbered after `MarketError` so a code alone says which enum raised it. Only
+// `MarketError` makes it into the IDL, which anchor numbers from 6000.
+//
+#[error_code(offset = 7000)]
+pub enum MintError {
+    #[msg("Mint failed!")]
+    MintFailed,
+
+    #[msg("Metadata account create failed!")]
+    MetadataCreateFailed,
+
+    #[msg("Royalty cannot be more than the marketplace maximum")]
+    RoyaltyExceeded,
+
+    #[msg("Creator shares must list 1 to 5 creators and sum to 100")]
+    InvalidCreatorShares,
+
+    #[msg("Royalty is already in basis points!")]
+    RoyaltyAlreadyMigrated,
+
+    #[msg("Metadata account update failed!")]
+    MetadataUpdateFailed,
 }
 
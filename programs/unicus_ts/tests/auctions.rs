@@ -0,0 +1,699 @@
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::hash::hashv;
+use common::*;
+use solana_program_test::tokio;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::signature::{Keypair, Signer};
+use unicus_client::{
+    args, instruction, pda, Auction, BidRefund, BidVault, MarketError, MAX_EXTENSION_DURATION,
+    RESERVE_REVEAL_WINDOW,
+};
+
+const PRICE: u64 = LAMPORTS_PER_SOL;
+const TOKEN_PRICE: u64 = 1_000_000;
+const DURATION: i64 = 3600;
+
+
+struct Listing {
+    creator: Keypair,
+    artist: Keypair,
+    mint: Pubkey,
+    auction: Pubkey,
+    treasury: Pubkey,
+    end_time: i64,
+}
+
+
+// Lists an nft minted by a separate artist in an auction that started a minute ago.
+async fn list(harness: &Harness, customize: impl FnOnce(args::CreateAuction) -> args::CreateAuction) -> Listing {
+    let artist = harness.user(1).await;
+    let creator = harness.user(1).await;
+    let mint = harness.mint_nft(&artist, ROYALTY_BASIS_POINTS).await;
+    harness.transfer_nft(&artist, &mint, &creator.pubkey()).await;
+
+    let now = harness.now().await;
+    let args = customize(auction_args(PRICE, now - 60, now + DURATION, 0));
+    let end_time = args.end_time;
+    harness
+        .send(&[instruction::create_auction(&creator.pubkey(), &mint, &harness.treasury, args)], &[&creator])
+        .await
+        .unwrap();
+
+    Listing {
+        auction: pda::auction(&mint, &creator.pubkey(), 0),
+        treasury: harness.treasury,
+        creator,
+        artist,
+        mint,
+        end_time,
+    }
+}
+
+
+impl Listing {
+    fn bid(&self, bidder: &Keypair, leader: &Pubkey, price: u64) -> Instruction {
+        instruction::bid(&bidder.pubkey(), &self.creator.pubkey(), &self.mint, 0, leader, price)
+    }
+
+    fn resolve(&self, settler: &Keypair, winner: &Pubkey) -> Instruction {
+        instruction::auction_resolve(&settler.pubkey(), &self.creator.pubkey(), &self.mint, 0, winner, &self.treasury, &[self.artist.pubkey()])
+    }
+
+    fn cancel(&self, leader: &Pubkey) -> Instruction {
+        instruction::cancel_auction(&self.creator.pubkey(), &self.mint, 0, leader, &self.treasury)
+    }
+}
+
+
+#[tokio::test]
+async fn runs_an_auction_to_the_highest_bidder() {
+    let harness = Harness::start().await;
+    let treasury_balance = harness.balance(&harness.treasury).await;
+    let listing = list(&harness, |args| args).await;
+    assert_eq!(harness.balance(&harness.treasury).await, treasury_balance + fee(PRICE));
+
+    let creator = listing.creator.pubkey();
+    let first = harness.user(3).await;
+    let second = harness.user(3).await;
+    let settler = harness.user(1).await;
+
+    let result = harness.send(&[listing.bid(&first, &creator, PRICE - 1)], &[&first]).await;
+    assert_error(result, MarketError::InsufficientMoney);
+
+    harness.send(&[listing.bid(&first, &creator, PRICE)], &[&first]).await.unwrap();
+    let vault: BidVault = harness.fetch(&pda::bid_vault(&listing.auction)).await;
+    assert_eq!(vault.amount, PRICE);
+
+    // Bids must beat the leader, and refund exactly the leader.
+    let result = harness.send(&[listing.bid(&second, &first.pubkey(), PRICE)], &[&second]).await;
+    assert_error(result, MarketError::InsufficientMoney);
+    let result = harness.send(&[listing.bid(&second, &second.pubkey(), 2 * PRICE)], &[&second]).await;
+    assert_error(result, MarketError::InvalidReceiver);
+
+    let first_balance = harness.balance(&first.pubkey()).await;
+    harness.send(&[listing.bid(&second, &first.pubkey(), 2 * PRICE)], &[&second]).await.unwrap();
+    assert_eq!(harness.balance(&first.pubkey()).await, first_balance + PRICE);
+
+    let auction: Auction = harness.fetch(&listing.auction).await;
+    assert_eq!(auction.refund_receiver, second.pubkey());
+    assert_eq!(auction.price, 2 * PRICE);
+
+    let result = harness.send(&[listing.cancel(&first.pubkey())], &[&listing.creator]).await;
+    assert_error(result, MarketError::InvalidReceiver);
+
+    let result = harness.send(&[listing.resolve(&settler, &second.pubkey())], &[&settler]).await;
+    assert_error(result, MarketError::AuctionNotEnded);
+
+    harness.warp_to(listing.end_time + 1).await;
+    let result = harness.send(&[listing.bid(&first, &second.pubkey(), 3 * PRICE)], &[&first]).await;
+    assert_error(result, MarketError::AuctionEnded);
+
+    let result = harness.send(&[listing.resolve(&settler, &first.pubkey())], &[&settler]).await;
+    assert_error(result, MarketError::InvalidReceiver);
+
+    let artist_balance = harness.balance(&listing.artist.pubkey()).await;
+    harness.send(&[listing.resolve(&settler, &second.pubkey())], &[&settler]).await.unwrap();
+
+    assert_eq!(harness.token_balance(&pda::token_account(&second.pubkey(), &listing.mint)).await, 1);
+    assert_eq!(harness.balance(&listing.artist.pubkey()).await, artist_balance + royalty(2 * PRICE));
+    assert!(!harness.exists(&listing.auction).await);
+    assert!(!harness.exists(&pda::bid_vault(&listing.auction)).await);
+}
+
+
+#[tokio::test]
+async fn unsold_auctions_return_the_nft() {
+    let harness = Harness::start().await;
+    let listing = list(&harness, |args| args).await;
+    let creator = listing.creator.pubkey();
+    let settler = harness.user(1).await;
+
+    harness.warp_to(listing.end_time).await;
+    harness.send(&[listing.resolve(&settler, &creator)], &[&settler]).await.unwrap();
+
+    assert_eq!(harness.token_balance(&pda::token_account(&creator, &listing.mint)).await, 1);
+    assert!(!harness.exists(&listing.auction).await);
+}
+
+
+#[tokio::test]
+async fn create_auction_checks_its_schedule_and_prices() {
+    let harness = Harness::start().await;
+    let creator = harness.user(1).await;
+    let mint = harness.mint_nft(&creator, ROYALTY_BASIS_POINTS).await;
+    let now = harness.now().await;
+
+    let create = |args| instruction::create_auction(&creator.pubkey(), &mint, &harness.treasury, args);
+
+    let result = harness.send(&[create(auction_args(PRICE, now - 120, now - 60, 0))], &[&creator]).await;
+    assert_error(result, MarketError::InvalidEndTIme);
+
+    let result = harness.send(&[create(auction_args(PRICE, now + 60, now + 60, 0))], &[&creator]).await;
+    assert_error(result, MarketError::InvalidStartTime);
+
+    let args = args::CreateAuction { buy_now_price: Some(PRICE - 1), ..auction_args(PRICE, now, now + 60, 0) };
+    let result = harness.send(&[create(args)], &[&creator]).await;
+    assert_error(result, MarketError::InvalidBuyNowPrice);
+
+    let args = args::CreateAuction { reserve_price: PRICE, reserve_hash: Some([1; 32]), ..auction_args(PRICE, now, now + 60, 0) };
+    let result = harness.send(&[create(args)], &[&creator]).await;
+    assert_error(result, MarketError::InvalidReserve);
+
+    // Extensions are bounded by the auction's length and by `MAX_EXTENSION_DURATION`.
+    let args = args::CreateAuction { extension_window: 61, ..auction_args(PRICE, now, now + 60, 0) };
+    let result = harness.send(&[create(args)], &[&creator]).await;
+    assert_error(result, MarketError::InvalidExtension);
+
+    let args = args::CreateAuction { extension_window: 60, extension_duration: u64::MAX, ..auction_args(PRICE, now, now + 60, 0) };
+    let result = harness.send(&[create(args)], &[&creator]).await;
+    assert_error(result, MarketError::InvalidExtension);
+
+    let end_time = now + 2 * MAX_EXTENSION_DURATION as i64;
+    let args = args::CreateAuction { extension_duration: MAX_EXTENSION_DURATION + 1, ..auction_args(PRICE, now, end_time, 0) };
+    let result = harness.send(&[create(args)], &[&creator]).await;
+    assert_error(result, MarketError::InvalidExtension);
+
+    let result = harness
+        .send(
+            &[instruction::create_auction(&creator.pubkey(), &mint, &creator.pubkey(), auction_args(PRICE, now, now + 60, 0))],
+            &[&creator],
+        )
+        .await;
+    assert_error(result, MarketError::InvalidTreasury);
+
+    harness.send(&[create(auction_args(PRICE, now, now + 60, 0))], &[&creator]).await.unwrap();
+    let result = harness.send(&[create(auction_args(PRICE, now, now + 60, 1))], &[&creator]).await;
+    assert_error(result, MarketError::TokenNotHeld);
+}
+
+
+#[tokio::test]
+async fn pending_auctions_take_no_bids_and_cancel_freely() {
+    let harness = Harness::start().await;
+    let bidder = harness.user(3).await;
+    let treasury_balance = harness.balance(&harness.treasury).await;
+    let listing = list(&harness, |args| args::CreateAuction { start_time: args.start_time + 3600, ..args }).await;
+    let creator = listing.creator.pubkey();
+
+    // The listing fee waits in the auction until it starts.
+    assert_eq!(harness.balance(&harness.treasury).await, treasury_balance);
+    let auction: Auction = harness.fetch(&listing.auction).await;
+    assert_eq!(auction.listing_fee, fee(PRICE));
+
+    let result = harness.send(&[listing.bid(&bidder, &creator, PRICE)], &[&bidder]).await;
+    assert_error(result, MarketError::AuctionNotStarted);
+
+    // Cancelling returns it along with the auction's rent.
+    let held = harness.balance(&listing.auction).await
+        + harness.balance(&pda::bid_vault(&listing.auction)).await
+        + harness.balance(&pda::token_account(&listing.auction, &listing.mint)).await;
+    let creator_balance = harness.balance(&creator).await;
+    harness.send(&[listing.cancel(&creator)], &[&listing.creator]).await.unwrap();
+
+    assert_eq!(harness.balance(&creator).await, creator_balance + held);
+    assert_eq!(harness.balance(&harness.treasury).await, treasury_balance);
+    assert_eq!(harness.token_balance(&pda::token_account(&creator, &listing.mint)).await, 1);
+    assert!(!harness.exists(&listing.auction).await);
+
+    // Ended auctions have to be resolved instead.
+    let listing = list(&harness, |args| args).await;
+    harness.warp_to(listing.end_time + 1).await;
+    let result = harness.send(&[listing.cancel(&listing.creator.pubkey())], &[&listing.creator]).await;
+    assert_error(result, MarketError::AuctionEnded);
+}
+
+
+#[tokio::test]
+async fn started_auctions_cancel_by_refunding_the_leader_and_keeping_the_fee() {
+    let harness = Harness::start().await;
+    let bidder = harness.user(3).await;
+    let treasury_balance = harness.balance(&harness.treasury).await;
+    let listing = list(&harness, |args| args::CreateAuction { start_time: args.start_time + 3600, ..args }).await;
+    let creator = listing.creator.pubkey();
+
+    harness.warp_to(listing.end_time - 60).await;
+    harness.send(&[listing.bid(&bidder, &creator, PRICE)], &[&bidder]).await.unwrap();
+
+    // The refund can only go to the leading bidder.
+    let result = harness.send(&[listing.cancel(&creator)], &[&listing.creator]).await;
+    assert_error(result, MarketError::InvalidReceiver);
+
+    let bidder_balance = harness.balance(&bidder.pubkey()).await;
+    harness.send(&[listing.cancel(&bidder.pubkey())], &[&listing.creator]).await.unwrap();
+
+    assert_eq!(harness.balance(&bidder.pubkey()).await, bidder_balance + PRICE);
+    assert_eq!(harness.balance(&harness.treasury).await, treasury_balance + fee(PRICE));
+    assert_eq!(harness.token_balance(&pda::token_account(&creator, &listing.mint)).await, 1);
+    assert!(!harness.exists(&listing.auction).await);
+    assert!(!harness.exists(&pda::bid_vault(&listing.auction)).await);
+}
+
+
+#[tokio::test]
+async fn late_bids_extend_the_auction() {
+    let harness = Harness::start().await;
+    let first = harness.user(3).await;
+    let second = harness.user(3).await;
+    let listing = list(&harness, |args| args::CreateAuction {
+        min_increment_bps: 1000,
+        extension_window: 300,
+        extension_duration: 600,
+        max_extensions: 1,
+        ..args
+    })
+    .await;
+    let creator = listing.creator.pubkey();
+
+    harness.send(&[listing.bid(&first, &creator, PRICE)], &[&first]).await.unwrap();
+
+    // A tenth of the leading bid on top of it.
+    let result = harness.send(&[listing.bid(&second, &first.pubkey(), PRICE * 11 / 10 - 1)], &[&second]).await;
+    assert_error(result, MarketError::InsufficientMoney);
+
+    harness.warp_to(listing.end_time - 100).await;
+    harness.send(&[listing.bid(&second, &first.pubkey(), PRICE * 11 / 10)], &[&second]).await.unwrap();
+    let auction: Auction = harness.fetch(&listing.auction).await;
+    assert_eq!(auction.end_time, listing.end_time + 600);
+    assert_eq!(auction.extensions, 1);
+
+    harness.warp_to(listing.end_time + 550).await;
+    harness.send(&[listing.bid(&first, &second.pubkey(), 2 * PRICE)], &[&first]).await.unwrap();
+    let auction: Auction = harness.fetch(&listing.auction).await;
+    assert_eq!(auction.end_time, listing.end_time + 600);
+    assert_eq!(auction.extensions, 1);
+}
+
+
+#[tokio::test]
+async fn hidden_reserve_holds_back_a_low_winning_bid() {
+    let harness = Harness::start().await;
+    let bidder = harness.user(3).await;
+    let settler = harness.user(1).await;
+    let salt = [7; 32];
+    let reserve_hash = hashv(&[&(2 * PRICE).to_le_bytes(), &salt]).to_bytes();
+    let listing = list(&harness, |args| args::CreateAuction { reserve_hash: Some(reserve_hash), ..args }).await;
+    let creator = listing.creator.pubkey();
+
+    harness.send(&[listing.bid(&bidder, &creator, PRICE)], &[&bidder]).await.unwrap();
+
+    let result = harness
+        .send(&[instruction::reveal_reserve(&creator, &listing.mint, 0, 2 * PRICE, [8; 32])], &[])
+        .await;
+    assert_error(result, MarketError::InvalidReserve);
+    harness
+        .send(&[instruction::reveal_reserve(&creator, &listing.mint, 0, 2 * PRICE, salt)], &[])
+        .await
+        .unwrap();
+
+    let auction: Auction = harness.fetch(&listing.auction).await;
+    assert_eq!(auction.reserve_price, 2 * PRICE);
+    assert_eq!(auction.reserve_hash, None);
+
+    harness.warp_to(listing.end_time + 1).await;
+    let bidder_balance = harness.balance(&bidder.pubkey()).await;
+    harness.send(&[listing.resolve(&settler, &bidder.pubkey())], &[&settler]).await.unwrap();
+
+    assert_eq!(harness.balance(&bidder.pubkey()).await, bidder_balance + PRICE);
+    assert_eq!(harness.token_balance(&pda::token_account(&creator, &listing.mint)).await, 1);
+}
+
+
+#[tokio::test]
+async fn unrevealed_reserve_is_not_met_after_the_reveal_window() {
+    let harness = Harness::start().await;
+    let bidder = harness.user(3).await;
+    let settler = harness.user(1).await;
+    let salt = [7; 32];
+    let reserve_hash = hashv(&[&(2 * PRICE).to_le_bytes(), &salt]).to_bytes();
+    let listing = list(&harness, |args| args::CreateAuction { reserve_hash: Some(reserve_hash), ..args }).await;
+    let creator = listing.creator.pubkey();
+    let reveal = || instruction::reveal_reserve(&creator, &listing.mint, 0, 2 * PRICE, salt);
+
+    harness.send(&[listing.bid(&bidder, &creator, 3 * PRICE)], &[&bidder]).await.unwrap();
+
+    // Nobody can settle ahead of the creator's reveal, even above the reserve.
+    harness.warp_to(listing.end_time + 1).await;
+    let result = harness.send(&[listing.resolve(&settler, &bidder.pubkey())], &[&settler]).await;
+    assert_error(result, MarketError::ReserveNotRevealed);
+
+    harness.warp_to(listing.end_time + RESERVE_REVEAL_WINDOW).await;
+    let result = harness.send(&[reveal()], &[]).await;
+    assert_error(result, MarketError::ReserveRevealEnded);
+
+    let bidder_balance = harness.balance(&bidder.pubkey()).await;
+    harness.send(&[listing.resolve(&settler, &bidder.pubkey())], &[&settler]).await.unwrap();
+
+    assert_eq!(harness.balance(&bidder.pubkey()).await, bidder_balance + 3 * PRICE);
+    assert_eq!(harness.token_balance(&pda::token_account(&creator, &listing.mint)).await, 1);
+    assert!(!harness.exists(&listing.auction).await);
+}
+
+
+#[tokio::test]
+async fn deferred_bids_credit_refund_accounts() {
+    let harness = Harness::start().await;
+    let first = harness.user(3).await;
+    let second = harness.user(3).await;
+    let listing = list(&harness, |args| args).await;
+    let creator = listing.creator.pubkey();
+    let deferred_bid = |bidder: &Keypair, leader: &Pubkey, price| {
+        instruction::deferred_bid(&bidder.pubkey(), &creator, &listing.mint, 0, leader, price)
+    };
+
+    harness.send(&[deferred_bid(&first, &creator, PRICE)], &[&first]).await.unwrap();
+
+    let result = harness.send(&[deferred_bid(&second, &creator, 2 * PRICE)], &[&second]).await;
+    assert_error(result, MarketError::InvalidReceiver);
+
+    let first_balance = harness.balance(&first.pubkey()).await;
+    harness.send(&[deferred_bid(&second, &first.pubkey(), 2 * PRICE)], &[&second]).await.unwrap();
+    assert_eq!(harness.balance(&first.pubkey()).await, first_balance);
+
+    let refund_key = pda::bid_refund(&listing.auction, &first.pubkey());
+    let refund: BidRefund = harness.fetch(&refund_key).await;
+    assert_eq!(refund.amount, PRICE);
+
+    let refund_lamports = harness.balance(&refund_key).await;
+    harness.send(&[instruction::claim_refund(&first.pubkey(), &listing.auction)], &[&first]).await.unwrap();
+    assert_eq!(harness.balance(&first.pubkey()).await, first_balance + refund_lamports);
+    assert!(!harness.exists(&refund_key).await);
+}
+
+
+#[tokio::test]
+async fn deferred_bids_refund_leaders_without_a_refund_account() {
+    let harness = Harness::start().await;
+    let first = harness.user(5).await;
+    let second = harness.user(3).await;
+    let third = harness.user(4).await;
+    let listing = list(&harness, |args| args).await;
+    let creator = listing.creator.pubkey();
+    let deferred_bid = |bidder: &Keypair, leader: &Pubkey, price| {
+        instruction::deferred_bid(&bidder.pubkey(), &creator, &listing.mint, 0, leader, price)
+    };
+
+    // A leader who bid with `bid` is paid back to their wallet.
+    harness.send(&[listing.bid(&first, &creator, PRICE)], &[&first]).await.unwrap();
+    let first_balance = harness.balance(&first.pubkey()).await;
+    harness.send(&[deferred_bid(&second, &first.pubkey(), 2 * PRICE)], &[&second]).await.unwrap();
+    assert_eq!(harness.balance(&first.pubkey()).await, first_balance + PRICE);
+    assert!(!harness.exists(&pda::bid_refund(&listing.auction, &first.pubkey())).await);
+
+    // So is a leader who closed their refund account while leading.
+    harness.send(&[instruction::claim_refund(&second.pubkey(), &listing.auction)], &[&second]).await.unwrap();
+    let second_balance = harness.balance(&second.pubkey()).await;
+    harness.send(&[deferred_bid(&third, &second.pubkey(), 3 * PRICE)], &[&third]).await.unwrap();
+    assert_eq!(harness.balance(&second.pubkey()).await, second_balance + 2 * PRICE);
+
+    // A plain bid over a deferred bidder pays them back directly too.
+    let third_balance = harness.balance(&third.pubkey()).await;
+    harness.send(&[listing.bid(&first, &third.pubkey(), 4 * PRICE)], &[&first]).await.unwrap();
+    assert_eq!(harness.balance(&third.pubkey()).await, third_balance + 3 * PRICE);
+    let refund: BidRefund = harness.fetch(&pda::bid_refund(&listing.auction, &third.pubkey())).await;
+    assert_eq!(refund.amount, 0);
+
+    let vault: BidVault = harness.fetch(&pda::bid_vault(&listing.auction)).await;
+    assert_eq!(vault.amount, 4 * PRICE);
+}
+
+
+#[tokio::test]
+async fn buy_now_ends_the_auction_at_its_price() {
+    let harness = Harness::start().await;
+    let bidder = harness.user(3).await;
+    let buyer = harness.user(6).await;
+    let listing = list(&harness, |args| args::CreateAuction { buy_now_price: Some(5 * PRICE), ..args }).await;
+    let creator = listing.creator.pubkey();
+    harness.create_token_account(&buyer.pubkey(), &listing.mint).await;
+    let buy_now = |leader: &Pubkey| {
+        instruction::buy_now(&buyer.pubkey(), &creator, &listing.mint, 0, leader, &harness.treasury, &[listing.artist.pubkey()])
+    };
+
+    harness.send(&[listing.bid(&bidder, &creator, PRICE)], &[&bidder]).await.unwrap();
+
+    let bidder_balance = harness.balance(&bidder.pubkey()).await;
+    let buyer_balance = harness.balance(&buyer.pubkey()).await;
+    let artist_balance = harness.balance(&listing.artist.pubkey()).await;
+    harness.send(&[buy_now(&bidder.pubkey())], &[&buyer]).await.unwrap();
+
+    assert_eq!(harness.balance(&bidder.pubkey()).await, bidder_balance + PRICE);
+    assert_eq!(harness.balance(&buyer.pubkey()).await, buyer_balance - 5 * PRICE);
+    assert_eq!(harness.balance(&listing.artist.pubkey()).await, artist_balance + royalty(5 * PRICE));
+    assert_eq!(harness.token_balance(&pda::token_account(&buyer.pubkey(), &listing.mint)).await, 1);
+    assert!(!harness.exists(&listing.auction).await);
+}
+
+
+#[tokio::test]
+async fn buy_now_is_only_available_while_bidding_is_below_it() {
+    let harness = Harness::start().await;
+    let bidder = harness.user(6).await;
+    let buyer = harness.user(6).await;
+
+    let listing = list(&harness, |args| args).await;
+    let creator = listing.creator.pubkey();
+    harness.create_token_account(&buyer.pubkey(), &listing.mint).await;
+    let result = harness
+        .send(&[instruction::buy_now(&buyer.pubkey(), &creator, &listing.mint, 0, &creator, &harness.treasury, &[listing.artist.pubkey()])], &[&buyer])
+        .await;
+    assert_error(result, MarketError::BuyNowUnavailable);
+
+    let listing = list(&harness, |args| args::CreateAuction { buy_now_price: Some(2 * PRICE), ..args }).await;
+    let creator = listing.creator.pubkey();
+    harness.create_token_account(&buyer.pubkey(), &listing.mint).await;
+    let buy_now = |leader: &Pubkey| {
+        instruction::buy_now(&buyer.pubkey(), &creator, &listing.mint, 0, leader, &harness.treasury, &[listing.artist.pubkey()])
+    };
+
+    harness.send(&[listing.bid(&bidder, &creator, 2 * PRICE)], &[&bidder]).await.unwrap();
+    let result = harness.send(&[buy_now(&bidder.pubkey())], &[&buyer]).await;
+    assert_error(result, MarketError::BuyNowUnavailable);
+
+    harness.warp_to(listing.end_time + 1).await;
+    let result = harness.send(&[buy_now(&bidder.pubkey())], &[&buyer]).await;
+    assert_error(result, MarketError::AuctionEnded);
+
+    let listing = list(&harness, |args| args::CreateAuction {
+        start_time: args.start_time + 3600,
+        buy_now_price: Some(2 * PRICE),
+        ..args
+    })
+    .await;
+    let creator = listing.creator.pubkey();
+    harness.create_token_account(&buyer.pubkey(), &listing.mint).await;
+    let result = harness
+        .send(&[instruction::buy_now(&buyer.pubkey(), &creator, &listing.mint, 0, &creator, &harness.treasury, &[listing.artist.pubkey()])], &[&buyer])
+        .await;
+    assert_error(result, MarketError::AuctionNotStarted);
+}
+
+
+#[tokio::test]
+async fn settlers_earn_the_crank_fee() {
+    let harness = Harness::start().await;
+    harness
+        .send(&[instruction::set_crank_fee(&harness.admin.pubkey(), 5000)], &[&harness.admin])
+        .await
+        .unwrap();
+
+    let treasury_balance = harness.balance(&harness.treasury).await;
+    let listing = list(&harness, |args| args).await;
+    let crank_fee = fee(PRICE) / 2;
+    assert_eq!(harness.balance(&harness.treasury).await, treasury_balance + fee(PRICE) - crank_fee);
+
+    let auction: Auction = harness.fetch(&listing.auction).await;
+    assert_eq!(auction.crank_fee, crank_fee);
+
+    let bidder = harness.user(3).await;
+    let settler = harness.user(1).await;
+    harness.send(&[listing.bid(&bidder, &listing.creator.pubkey(), PRICE)], &[&bidder]).await.unwrap();
+    harness.warp_to(listing.end_time + 1).await;
+
+    // The settler also pays for the winner's token account.
+    let settler_balance = harness.balance(&settler.pubkey()).await;
+    harness.send(&[listing.resolve(&settler, &bidder.pubkey())], &[&settler]).await.unwrap();
+    let token_account_rent = harness.rent().await.minimum_balance(anchor_spl::token::TokenAccount::LEN);
+    assert_eq!(harness.balance(&settler.pubkey()).await + token_account_rent, settler_balance + crank_fee);
+    assert_eq!(harness.token_balance(&pda::token_account(&bidder.pubkey(), &listing.mint)).await, 1);
+}
+
+
+#[tokio::test]
+async fn settlers_pay_no_rent_when_the_auction_does_not_sell() {
+    let harness = Harness::start().await;
+    harness
+        .send(&[instruction::set_crank_fee(&harness.admin.pubkey(), 5000)], &[&harness.admin])
+        .await
+        .unwrap();
+
+    let listing = list(&harness, |args| args::CreateAuction { reserve_price: 2 * PRICE, ..args }).await;
+    let bidder = harness.user(3).await;
+    let settler = harness.user(1).await;
+    harness.send(&[listing.bid(&bidder, &listing.creator.pubkey(), PRICE)], &[&bidder]).await.unwrap();
+    harness.warp_to(listing.end_time + 1).await;
+
+    let settler_balance = harness.balance(&settler.pubkey()).await;
+    harness.send(&[listing.resolve(&settler, &bidder.pubkey())], &[&settler]).await.unwrap();
+    assert_eq!(harness.balance(&settler.pubkey()).await, settler_balance + fee(PRICE) / 2);
+    assert!(!harness.exists(&pda::token_account(&bidder.pubkey(), &listing.mint)).await);
+    assert_eq!(harness.token_balance(&pda::token_account(&listing.creator.pubkey(), &listing.mint)).await, 1);
+}
+
+
+#[tokio::test]
+async fn runs_a_token_auction() {
+    let harness = Harness::start().await;
+    let artist = harness.user(1).await;
+    let creator = harness.user(1).await;
+    let first = harness.user(1).await;
+    let second = harness.user(1).await;
+    let settler = harness.user(1).await;
+    let mint = harness.mint_nft(&artist, ROYALTY_BASIS_POINTS).await;
+    harness.transfer_nft(&artist, &mint, &creator.pubkey()).await;
+    harness
+        .send(&[instruction::set_crank_fee(&harness.admin.pubkey(), 5000)], &[&harness.admin])
+        .await
+        .unwrap();
+
+    let payment_mint = harness.create_payment_mint().await;
+    let creator_payment = harness.fund_tokens(&creator.pubkey(), &payment_mint, fee(TOKEN_PRICE)).await;
+    let treasury_payment = harness.fund_tokens(&harness.treasury, &payment_mint, 0).await;
+    let artist_payment = harness.fund_tokens(&artist.pubkey(), &payment_mint, 0).await;
+    let settler_payment = harness.fund_tokens(&settler.pubkey(), &payment_mint, 0).await;
+    let first_payment = harness.fund_tokens(&first.pubkey(), &payment_mint, 3 * TOKEN_PRICE).await;
+    let second_payment = harness.fund_tokens(&second.pubkey(), &payment_mint, 3 * TOKEN_PRICE).await;
+
+    let now = harness.now().await;
+    let end_time = now + DURATION;
+    harness
+        .send(
+            &[instruction::create_token_auction(
+                &creator.pubkey(),
+                &mint,
+                &payment_mint,
+                &harness.treasury,
+                token_auction_args(TOKEN_PRICE, now - 60, end_time, 0),
+            )],
+            &[&creator],
+        )
+        .await
+        .unwrap();
+    let crank_fee = fee(TOKEN_PRICE) / 2;
+    assert_eq!(harness.token_balance(&treasury_payment).await, fee(TOKEN_PRICE) - crank_fee);
+
+    let auction_key = pda::auction(&mint, &creator.pubkey(), 0);
+    let token_bid = |bidder: &Keypair, leader: &Pubkey, price| {
+        instruction::token_bid(&bidder.pubkey(), &creator.pubkey(), &mint, &payment_mint, 0, leader, price)
+    };
+
+    // Bids are only taken in the auction's payment mint.
+    let other_mint = harness.create_payment_mint().await;
+    let other_payment = harness.fund_tokens(&first.pubkey(), &other_mint, TOKEN_PRICE).await;
+    let result = harness
+        .send(&[replace_account(token_bid(&first, &creator.pubkey(), TOKEN_PRICE), &first_payment, other_payment)], &[&first])
+        .await;
+    assert_error(result, MarketError::InvalidPaymentMint);
+
+    harness.send(&[token_bid(&first, &creator.pubkey(), TOKEN_PRICE)], &[&first]).await.unwrap();
+    assert_eq!(harness.token_balance(&first_payment).await, 2 * TOKEN_PRICE);
+
+    let result = harness.send(&[token_bid(&second, &second.pubkey(), 2 * TOKEN_PRICE)], &[&second]).await;
+    assert_error(result, MarketError::InvalidReceiver);
+
+    harness.send(&[token_bid(&second, &first.pubkey(), 2 * TOKEN_PRICE)], &[&second]).await.unwrap();
+    assert_eq!(harness.token_balance(&first_payment).await, 3 * TOKEN_PRICE);
+
+    let result = harness
+        .send(
+            &[instruction::cancel_token_auction(&creator.pubkey(), &mint, &payment_mint, 0, &first.pubkey(), &harness.treasury)],
+            &[&creator],
+        )
+        .await;
+    assert_error(result, MarketError::InvalidReceiver);
+
+    let resolve = || {
+        instruction::resolve_token_auction(
+            &settler.pubkey(),
+            &creator.pubkey(),
+            &mint,
+            &payment_mint,
+            0,
+            &second.pubkey(),
+            &harness.treasury,
+            &[artist.pubkey()],
+        )
+    };
+    let result = harness.send(&[resolve()], &[&settler]).await;
+    assert_error(result, MarketError::AuctionNotEnded);
+
+    harness.warp_to(end_time + 1).await;
+    let result = harness.send(&[token_bid(&first, &second.pubkey(), 3 * TOKEN_PRICE)], &[&first]).await;
+    assert_error(result, MarketError::AuctionEnded);
+
+    // Anyone can settle for the crank fee, paying for the winner's token account.
+    harness.send(&[resolve()], &[&settler]).await.unwrap();
+    assert_eq!(harness.token_balance(&settler_payment).await, crank_fee);
+    assert_eq!(harness.token_balance(&second_payment).await, TOKEN_PRICE);
+    assert_eq!(harness.token_balance(&artist_payment).await, royalty(2 * TOKEN_PRICE));
+    assert_eq!(harness.token_balance(&creator_payment).await, 2 * TOKEN_PRICE - royalty(2 * TOKEN_PRICE));
+    assert_eq!(harness.token_balance(&pda::token_account(&second.pubkey(), &mint)).await, 1);
+    assert!(!harness.exists(&auction_key).await);
+}
+
+
+#[tokio::test]
+async fn cancels_token_auctions_before_and_after_they_start() {
+    let harness = Harness::start().await;
+    let creator = harness.user(1).await;
+    let bidder = harness.user(1).await;
+    let mint = harness.mint_nft(&creator, ROYALTY_BASIS_POINTS).await;
+    let payment_mint = harness.create_payment_mint().await;
+    let creator_payment = harness.fund_tokens(&creator.pubkey(), &payment_mint, 2 * fee(TOKEN_PRICE)).await;
+    let treasury_payment = harness.fund_tokens(&harness.treasury, &payment_mint, 0).await;
+    let bidder_payment = harness.fund_tokens(&bidder.pubkey(), &payment_mint, TOKEN_PRICE).await;
+
+    let now = harness.now().await;
+    let create = |args| instruction::create_token_auction(&creator.pubkey(), &mint, &payment_mint, &harness.treasury, args);
+
+    let result = harness.send(&[create(token_auction_args(TOKEN_PRICE, now - 120, now - 60, 0))], &[&creator]).await;
+    assert_error(result, MarketError::InvalidEndTIme);
+    let result = harness.send(&[create(token_auction_args(TOKEN_PRICE, now + 60, now + 30, 0))], &[&creator]).await;
+    assert_error(result, MarketError::InvalidStartTime);
+    let args = args::CreateTokenAuction { extension_duration: DURATION as u64 + 1, ..token_auction_args(TOKEN_PRICE, now, now + DURATION, 0) };
+    let result = harness.send(&[create(args)], &[&creator]).await;
+    assert_error(result, MarketError::InvalidExtension);
+
+    let cancel = |leader: &Pubkey| {
+        instruction::cancel_token_auction(&creator.pubkey(), &mint, &payment_mint, 0, leader, &harness.treasury)
+    };
+
+    // Before it starts the listing fee sits in the auction's escrow and comes back on cancel.
+    harness.send(&[create(token_auction_args(TOKEN_PRICE, now + 60, now + DURATION, 0))], &[&creator]).await.unwrap();
+    assert_eq!(harness.token_balance(&treasury_payment).await, 0);
+    harness.send(&[cancel(&creator.pubkey())], &[&creator]).await.unwrap();
+
+    assert_eq!(harness.token_balance(&creator_payment).await, 2 * fee(TOKEN_PRICE));
+    assert_eq!(harness.token_balance(&pda::token_account(&creator.pubkey(), &mint)).await, 1);
+    assert!(!harness.exists(&pda::auction(&mint, &creator.pubkey(), 0)).await);
+
+    // After it starts the leader is refunded and the treasury keeps the fee.
+    harness.send(&[create(token_auction_args(TOKEN_PRICE, now + 60, now + DURATION, 0))], &[&creator]).await.unwrap();
+    harness.warp_to(now + 60).await;
+    harness
+        .send(
+            &[instruction::token_bid(&bidder.pubkey(), &creator.pubkey(), &mint, &payment_mint, 0, &creator.pubkey(), TOKEN_PRICE)],
+            &[&bidder],
+        )
+        .await
+        .unwrap();
+    harness.send(&[cancel(&bidder.pubkey())], &[&creator]).await.unwrap();
+
+    assert_eq!(harness.token_balance(&bidder_payment).await, TOKEN_PRICE);
+    assert_eq!(harness.token_balance(&treasury_payment).await, fee(TOKEN_PRICE));
+    assert_eq!(harness.token_balance(&creator_payment).await, fee(TOKEN_PRICE));
+    assert_eq!(harness.token_balance(&pda::token_account(&creator.pubkey(), &mint)).await, 1);
+    assert!(!harness.exists(&pda::auction(&mint, &creator.pubkey(), 0)).await);
+}
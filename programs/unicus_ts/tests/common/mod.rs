@@ -0,0 +1,452 @@
+//
+// Harness shared by the program tests.
+//
+// The program is loaded from `anchor build`'s output (or `BPF_OUT_DIR`) under the
+// upgradeable loader with `admin` as its upgrade authority, so the marketplace is
+// initialized the same way as on chain. Token Metadata is loaded from
+// `tests/fixtures/mpl_token_metadata.so`, a build of the mpl-token-metadata crate this
+// program links against. It is not checked in; the first harness to start runs
+// `tests/fixtures/build-token-metadata.sh` to build it when it is missing.
+//
+// The clock never moves on its own; tests warp it to step through auction phases.
+//
+
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Once;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AccountDeserialize;
+use anchor_spl::token::spl_token;
+use mpl_token_metadata::state::{Creator, Metadata, TokenMetadataAccount};
+use solana_program_test::{find_file, read_file, BanksClient, ProgramTest, ProgramTestBanksClientExt, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use solana_sdk::clock::Clock;
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::rent::Rent;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::system_program;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use unicus_client::{args, instruction, pda, CreatorShare};
+
+pub const FEE_BASIS_POINTS: u16 = 200;
+pub const MAX_ROYALTY_BASIS_POINTS: u16 = 1000;
+pub const ROYALTY_BASIS_POINTS: u16 = 500;
+
+
+pub struct Harness {
+    pub context: ProgramTestContext,
+    pub admin: Keypair,
+    pub treasury: Pubkey,
+    sent: RefCell<HashSet<Signature>>,
+}
+
+
+impl Harness {
+    // A chain with the program deployed and no marketplace yet.
+    pub async fn new() -> Self {
+        let admin = Keypair::new();
+        let treasury = Keypair::new().pubkey();
+
+        let mut program_test = ProgramTest::default();
+        build_token_metadata();
+        program_test.add_program("mpl_token_metadata", mpl_token_metadata::ID, None);
+        add_upgradeable_program(&mut program_test, &admin.pubkey());
+        for wallet in [admin.pubkey(), treasury] {
+            program_test.add_account(wallet, Account::new(10 * LAMPORTS_PER_SOL, 0, &system_program::ID));
+        }
+
+        Harness {
+            context: program_test.start_with_context().await,
+            admin,
+            treasury,
+            sent: RefCell::default(),
+        }
+    }
+
+    // A chain with the marketplace initialized by `admin`.
+    pub async fn start() -> Self {
+        let harness = Harness::new().await;
+        harness
+            .send(
+                &[instruction::initialize_marketplace(
+                    &harness.admin.pubkey(),
+                    harness.treasury,
+                    FEE_BASIS_POINTS,
+                    MAX_ROYALTY_BASIS_POINTS,
+                )],
+                &[&harness.admin],
+            )
+            .await
+            .unwrap();
+        harness
+    }
+
+    fn banks_client(&self) -> BanksClient {
+        self.context.banks_client.clone()
+    }
+
+    // Sends `instructions` in one transaction paid for by the harness' payer. Retrying a
+    // transaction waits for a new blockhash, since the bank rejects exact duplicates.
+    pub async fn send(&self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<(), TransactionError> {
+        let mut banks_client = self.banks_client();
+        let payer = &self.context.payer;
+        let mut all_signers = vec![payer];
+        all_signers.extend_from_slice(signers);
+
+        let mut blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let mut transaction = Transaction::new_signed_with_payer(instructions, Some(&payer.pubkey()), &all_signers, blockhash);
+        while !self.sent.borrow_mut().insert(transaction.signatures[0]) {
+            blockhash = banks_client.get_new_latest_blockhash(&blockhash).await.unwrap();
+            transaction = Transaction::new_signed_with_payer(instructions, Some(&payer.pubkey()), &all_signers, blockhash);
+        }
+        banks_client.process_transaction(transaction).await.map_err(|err| err.unwrap())
+    }
+
+    // A new wallet funded with `sol` from the payer.
+    pub async fn user(&self, sol: u64) -> Keypair {
+        let user = Keypair::new();
+        self.send(
+            &[system_instruction::transfer(&self.context.payer.pubkey(), &user.pubkey(), sol * LAMPORTS_PER_SOL)],
+            &[],
+        )
+        .await
+        .unwrap();
+        user
+    }
+
+    pub async fn now(&self) -> i64 {
+        self.banks_client().get_sysvar::<Clock>().await.unwrap().unix_timestamp
+    }
+
+    pub async fn warp_to(&self, unix_timestamp: i64) {
+        let mut clock: Clock = self.banks_client().get_sysvar().await.unwrap();
+        clock.unix_timestamp = unix_timestamp;
+        self.context.set_sysvar(&clock);
+    }
+
+    pub async fn rent(&self) -> Rent {
+        self.banks_client().get_rent().await.unwrap()
+    }
+
+    pub async fn balance(&self, address: &Pubkey) -> u64 {
+        self.banks_client().get_balance(*address).await.unwrap()
+    }
+
+    pub async fn exists(&self, address: &Pubkey) -> bool {
+        self.banks_client().get_account(*address).await.unwrap().is_some()
+    }
+
+    pub async fn fetch<T: AccountDeserialize>(&self, address: &Pubkey) -> T {
+        let account = self.banks_client().get_account(*address).await.unwrap().expect("account exists");
+        unicus_client::decode_account(&account.data).unwrap()
+    }
+
+    pub async fn metadata(&self, mint: &Pubkey) -> Metadata {
+        let account = self.banks_client().get_account(pda::metadata(mint)).await.unwrap().expect("metadata exists");
+        Metadata::safe_deserialize(&account.data).unwrap()
+    }
+
+    pub async fn token_balance(&self, token_account: &Pubkey) -> u64 {
+        let account = self.banks_client().get_account(*token_account).await.unwrap().expect("token account exists");
+        spl_token::state::Account::unpack(&account.data).unwrap().amount
+    }
+
+    // A mint with `owner` as mint and freeze authority and an empty associated token
+    // account for `owner`, ready for `mint_nft`.
+    pub async fn create_mint(&self, owner: &Keypair) -> Pubkey {
+        let mint = Keypair::new();
+        let rent = self.rent().await;
+        self.send(
+            &instruction::create_mint(&owner.pubkey(), &mint.pubkey(), rent.minimum_balance(spl_token::state::Mint::LEN)),
+            &[owner, &mint],
+        )
+        .await
+        .unwrap();
+        mint.pubkey()
+    }
+
+    // Mints an nft to `owner`, who is its only creator.
+    pub async fn mint_nft(&self, owner: &Keypair, royalty: u16) -> Pubkey {
+        let mint = self.create_mint(owner).await;
+        self.send(&[mint_nft(&owner.pubkey(), &mint, royalty)], &[owner]).await.unwrap();
+        mint
+    }
+
+    // Mints an nft with mutable metadata straight through Token Metadata, standing in
+    // for one listed before royalties were basis points.
+    pub async fn mint_legacy_nft(&self, owner: &Keypair, royalty_percent: u16) -> Pubkey {
+        let mint = self.create_mint(owner).await;
+        self.send(
+            &[
+                mpl_token_metadata::instruction::create_metadata_accounts_v2(
+                    mpl_token_metadata::ID,
+                    pda::metadata(&mint),
+                    mint,
+                    owner.pubkey(),
+                    owner.pubkey(),
+                    owner.pubkey(),
+                    "Unicus".to_string(),
+                    "UNI".to_string(),
+                    "https://unicus.one/nft.json".to_string(),
+                    Some(vec![Creator { address: owner.pubkey(), verified: false, share: 100 }]),
+                    royalty_percent,
+                    true,
+                    true,
+                    None,
+                    None,
+                ),
+                spl_token::instruction::mint_to(
+                    &spl_token::ID,
+                    &mint,
+                    &pda::token_account(&owner.pubkey(), &mint),
+                    &owner.pubkey(),
+                    &[],
+                    1,
+                )
+                .unwrap(),
+            ],
+            &[owner],
+        )
+        .await
+        .unwrap();
+        mint
+    }
+
+    // Hands `owner`'s nft to `to`, whose token account is created for it.
+    pub async fn transfer_nft(&self, owner: &Keypair, mint: &Pubkey, to: &Pubkey) -> Pubkey {
+        let token_account = self.create_token_account(to, mint).await;
+        self.send(
+            &[spl_token::instruction::transfer(
+                &spl_token::ID,
+                &pda::token_account(&owner.pubkey(), mint),
+                &token_account,
+                &owner.pubkey(),
+                &[],
+                1,
+            )
+            .unwrap()],
+            &[owner],
+        )
+        .await
+        .unwrap();
+        token_account
+    }
+
+    // Creates `owner`'s associated token account for `mint`, paid for by the payer.
+    pub async fn create_token_account(&self, owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+        self.send(
+            &[spl_associated_token_account::instruction::create_associated_token_account(
+                &self.context.payer.pubkey(),
+                owner,
+                mint,
+            )],
+            &[],
+        )
+        .await
+        .unwrap();
+        pda::token_account(owner, mint)
+    }
+
+    // A fungible payment mint whose authority is the payer.
+    pub async fn create_payment_mint(&self) -> Pubkey {
+        let mint = Keypair::new();
+        let payer = self.context.payer.pubkey();
+        let rent = self.rent().await;
+        self.send(
+            &[
+                system_instruction::create_account(
+                    &payer,
+                    &mint.pubkey(),
+                    rent.minimum_balance(spl_token::state::Mint::LEN),
+                    spl_token::state::Mint::LEN as u64,
+                    &spl_token::ID,
+                ),
+                spl_token::instruction::initialize_mint(&spl_token::ID, &mint.pubkey(), &payer, None, 6).unwrap(),
+            ],
+            &[&mint],
+        )
+        .await
+        .unwrap();
+        mint.pubkey()
+    }
+
+    // Creates `owner`'s associated token account for `payment_mint` holding `amount`.
+    pub async fn fund_tokens(&self, owner: &Pubkey, payment_mint: &Pubkey, amount: u64) -> Pubkey {
+        let token_account = self.create_token_account(owner, payment_mint).await;
+        if amount > 0 {
+            self.send(
+                &[spl_token::instruction::mint_to(
+                    &spl_token::ID,
+                    payment_mint,
+                    &token_account,
+                    &self.context.payer.pubkey(),
+                    &[],
+                    amount,
+                )
+                .unwrap()],
+                &[],
+            )
+            .await
+            .unwrap();
+        }
+        token_account
+    }
+
+    // Freezes `token_account` of an nft minted by `owner`, so any transfer in or out of it fails.
+    pub async fn freeze(&self, owner: &Keypair, mint: &Pubkey, token_account: &Pubkey) {
+        self.send(
+            &[spl_token::instruction::freeze_account(&spl_token::ID, token_account, mint, &owner.pubkey(), &[]).unwrap()],
+            &[owner],
+        )
+        .await
+        .unwrap();
+    }
+}
+
+
+// Builds the Token Metadata fixture once per test binary if it is not already there.
+fn build_token_metadata() {
+    static BUILD: Once = Once::new();
+    BUILD.call_once(|| {
+        if find_file("mpl_token_metadata.so").is_some() {
+            return;
+        }
+        let script = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/build-token-metadata.sh");
+        let status = Command::new(script).status().unwrap_or_else(|err| panic!("running {}: {}", script, err));
+        assert!(status.success(), "{} failed", script);
+    });
+}
+
+
+// Deploys the program the way `anchor deploy` does, under the upgradeable loader, so
+// `initialize_marketplace` can check its upgrade authority.
+fn add_upgradeable_program(program_test: &mut ProgramTest, upgrade_authority: &Pubkey) {
+    let path = find_file("unicus_ts.so")
+        .unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../../target/deploy/unicus_ts.so")));
+    let rent = Rent::default();
+
+    let program_data = pda::program_data();
+    let program = bincode::serialize(&UpgradeableLoaderState::Program {
+        programdata_address: program_data,
+    })
+    .unwrap();
+    let mut data = bincode::serialize(&UpgradeableLoaderState::ProgramData {
+        slot: 0,
+        upgrade_authority_address: Some(*upgrade_authority),
+    })
+    .unwrap();
+    data.resize(UpgradeableLoaderState::programdata_data_offset().unwrap(), 0);
+    data.extend(read_file(path));
+
+    program_test.add_account(
+        unicus_ts::ID,
+        Account {
+            lamports: rent.minimum_balance(program.len()),
+            data: program,
+            owner: bpf_loader_upgradeable::ID,
+            executable: true,
+            rent_epoch: 0,
+        },
+    );
+    program_test.add_account(
+        program_data,
+        Account {
+            lamports: rent.minimum_balance(data.len()),
+            data,
+            owner: bpf_loader_upgradeable::ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+
+pub fn mint_nft(owner: &Pubkey, mint: &Pubkey, royalty: u16) -> Instruction {
+    instruction::mint_nft(
+        owner,
+        mint,
+        vec![CreatorShare { address: *owner, share: 100 }],
+        "Unicus".to_string(),
+        "UNI".to_string(),
+        "https://unicus.one/nft.json".to_string(),
+        royalty,
+    )
+}
+
+
+// An open auction without reserve, increments, extensions or buy-now price.
+pub fn auction_args(price: u64, start_time: i64, end_time: i64, listing_id: u64) -> args::CreateAuction {
+    args::CreateAuction {
+        memo: "This is test auction.".to_string(),
+        price,
+        start_time,
+        end_time,
+        listing_id,
+        min_increment_bps: 0,
+        min_increment: 0,
+        reserve_price: 0,
+        reserve_hash: None,
+        extension_window: 0,
+        extension_duration: 0,
+        max_extensions: 0,
+        buy_now_price: None,
+    }
+}
+
+
+pub fn token_auction_args(price: u64, start_time: i64, end_time: i64, listing_id: u64) -> args::CreateTokenAuction {
+    args::CreateTokenAuction {
+        memo: "This is test auction.".to_string(),
+        price,
+        start_time,
+        end_time,
+        listing_id,
+        min_increment_bps: 0,
+        min_increment: 0,
+        reserve_price: 0,
+        reserve_hash: None,
+        extension_window: 0,
+        extension_duration: 0,
+        max_extensions: 0,
+    }
+}
+
+
+pub fn fee(price: u64) -> u64 {
+    price * FEE_BASIS_POINTS as u64 / 10000
+}
+
+
+pub fn royalty(price: u64) -> u64 {
+    price * ROYALTY_BASIS_POINTS as u64 / 10000
+}
+
+
+// Swaps the account `from` for `to` in `instruction`, to pass an account the builder would not.
+pub fn replace_account(mut instruction: Instruction, from: &Pubkey, to: Pubkey) -> Instruction {
+    for account in instruction.accounts.iter_mut().filter(|account| account.pubkey == *from) {
+        account.pubkey = to;
+    }
+    instruction
+}
+
+
+// Asserts the transaction failed with the program error `error`, whichever instruction raised it.
+pub fn assert_error(result: Result<(), TransactionError>, error: impl Into<u32>) {
+    let code = error.into();
+    match result {
+        Err(TransactionError::InstructionError(_, InstructionError::Custom(raised))) => {
+            assert_eq!(raised, code, "expected error {}, got {}", code, raised)
+        }
+        other => panic!("expected error {}, got {:?}", code, other),
+    }
+}
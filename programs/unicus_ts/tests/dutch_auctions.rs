@@ -0,0 +1,190 @@
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use common::*;
+use solana_program_test::tokio;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::signature::{Keypair, Signer};
+use unicus_client::{instruction, pda, DutchAuction, MarketError};
+
+const START_PRICE: u64 = 2 * LAMPORTS_PER_SOL;
+const FLOOR_PRICE: u64 = LAMPORTS_PER_SOL;
+const DURATION: i64 = 1000;
+
+
+fn create_dutch_auction(creator: &Keypair, mint: &Pubkey, treasury: &Pubkey, start_time: i64, end_time: i64) -> Instruction {
+    instruction::create_dutch_auction(
+        &creator.pubkey(),
+        mint,
+        treasury,
+        "This is test auction.".to_string(),
+        START_PRICE,
+        FLOOR_PRICE,
+        start_time,
+        end_time,
+        0,
+    )
+}
+
+
+#[tokio::test]
+async fn sells_at_the_decayed_price() {
+    let harness = Harness::start().await;
+    let artist = harness.user(1).await;
+    let creator = harness.user(1).await;
+    let buyer = harness.user(3).await;
+    let mint = harness.mint_nft(&artist, ROYALTY_BASIS_POINTS).await;
+    harness.transfer_nft(&artist, &mint, &creator.pubkey()).await;
+    harness.create_token_account(&buyer.pubkey(), &mint).await;
+
+    let now = harness.now().await;
+    let treasury_balance = harness.balance(&harness.treasury).await;
+    harness
+        .send(&[create_dutch_auction(&creator, &mint, &harness.treasury, now, now + DURATION)], &[&creator])
+        .await
+        .unwrap();
+    assert_eq!(harness.balance(&harness.treasury).await, treasury_balance + fee(FLOOR_PRICE));
+
+    let dutch_auction_key = pda::dutch_auction(&mint, &creator.pubkey(), 0);
+    let dutch_auction: DutchAuction = harness.fetch(&dutch_auction_key).await;
+    assert_eq!(dutch_auction.current_price(now).unwrap(), START_PRICE);
+    assert_eq!(dutch_auction.current_price(now + DURATION / 2).unwrap(), (START_PRICE + FLOOR_PRICE) / 2);
+    assert_eq!(dutch_auction.current_price(now + 2 * DURATION).unwrap(), FLOOR_PRICE);
+
+    // Half way through, the price is half way down to the floor.
+    harness.warp_to(now + DURATION / 2).await;
+    let price = (START_PRICE + FLOOR_PRICE) / 2;
+    let buy_dutch = |max_price| instruction::buy_dutch(&buyer.pubkey(), &creator.pubkey(), &mint, 0, max_price, &[artist.pubkey()]);
+
+    let result = harness.send(&[buy_dutch(price - 1)], &[&buyer]).await;
+    assert_error(result, MarketError::InsufficientMoney);
+
+    let buyer_balance = harness.balance(&buyer.pubkey()).await;
+    let artist_balance = harness.balance(&artist.pubkey()).await;
+    harness.send(&[buy_dutch(START_PRICE)], &[&buyer]).await.unwrap();
+
+    assert_eq!(harness.balance(&buyer.pubkey()).await, buyer_balance - price);
+    assert_eq!(harness.balance(&artist.pubkey()).await, artist_balance + royalty(price));
+    assert_eq!(harness.token_balance(&pda::token_account(&buyer.pubkey(), &mint)).await, 1);
+    assert!(!harness.exists(&dutch_auction_key).await);
+}
+
+
+#[tokio::test]
+async fn holds_the_floor_price_after_the_end() {
+    let harness = Harness::start().await;
+    let creator = harness.user(1).await;
+    let buyer = harness.user(3).await;
+    let mint = harness.mint_nft(&creator, ROYALTY_BASIS_POINTS).await;
+    harness.create_token_account(&buyer.pubkey(), &mint).await;
+
+    let now = harness.now().await;
+    harness
+        .send(&[create_dutch_auction(&creator, &mint, &harness.treasury, now, now + DURATION)], &[&creator])
+        .await
+        .unwrap();
+
+    harness.warp_to(now + 2 * DURATION).await;
+    let buy_dutch = |max_price| instruction::buy_dutch(&buyer.pubkey(), &creator.pubkey(), &mint, 0, max_price, &[creator.pubkey()]);
+
+    let result = harness.send(&[buy_dutch(FLOOR_PRICE - 1)], &[&buyer]).await;
+    assert_error(result, MarketError::InsufficientMoney);
+
+    let buyer_balance = harness.balance(&buyer.pubkey()).await;
+    harness.send(&[buy_dutch(FLOOR_PRICE)], &[&buyer]).await.unwrap();
+    assert_eq!(harness.balance(&buyer.pubkey()).await, buyer_balance - FLOOR_PRICE);
+    assert_eq!(harness.token_balance(&pda::token_account(&buyer.pubkey(), &mint)).await, 1);
+}
+
+
+#[tokio::test]
+async fn create_dutch_auction_checks_its_schedule_and_prices() {
+    let harness = Harness::start().await;
+    let creator = harness.user(1).await;
+    let mint = harness.mint_nft(&creator, ROYALTY_BASIS_POINTS).await;
+    let now = harness.now().await;
+
+    let result = harness
+        .send(&[create_dutch_auction(&creator, &mint, &harness.treasury, now - 120, now - 60)], &[&creator])
+        .await;
+    assert_error(result, MarketError::InvalidEndTIme);
+
+    let result = harness
+        .send(&[create_dutch_auction(&creator, &mint, &harness.treasury, now + 60, now + 60)], &[&creator])
+        .await;
+    assert_error(result, MarketError::InvalidStartTime);
+
+    let result = harness
+        .send(
+            &[instruction::create_dutch_auction(
+                &creator.pubkey(),
+                &mint,
+                &harness.treasury,
+                "This is test auction.".to_string(),
+                FLOOR_PRICE,
+                START_PRICE,
+                now,
+                now + DURATION,
+                0,
+            )],
+            &[&creator],
+        )
+        .await;
+    assert_error(result, MarketError::InvalidFloorPrice);
+
+    let result = harness
+        .send(&[create_dutch_auction(&creator, &mint, &creator.pubkey(), now, now + DURATION)], &[&creator])
+        .await;
+    assert_error(result, MarketError::InvalidTreasury);
+}
+
+
+#[tokio::test]
+async fn pending_dutch_auctions_take_no_buyers_and_cancel() {
+    let harness = Harness::start().await;
+    let creator = harness.user(1).await;
+    let buyer = harness.user(3).await;
+    let mint = harness.mint_nft(&creator, ROYALTY_BASIS_POINTS).await;
+    harness.create_token_account(&buyer.pubkey(), &mint).await;
+
+    let now = harness.now().await;
+    harness
+        .send(&[create_dutch_auction(&creator, &mint, &harness.treasury, now + 60, now + DURATION)], &[&creator])
+        .await
+        .unwrap();
+
+    let result = harness
+        .send(
+            &[instruction::buy_dutch(&buyer.pubkey(), &creator.pubkey(), &mint, 0, START_PRICE, &[creator.pubkey()])],
+            &[&buyer],
+        )
+        .await;
+    assert_error(result, MarketError::AuctionNotStarted);
+
+    harness
+        .send(&[instruction::cancel_dutch_auction(&creator.pubkey(), &mint, 0)], &[&creator])
+        .await
+        .unwrap();
+    assert_eq!(harness.token_balance(&pda::token_account(&creator.pubkey(), &mint)).await, 1);
+    assert!(!harness.exists(&pda::dutch_auction(&mint, &creator.pubkey(), 0)).await);
+}
+
+
+#[test]
+fn current_price_refuses_a_floor_above_the_start_price() {
+    let dutch_auction = DutchAuction {
+        creator: Pubkey::new_unique(),
+        mint_key: Pubkey::new_unique(),
+        listing_id: 0,
+        memo: String::new(),
+        start_price: FLOOR_PRICE,
+        floor_price: START_PRICE,
+        start_time: 0,
+        end_time: DURATION,
+        bump: 0,
+    };
+    assert_eq!(dutch_auction.current_price(-1).unwrap(), FLOOR_PRICE);
+    assert_eq!(dutch_auction.current_price(DURATION).unwrap(), START_PRICE);
+    assert!(dutch_auction.current_price(DURATION / 2).is_err());
+}
@@ -0,0 +1,32 @@
+#!/usr/bin/env bash
+#
+# Builds mpl_token_metadata.so next to this script from the mpl-token-metadata crate
+# unicus_ts links against, so the tests run the same Token Metadata version the program
+# was written for. Mainnet's current build has dropped instructions the program uses.
+#
+# The program tests run this themselves when the .so is missing. Needs `cargo build-bpf`.
+#
+
+set -euo pipefail
+
+fixtures="$(cd "$(dirname "$0")" && pwd)"
+program="$(dirname "$(dirname "$fixtures")")"
+
+manifest="$(
+    cargo metadata --format-version 1 --manifest-path "$program/Cargo.toml" \
+        | grep -o '"manifest_path":"[^"]*/mpl-token-metadata-[0-9][^"/]*/Cargo.toml"' \
+        | head -n 1 \
+        | cut -d '"' -f 4
+)"
+if [ -z "$manifest" ]; then
+    echo "mpl-token-metadata is not among unicus_ts' dependencies" >&2
+    exit 1
+fi
+
+# Build a copy so the registry's sources stay untouched.
+build="$(mktemp -d)"
+trap 'rm -rf "$build"' EXIT
+cp -r "$(dirname "$manifest")/." "$build"
+
+echo "building $(basename "$(dirname "$manifest")") into $fixtures" >&2
+cargo build-bpf --manifest-path "$build/Cargo.toml" --bpf-out-dir "$fixtures"
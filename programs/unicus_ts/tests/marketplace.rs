@@ -0,0 +1,281 @@
+mod common;
+
+use common::*;
+use mpl_token_metadata::state::Creator;
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use unicus_client::{
+    instruction, pda, CreatorShare, MarketError, Marketplace, MintError, RoyaltyVersion, ROYALTY_VERSION_BASIS_POINTS,
+};
+
+
+#[tokio::test]
+async fn only_the_upgrade_authority_initializes_the_marketplace() {
+    let harness = Harness::new().await;
+    let impostor = harness.user(1).await;
+    let treasury = Keypair::new().pubkey();
+
+    let result = harness
+        .send(&[instruction::initialize_marketplace(&impostor.pubkey(), treasury, 200, 1000)], &[&impostor])
+        .await;
+    assert_error(result, MarketError::Unauthorized);
+
+    let admin = &harness.admin;
+    let result = harness
+        .send(&[instruction::initialize_marketplace(&admin.pubkey(), treasury, 10001, 1000)], &[admin])
+        .await;
+    assert_error(result, MarketError::InvalidFee);
+
+    let result = harness
+        .send(&[instruction::initialize_marketplace(&admin.pubkey(), treasury, 200, 10001)], &[admin])
+        .await;
+    assert_error(result, MintError::RoyaltyExceeded);
+
+    harness
+        .send(&[instruction::initialize_marketplace(&admin.pubkey(), treasury, 200, 1000)], &[admin])
+        .await
+        .unwrap();
+
+    let marketplace: Marketplace = harness.fetch(&pda::marketplace()).await;
+    assert_eq!(marketplace.admin, admin.pubkey());
+    assert_eq!(marketplace.treasury, treasury);
+    assert_eq!(marketplace.fee_basis_points, 200);
+    assert_eq!(marketplace.max_royalty_basis_points, 1000);
+    assert_eq!(marketplace.crank_fee_basis_points, 0);
+}
+
+
+#[tokio::test]
+async fn admin_updates_the_marketplace() {
+    let harness = Harness::start().await;
+    let admin = &harness.admin;
+    let treasury = Keypair::new().pubkey();
+
+    harness
+        .send(
+            &[
+                instruction::set_treasury(&admin.pubkey(), treasury),
+                instruction::set_fee(&admin.pubkey(), 300),
+                instruction::set_crank_fee(&admin.pubkey(), 5000),
+                instruction::set_max_royalty(&admin.pubkey(), 2000),
+            ],
+            &[admin],
+        )
+        .await
+        .unwrap();
+
+    let marketplace: Marketplace = harness.fetch(&pda::marketplace()).await;
+    assert_eq!(marketplace.treasury, treasury);
+    assert_eq!(marketplace.fee_basis_points, 300);
+    assert_eq!(marketplace.crank_fee_basis_points, 5000);
+    assert_eq!(marketplace.max_royalty_basis_points, 2000);
+
+    let result = harness.send(&[instruction::set_fee(&admin.pubkey(), 10001)], &[admin]).await;
+    assert_error(result, MarketError::InvalidFee);
+
+    let result = harness.send(&[instruction::set_crank_fee(&admin.pubkey(), 10001)], &[admin]).await;
+    assert_error(result, MarketError::InvalidFee);
+
+    let result = harness.send(&[instruction::set_max_royalty(&admin.pubkey(), 10001)], &[admin]).await;
+    assert_error(result, MintError::RoyaltyExceeded);
+}
+
+
+#[tokio::test]
+async fn only_the_admin_updates_the_marketplace() {
+    let harness = Harness::start().await;
+    let user = harness.user(1).await;
+
+    for update in [
+        instruction::set_treasury(&user.pubkey(), user.pubkey()),
+        instruction::set_fee(&user.pubkey(), 0),
+        instruction::set_crank_fee(&user.pubkey(), 0),
+        instruction::set_max_royalty(&user.pubkey(), 0),
+    ] {
+        let result = harness.send(&[update], &[&user]).await;
+        assert_error(result, MarketError::Unauthorized);
+    }
+
+    let marketplace: Marketplace = harness.fetch(&pda::marketplace()).await;
+    assert_eq!(marketplace.treasury, harness.treasury);
+    assert_eq!(marketplace.fee_basis_points, FEE_BASIS_POINTS);
+}
+
+
+#[tokio::test]
+async fn mints_an_nft_with_metadata() {
+    let harness = Harness::start().await;
+    let user = harness.user(1).await;
+    let other = harness.user(1).await;
+
+    let mint = harness.create_mint(&user).await;
+    harness
+        .send(
+            &[instruction::mint_nft(
+                &user.pubkey(),
+                &mint,
+                vec![
+                    CreatorShare { address: user.pubkey(), share: 60 },
+                    CreatorShare { address: other.pubkey(), share: 40 },
+                ],
+                "Unicus".to_string(),
+                "UNI".to_string(),
+                "https://unicus.one/nft.json".to_string(),
+                ROYALTY_BASIS_POINTS,
+            )],
+            &[&user],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(harness.token_balance(&pda::token_account(&user.pubkey(), &mint)).await, 1);
+
+    let metadata = harness.metadata(&mint).await;
+    assert_eq!(metadata.mint, mint);
+    assert_eq!(metadata.update_authority, user.pubkey());
+    assert_eq!(metadata.data.seller_fee_basis_points, ROYALTY_BASIS_POINTS);
+    assert_eq!(
+        metadata.data.creators,
+        Some(vec![
+            Creator { address: user.pubkey(), verified: false, share: 60 },
+            Creator { address: other.pubkey(), verified: false, share: 40 },
+        ])
+    );
+}
+
+
+#[tokio::test]
+async fn mint_nft_rejects_invalid_royalties_and_creators() {
+    let harness = Harness::start().await;
+    let user = harness.user(1).await;
+    let mint = harness.create_mint(&user).await;
+
+    let result = harness
+        .send(&[mint_nft(&user.pubkey(), &mint, MAX_ROYALTY_BASIS_POINTS + 1)], &[&user])
+        .await;
+    assert_error(result, MintError::RoyaltyExceeded);
+
+    for creator_shares in [
+        vec![],
+        vec![CreatorShare { address: user.pubkey(), share: 99 }],
+        (0..6).map(|_| CreatorShare { address: Keypair::new().pubkey(), share: 20 }).collect(),
+    ] {
+        let result = harness
+            .send(
+                &[instruction::mint_nft(
+                    &user.pubkey(),
+                    &mint,
+                    creator_shares,
+                    "Unicus".to_string(),
+                    "UNI".to_string(),
+                    "https://unicus.one/nft.json".to_string(),
+                    ROYALTY_BASIS_POINTS,
+                )],
+                &[&user],
+            )
+            .await;
+        assert_error(result, MintError::InvalidCreatorShares);
+    }
+}
+
+
+#[tokio::test]
+async fn mint_nft_reports_failed_cpis() {
+    let harness = Harness::start().await;
+    let user = harness.user(1).await;
+    let other = harness.user(1).await;
+    let mint = harness.create_mint(&user).await;
+
+    // Only the mint authority can mint.
+    harness.create_token_account(&other.pubkey(), &mint).await;
+    let result = harness.send(&[mint_nft(&other.pubkey(), &mint, ROYALTY_BASIS_POINTS)], &[&other]).await;
+    assert_error(result, MintError::MintFailed);
+
+    // Token Metadata caps names at 32 characters.
+    let result = harness
+        .send(
+            &[instruction::mint_nft(
+                &user.pubkey(),
+                &mint,
+                vec![CreatorShare { address: user.pubkey(), share: 100 }],
+                "A name far longer than Token Metadata allows".to_string(),
+                "UNI".to_string(),
+                "https://unicus.one/nft.json".to_string(),
+                ROYALTY_BASIS_POINTS,
+            )],
+            &[&user],
+        )
+        .await;
+    assert_error(result, MintError::MetadataCreateFailed);
+}
+
+
+#[tokio::test]
+async fn migrates_a_percent_royalty_to_basis_points() {
+    let harness = Harness::start().await;
+    let user = harness.user(1).await;
+    let other = harness.user(1).await;
+    let mint = harness.mint_legacy_nft(&user, 5).await;
+
+    let result = harness.send(&[instruction::migrate_royalty(&other.pubkey(), &mint)], &[&other]).await;
+    assert_error(result, MarketError::Unauthorized);
+
+    harness.send(&[instruction::migrate_royalty(&user.pubkey(), &mint)], &[&user]).await.unwrap();
+    let metadata = harness.metadata(&mint).await;
+    assert_eq!(metadata.data.seller_fee_basis_points, 500);
+    assert_eq!(metadata.data.name.trim_matches(char::from(0)), "Unicus");
+    let royalty_version: RoyaltyVersion = harness.fetch(&pda::royalty_version(&mint)).await;
+    assert_eq!(royalty_version.version, ROYALTY_VERSION_BASIS_POINTS);
+
+    let result = harness.send(&[instruction::migrate_royalty(&user.pubkey(), &mint)], &[&user]).await;
+    assert_error(result, MintError::RoyaltyAlreadyMigrated);
+
+    let too_high = harness.mint_legacy_nft(&user, 11).await;
+    let result = harness.send(&[instruction::migrate_royalty(&user.pubkey(), &too_high)], &[&user]).await;
+    assert_error(result, MintError::RoyaltyExceeded);
+}
+
+
+#[tokio::test]
+async fn minted_royalties_are_never_migrated() {
+    let harness = Harness::start().await;
+    let user = harness.user(1).await;
+
+    // A royalty of 5 basis points is in the legacy percent range, but the program
+    // recorded it as basis points when minting.
+    let mint = harness.mint_nft(&user, 5).await;
+    let royalty_version: RoyaltyVersion = harness.fetch(&pda::royalty_version(&mint)).await;
+    assert_eq!(royalty_version.version, ROYALTY_VERSION_BASIS_POINTS);
+
+    let result = harness.send(&[instruction::migrate_royalty(&user.pubkey(), &mint)], &[&user]).await;
+    assert_error(result, MintError::RoyaltyAlreadyMigrated);
+    assert_eq!(harness.metadata(&mint).await.data.seller_fee_basis_points, 5);
+}
+
+
+#[tokio::test]
+async fn migrate_royalty_reports_a_failed_update() {
+    let harness = Harness::start().await;
+    let user = harness.user(1).await;
+
+    // Token Metadata refuses to update metadata that has been made immutable.
+    let mint = harness.mint_legacy_nft(&user, 5).await;
+    harness
+        .send(
+            &[mpl_token_metadata::instruction::update_metadata_accounts_v2(
+                mpl_token_metadata::ID,
+                pda::metadata(&mint),
+                user.pubkey(),
+                None,
+                None,
+                None,
+                Some(false),
+            )],
+            &[&user],
+        )
+        .await
+        .unwrap();
+    let result = harness.send(&[instruction::migrate_royalty(&user.pubkey(), &mint)], &[&user]).await;
+    assert_error(result, MintError::MetadataUpdateFailed);
+    assert!(!harness.exists(&pda::royalty_version(&mint)).await);
+}
@@ -0,0 +1,261 @@
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_spl::token::TokenAccount;
+use common::*;
+use mpl_token_metadata::pda::find_master_edition_account;
+use solana_program_test::tokio;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::signature::{Keypair, Signer};
+use unicus_client::{instruction, pda, CollectionOffer, MarketError, Offer};
+
+const PRICE: u64 = LAMPORTS_PER_SOL;
+
+
+// Mints a collection nft with a master edition and `size` items verified as part of it,
+// all by `artist`.
+async fn collection(harness: &Harness, artist: &Keypair, size: usize) -> (Pubkey, Vec<Pubkey>) {
+    let collection = harness.mint_nft(artist, ROYALTY_BASIS_POINTS).await;
+    let master_edition = find_master_edition_account(&collection).0;
+    harness
+        .send(
+            &[mpl_token_metadata::instruction::create_master_edition_v3(
+                mpl_token_metadata::ID,
+                master_edition,
+                collection,
+                artist.pubkey(),
+                artist.pubkey(),
+                pda::metadata(&collection),
+                artist.pubkey(),
+                Some(0),
+            )],
+            &[artist],
+        )
+        .await
+        .unwrap();
+
+    let mut items = vec![];
+    for _ in 0..size {
+        let item = harness.mint_nft(artist, ROYALTY_BASIS_POINTS).await;
+        harness
+            .send(
+                &[mpl_token_metadata::instruction::set_and_verify_collection(
+                    mpl_token_metadata::ID,
+                    pda::metadata(&item),
+                    artist.pubkey(),
+                    artist.pubkey(),
+                    artist.pubkey(),
+                    collection,
+                    pda::metadata(&collection),
+                    master_edition,
+                    None,
+                )],
+                &[artist],
+            )
+            .await
+            .unwrap();
+        items.push(item);
+    }
+
+    (collection, items)
+}
+
+
+#[tokio::test]
+async fn accepts_an_offer() {
+    let harness = Harness::start().await;
+    let artist = harness.user(1).await;
+    let seller = harness.user(1).await;
+    let buyer = harness.user(3).await;
+    let mint = harness.mint_nft(&artist, ROYALTY_BASIS_POINTS).await;
+    harness.transfer_nft(&artist, &mint, &seller.pubkey()).await;
+    harness.create_token_account(&buyer.pubkey(), &mint).await;
+
+    harness.send(&[instruction::make_offer(&buyer.pubkey(), &mint, PRICE)], &[&buyer]).await.unwrap();
+    let offer_key = pda::offer(&mint, &buyer.pubkey());
+    let offer: Offer = harness.fetch(&offer_key).await;
+    assert_eq!(offer.buyer, buyer.pubkey());
+    assert_eq!(offer.price, PRICE);
+
+    let accept_offer = instruction::accept_offer(&seller.pubkey(), &buyer.pubkey(), &mint, &harness.treasury, &[artist.pubkey()]);
+
+    let result = harness
+        .send(
+            &[instruction::accept_offer(&seller.pubkey(), &buyer.pubkey(), &mint, &seller.pubkey(), &[artist.pubkey()])],
+            &[&seller],
+        )
+        .await;
+    assert_error(result, MarketError::InvalidTreasury);
+
+    let other_mint = harness.mint_nft(&artist, ROYALTY_BASIS_POINTS).await;
+    let result = harness
+        .send(&[replace_account(accept_offer.clone(), &pda::metadata(&mint), pda::metadata(&other_mint))], &[&seller])
+        .await;
+    assert_error(result, MarketError::InvalidMetadata);
+
+    let result = harness
+        .send(
+            &[instruction::accept_offer(&seller.pubkey(), &buyer.pubkey(), &mint, &harness.treasury, &[seller.pubkey()])],
+            &[&seller],
+        )
+        .await;
+    assert_error(result, MarketError::InvalidRoyaltyReceiver);
+
+    let seller_balance = harness.balance(&seller.pubkey()).await;
+    let artist_balance = harness.balance(&artist.pubkey()).await;
+    let treasury_balance = harness.balance(&harness.treasury).await;
+    harness.send(&[accept_offer], &[&seller]).await.unwrap();
+
+    assert_eq!(harness.balance(&seller.pubkey()).await, seller_balance + PRICE - fee(PRICE) - royalty(PRICE));
+    assert_eq!(harness.balance(&artist.pubkey()).await, artist_balance + royalty(PRICE));
+    assert_eq!(harness.balance(&harness.treasury).await, treasury_balance + fee(PRICE));
+    assert_eq!(harness.token_balance(&pda::token_account(&buyer.pubkey(), &mint)).await, 1);
+    assert!(!harness.exists(&offer_key).await);
+}
+
+
+#[tokio::test]
+async fn accept_offer_refuses_a_fee_and_royalty_above_the_price() {
+    let harness = Harness::start().await;
+    let seller = harness.user(1).await;
+    let buyer = harness.user(3).await;
+    let mint = harness.mint_nft(&seller, ROYALTY_BASIS_POINTS).await;
+    harness.create_token_account(&buyer.pubkey(), &mint).await;
+    harness.send(&[instruction::make_offer(&buyer.pubkey(), &mint, PRICE)], &[&buyer]).await.unwrap();
+
+    harness
+        .send(&[instruction::set_fee(&harness.admin.pubkey(), 10000 - ROYALTY_BASIS_POINTS + 1)], &[&harness.admin])
+        .await
+        .unwrap();
+    let result = harness
+        .send(
+            &[instruction::accept_offer(&seller.pubkey(), &buyer.pubkey(), &mint, &harness.treasury, &[seller.pubkey()])],
+            &[&seller],
+        )
+        .await;
+    assert_error(result, MarketError::MathOverflow);
+    assert_eq!(harness.token_balance(&pda::token_account(&seller.pubkey(), &mint)).await, 1);
+}
+
+
+#[tokio::test]
+async fn make_offer_refuses_a_zero_price() {
+    let harness = Harness::start().await;
+    let owner = harness.user(1).await;
+    let buyer = harness.user(3).await;
+    let mint = harness.mint_nft(&owner, ROYALTY_BASIS_POINTS).await;
+
+    let result = harness.send(&[instruction::make_offer(&buyer.pubkey(), &mint, 0)], &[&buyer]).await;
+    assert_error(result, MarketError::InvalidPrice);
+    assert!(!harness.exists(&pda::offer(&mint, &buyer.pubkey())).await);
+}
+
+
+#[tokio::test]
+async fn cancels_an_offer() {
+    let harness = Harness::start().await;
+    let owner = harness.user(1).await;
+    let buyer = harness.user(3).await;
+    let mint = harness.mint_nft(&owner, ROYALTY_BASIS_POINTS).await;
+
+    let buyer_balance = harness.balance(&buyer.pubkey()).await;
+    harness.send(&[instruction::make_offer(&buyer.pubkey(), &mint, PRICE)], &[&buyer]).await.unwrap();
+    assert!(harness.balance(&buyer.pubkey()).await < buyer_balance - PRICE);
+
+    harness.send(&[instruction::cancel_offer(&buyer.pubkey(), &mint)], &[&buyer]).await.unwrap();
+    assert_eq!(harness.balance(&buyer.pubkey()).await, buyer_balance);
+    assert!(!harness.exists(&pda::offer(&mint, &buyer.pubkey())).await);
+}
+
+
+#[tokio::test]
+async fn fills_a_collection_offer_item_by_item() {
+    let harness = Harness::start().await;
+    let artist = harness.user(2).await;
+    let seller = harness.user(1).await;
+    let buyer = harness.user(3).await;
+    let (collection, items) = collection(&harness, &artist, 2).await;
+    for item in &items {
+        harness.transfer_nft(&artist, item, &seller.pubkey()).await;
+    }
+
+    let result = harness
+        .send(&[instruction::make_collection_offer(&buyer.pubkey(), &collection, PRICE, 0)], &[&buyer])
+        .await;
+    assert_error(result, MarketError::InvalidQuantity);
+
+    harness
+        .send(&[instruction::make_collection_offer(&buyer.pubkey(), &collection, PRICE, 2)], &[&buyer])
+        .await
+        .unwrap();
+    let collection_offer_key = pda::collection_offer(&collection, &buyer.pubkey());
+    let accept_collection_offer = |mint: &Pubkey| {
+        instruction::accept_collection_offer(&seller.pubkey(), &buyer.pubkey(), &collection, mint, &harness.treasury, &[artist.pubkey()])
+    };
+
+    // Only verified members of the collection are bought.
+    let stray = harness.mint_nft(&artist, ROYALTY_BASIS_POINTS).await;
+    harness.transfer_nft(&artist, &stray, &seller.pubkey()).await;
+    let result = harness.send(&[accept_collection_offer(&stray)], &[&seller]).await;
+    assert_error(result, MarketError::InvalidCollection);
+
+    // The seller pays for the buyer's token account.
+    let token_account_rent = harness.rent().await.minimum_balance(TokenAccount::LEN);
+    let seller_balance = harness.balance(&seller.pubkey()).await;
+    let artist_balance = harness.balance(&artist.pubkey()).await;
+    harness.send(&[accept_collection_offer(&items[0])], &[&seller]).await.unwrap();
+
+    assert_eq!(
+        harness.balance(&seller.pubkey()).await + token_account_rent,
+        seller_balance + PRICE - fee(PRICE) - royalty(PRICE)
+    );
+    assert_eq!(harness.balance(&artist.pubkey()).await, artist_balance + royalty(PRICE));
+    assert_eq!(harness.token_balance(&pda::token_account(&buyer.pubkey(), &items[0])).await, 1);
+
+    let collection_offer: CollectionOffer = harness.fetch(&collection_offer_key).await;
+    assert_eq!(collection_offer.quantity, 1);
+
+    let close = || instruction::close_collection_offer(&buyer.pubkey(), &collection);
+    let result = harness.send(&[close()], &[]).await;
+    assert_error(result, MarketError::OfferNotFilled);
+
+    harness.send(&[accept_collection_offer(&items[1])], &[&seller]).await.unwrap();
+    assert_eq!(harness.token_balance(&pda::token_account(&buyer.pubkey(), &items[1])).await, 1);
+
+    // A filled offer takes no more items and anyone can hand its rent back to the buyer.
+    let collection_offer: CollectionOffer = harness.fetch(&collection_offer_key).await;
+    assert_eq!(collection_offer.quantity, 0);
+    let extra = harness.mint_nft(&artist, ROYALTY_BASIS_POINTS).await;
+    harness.transfer_nft(&artist, &extra, &seller.pubkey()).await;
+    let result = harness.send(&[accept_collection_offer(&extra)], &[&seller]).await;
+    assert_error(result, MarketError::OfferFilled);
+
+    let buyer_balance = harness.balance(&buyer.pubkey()).await;
+    let offer_rent = harness.balance(&collection_offer_key).await;
+    harness.send(&[close()], &[]).await.unwrap();
+    assert_eq!(harness.balance(&buyer.pubkey()).await, buyer_balance + offer_rent);
+    assert!(!harness.exists(&collection_offer_key).await);
+}
+
+
+#[tokio::test]
+async fn cancels_a_collection_offer() {
+    let harness = Harness::start().await;
+    let artist = harness.user(2).await;
+    let buyer = harness.user(5).await;
+    let (collection, _) = collection(&harness, &artist, 0).await;
+
+    let buyer_balance = harness.balance(&buyer.pubkey()).await;
+    harness
+        .send(&[instruction::make_collection_offer(&buyer.pubkey(), &collection, PRICE, 3)], &[&buyer])
+        .await
+        .unwrap();
+    assert!(harness.balance(&buyer.pubkey()).await < buyer_balance - 3 * PRICE);
+
+    harness
+        .send(&[instruction::cancel_collection_offer(&buyer.pubkey(), &collection)], &[&buyer])
+        .await
+        .unwrap();
+    assert_eq!(harness.balance(&buyer.pubkey()).await, buyer_balance);
+    assert!(!harness.exists(&pda::collection_offer(&collection, &buyer.pubkey())).await);
+}
@@ -0,0 +1,391 @@
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_program_test::tokio;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::signature::{Keypair, Signer};
+use unicus_client::{instruction, pda, MarketError, Order};
+
+const PRICE: u64 = LAMPORTS_PER_SOL;
+const TOKEN_PRICE: u64 = 1_000_000;
+
+
+// An nft minted by `artist`, who is paid its royalty, and held by `seller`.
+async fn artwork(harness: &Harness, artist: &Keypair, seller: &Keypair) -> Pubkey {
+    let mint = harness.mint_nft(artist, ROYALTY_BASIS_POINTS).await;
+    harness.transfer_nft(artist, &mint, &seller.pubkey()).await;
+    mint
+}
+
+
+fn create_order(harness: &Harness, seller: &Keypair, mint: &Pubkey, listing_id: u64, expires_at: Option<i64>) -> Instruction {
+    instruction::create_order(&seller.pubkey(), mint, &harness.treasury, "This is test order.".to_string(), PRICE, listing_id, expires_at)
+}
+
+
+#[tokio::test]
+async fn lists_and_fills_an_order() {
+    let harness = Harness::start().await;
+    let artist = harness.user(1).await;
+    let seller = harness.user(1).await;
+    let buyer = harness.user(2).await;
+    let mint = artwork(&harness, &artist, &seller).await;
+
+    let treasury_balance = harness.balance(&harness.treasury).await;
+    harness.send(&[create_order(&harness, &seller, &mint, 0, None)], &[&seller]).await.unwrap();
+    assert_eq!(harness.balance(&harness.treasury).await, treasury_balance + fee(PRICE));
+
+    let order_key = pda::order(&mint, &seller.pubkey(), 0);
+    let order: Order = harness.fetch(&order_key).await;
+    assert_eq!(order.creator, seller.pubkey());
+    assert_eq!(order.mint_key, mint);
+    assert_eq!(order.price, PRICE);
+    assert_eq!(order.payment_mint, None);
+    assert_eq!(harness.token_balance(&pda::token_account(&order_key, &mint)).await, 1);
+    assert_eq!(harness.token_balance(&pda::token_account(&seller.pubkey(), &mint)).await, 0);
+
+    harness.create_token_account(&buyer.pubkey(), &mint).await;
+    let artist_balance = harness.balance(&artist.pubkey()).await;
+    let buyer_balance = harness.balance(&buyer.pubkey()).await;
+    harness
+        .send(&[instruction::fill_order(&buyer.pubkey(), &seller.pubkey(), &mint, 0, &[artist.pubkey()])], &[&buyer])
+        .await
+        .unwrap();
+
+    assert_eq!(harness.balance(&buyer.pubkey()).await, buyer_balance - PRICE);
+    assert_eq!(harness.balance(&artist.pubkey()).await, artist_balance + royalty(PRICE));
+    assert_eq!(harness.token_balance(&pda::token_account(&buyer.pubkey(), &mint)).await, 1);
+    assert!(!harness.exists(&order_key).await);
+    assert!(!harness.exists(&pda::token_account(&order_key, &mint)).await);
+}
+
+
+#[tokio::test]
+async fn cancels_and_updates_an_order() {
+    let harness = Harness::start().await;
+    let seller = harness.user(1).await;
+    let mint = harness.mint_nft(&seller, ROYALTY_BASIS_POINTS).await;
+    harness.send(&[create_order(&harness, &seller, &mint, 0, None)], &[&seller]).await.unwrap();
+
+    // Raising the price pays the fee on the difference, lowering it refunds nothing.
+    let treasury_balance = harness.balance(&harness.treasury).await;
+    let update = |price| {
+        instruction::update_order(&seller.pubkey(), &mint, 0, &harness.treasury, "A longer memo for the updated order.".to_string(), price)
+    };
+    harness.send(&[update(2 * PRICE)], &[&seller]).await.unwrap();
+    assert_eq!(harness.balance(&harness.treasury).await, treasury_balance + fee(PRICE));
+    harness.send(&[update(PRICE / 2)], &[&seller]).await.unwrap();
+    assert_eq!(harness.balance(&harness.treasury).await, treasury_balance + fee(PRICE));
+
+    let order_key = pda::order(&mint, &seller.pubkey(), 0);
+    let order: Order = harness.fetch(&order_key).await;
+    assert_eq!(order.price, PRICE / 2);
+    assert_eq!(order.memo, "A longer memo for the updated order.");
+
+    harness.send(&[instruction::cancel_order(&seller.pubkey(), &mint, 0)], &[&seller]).await.unwrap();
+    assert_eq!(harness.token_balance(&pda::token_account(&seller.pubkey(), &mint)).await, 1);
+    assert!(!harness.exists(&order_key).await);
+}
+
+
+#[tokio::test]
+async fn expired_orders_can_not_be_filled_and_anyone_closes_them() {
+    let harness = Harness::start().await;
+    let seller = harness.user(1).await;
+    let buyer = harness.user(2).await;
+    let mint = harness.mint_nft(&seller, ROYALTY_BASIS_POINTS).await;
+    harness.create_token_account(&buyer.pubkey(), &mint).await;
+
+    let now = harness.now().await;
+    let result = harness.send(&[create_order(&harness, &seller, &mint, 0, Some(now))], &[&seller]).await;
+    assert_error(result, MarketError::InvalidExpiry);
+
+    harness.send(&[create_order(&harness, &seller, &mint, 0, Some(now + 100))], &[&seller]).await.unwrap();
+
+    let close = || instruction::close_expired_order(&buyer.pubkey(), &seller.pubkey(), &mint, 0);
+    let result = harness.send(&[close()], &[&buyer]).await;
+    assert_error(result, MarketError::OrderNotExpired);
+
+    harness.warp_to(now + 100).await;
+    let result = harness
+        .send(&[instruction::fill_order(&buyer.pubkey(), &seller.pubkey(), &mint, 0, &[seller.pubkey()])], &[&buyer])
+        .await;
+    assert_error(result, MarketError::OrderExpired);
+
+    harness.send(&[close()], &[&buyer]).await.unwrap();
+    assert_eq!(harness.token_balance(&pda::token_account(&seller.pubkey(), &mint)).await, 1);
+    assert!(!harness.exists(&pda::order(&mint, &seller.pubkey(), 0)).await);
+}
+
+
+#[tokio::test]
+async fn closing_an_expired_order_recreates_the_sellers_token_account() {
+    let harness = Harness::start().await;
+    let seller = harness.user(1).await;
+    let closer = harness.user(1).await;
+    let mint = harness.mint_nft(&seller, ROYALTY_BASIS_POINTS).await;
+    let seller_token_account = pda::token_account(&seller.pubkey(), &mint);
+
+    let now = harness.now().await;
+    harness.send(&[create_order(&harness, &seller, &mint, 0, Some(now + 100))], &[&seller]).await.unwrap();
+    harness
+        .send(
+            &[spl_token::instruction::close_account(&spl_token::ID, &seller_token_account, &seller.pubkey(), &seller.pubkey(), &[])
+                .unwrap()],
+            &[&seller],
+        )
+        .await
+        .unwrap();
+    assert!(!harness.exists(&seller_token_account).await);
+
+    harness.warp_to(now + 100).await;
+    let closer_balance = harness.balance(&closer.pubkey()).await;
+    harness
+        .send(&[instruction::close_expired_order(&closer.pubkey(), &seller.pubkey(), &mint, 0)], &[&closer])
+        .await
+        .unwrap();
+    assert_eq!(harness.token_balance(&seller_token_account).await, 1);
+    let rent = harness.rent().await.minimum_balance(spl_token::state::Account::LEN);
+    assert_eq!(harness.balance(&closer.pubkey()).await, closer_balance - rent);
+}
+
+
+#[tokio::test]
+async fn create_order_checks_the_listing() {
+    let harness = Harness::start().await;
+    let seller = harness.user(1).await;
+    let mint = harness.mint_nft(&seller, ROYALTY_BASIS_POINTS).await;
+
+    let result = harness
+        .send(
+            &[instruction::create_order(&seller.pubkey(), &mint, &seller.pubkey(), "This is test order.".to_string(), PRICE, 0, None)],
+            &[&seller],
+        )
+        .await;
+    assert_error(result, MarketError::InvalidTreasury);
+
+    // A listed nft is escrowed, so it can not be listed again.
+    harness.send(&[create_order(&harness, &seller, &mint, 0, None)], &[&seller]).await.unwrap();
+    let result = harness.send(&[create_order(&harness, &seller, &mint, 1, None)], &[&seller]).await;
+    assert_error(result, MarketError::TokenNotHeld);
+
+    // Only a supply of exactly one is an nft.
+    let fungible = harness.mint_nft(&seller, ROYALTY_BASIS_POINTS).await;
+    harness
+        .send(
+            &[spl_token::instruction::mint_to(
+                &spl_token::ID,
+                &fungible,
+                &pda::token_account(&seller.pubkey(), &fungible),
+                &seller.pubkey(),
+                &[],
+                1,
+            )
+            .unwrap()],
+            &[&seller],
+        )
+        .await
+        .unwrap();
+    let result = harness.send(&[create_order(&harness, &seller, &fungible, 0, None)], &[&seller]).await;
+    assert_error(result, MarketError::InvalidNft);
+}
+
+
+#[tokio::test]
+async fn fill_order_checks_payment_and_royalties() {
+    let harness = Harness::start().await;
+    let artist = harness.user(1).await;
+    let seller = harness.user(1).await;
+    let buyer = harness.user(2).await;
+    let poor_buyer = Keypair::new();
+    let mint = artwork(&harness, &artist, &seller).await;
+    let other_mint = harness.mint_nft(&artist, ROYALTY_BASIS_POINTS).await;
+    harness.send(&[create_order(&harness, &seller, &mint, 0, None)], &[&seller]).await.unwrap();
+    harness.create_token_account(&buyer.pubkey(), &mint).await;
+    harness.create_token_account(&poor_buyer.pubkey(), &mint).await;
+
+    let fill = |buyer: &Keypair, creators: &[Pubkey]| {
+        instruction::fill_order(&buyer.pubkey(), &seller.pubkey(), &mint, 0, creators)
+    };
+
+    let result = harness.send(&[fill(&poor_buyer, &[artist.pubkey()])], &[&poor_buyer]).await;
+    assert_error(result, MarketError::InsufficientMoney);
+
+    let result = harness
+        .send(&[replace_account(fill(&buyer, &[artist.pubkey()]), &pda::metadata(&mint), pda::metadata(&other_mint))], &[&buyer])
+        .await;
+    assert_error(result, MarketError::InvalidMetadata);
+
+    for creators in [vec![], vec![seller.pubkey()], vec![artist.pubkey(), seller.pubkey()]] {
+        let result = harness.send(&[fill(&buyer, &creators)], &[&buyer]).await;
+        assert_error(result, MarketError::InvalidRoyaltyReceiver);
+    }
+}
+
+
+#[tokio::test]
+async fn order_transfers_report_failed_cpis() {
+    let harness = Harness::start().await;
+    let seller = harness.user(1).await;
+    let buyer = harness.user(2).await;
+
+    // A frozen token account can neither send nor receive the nft.
+    let frozen = harness.mint_nft(&seller, ROYALTY_BASIS_POINTS).await;
+    harness.freeze(&seller, &frozen, &pda::token_account(&seller.pubkey(), &frozen)).await;
+    let result = harness.send(&[create_order(&harness, &seller, &frozen, 0, None)], &[&seller]).await;
+    assert_error(result, MarketError::TokenTransferFailed);
+
+    let mint = harness.mint_nft(&seller, ROYALTY_BASIS_POINTS).await;
+    harness.send(&[create_order(&harness, &seller, &mint, 0, None)], &[&seller]).await.unwrap();
+    let buyer_token_account = harness.create_token_account(&buyer.pubkey(), &mint).await;
+    harness.freeze(&seller, &mint, &buyer_token_account).await;
+    let result = harness
+        .send(&[instruction::fill_order(&buyer.pubkey(), &seller.pubkey(), &mint, 0, &[seller.pubkey()])], &[&buyer])
+        .await;
+    assert_error(result, MarketError::TokenTransferFailed3);
+
+    harness.freeze(&seller, &mint, &pda::token_account(&seller.pubkey(), &mint)).await;
+    let result = harness.send(&[instruction::cancel_order(&seller.pubkey(), &mint, 0)], &[&seller]).await;
+    assert_error(result, MarketError::TokenTransferFailed2);
+
+    // The order's token account only closes once empty.
+    let mint = harness.mint_nft(&seller, ROYALTY_BASIS_POINTS).await;
+    harness.send(&[create_order(&harness, &seller, &mint, 0, None)], &[&seller]).await.unwrap();
+    let order_token_account = pda::token_account(&pda::order(&mint, &seller.pubkey(), 0), &mint);
+    harness
+        .send(
+            &[spl_token::instruction::mint_to(&spl_token::ID, &mint, &order_token_account, &seller.pubkey(), &[], 1).unwrap()],
+            &[&seller],
+        )
+        .await
+        .unwrap();
+    let result = harness.send(&[instruction::cancel_order(&seller.pubkey(), &mint, 0)], &[&seller]).await;
+    assert_error(result, MarketError::TokenCloseFailed);
+}
+
+
+#[tokio::test]
+async fn lists_and_fills_a_token_order() {
+    let harness = Harness::start().await;
+    let artist = harness.user(1).await;
+    let seller = harness.user(1).await;
+    let buyer = harness.user(1).await;
+    let mint = artwork(&harness, &artist, &seller).await;
+
+    let payment_mint = harness.create_payment_mint().await;
+    let seller_payment = harness.fund_tokens(&seller.pubkey(), &payment_mint, fee(TOKEN_PRICE)).await;
+    let treasury_payment = harness.fund_tokens(&harness.treasury, &payment_mint, 0).await;
+    let artist_payment = harness.fund_tokens(&artist.pubkey(), &payment_mint, 0).await;
+    let buyer_payment = harness.fund_tokens(&buyer.pubkey(), &payment_mint, TOKEN_PRICE).await;
+
+    harness
+        .send(
+            &[instruction::create_token_order(
+                &seller.pubkey(),
+                &mint,
+                &payment_mint,
+                &harness.treasury,
+                "This is test order.".to_string(),
+                TOKEN_PRICE,
+                0,
+                None,
+            )],
+            &[&seller],
+        )
+        .await
+        .unwrap();
+    assert_eq!(harness.token_balance(&treasury_payment).await, fee(TOKEN_PRICE));
+    assert_eq!(harness.token_balance(&seller_payment).await, 0);
+
+    let order: Order = harness.fetch(&pda::order(&mint, &seller.pubkey(), 0)).await;
+    assert_eq!(order.payment_mint, Some(payment_mint));
+
+    // Lamport priced instructions refuse token orders.
+    harness.create_token_account(&buyer.pubkey(), &mint).await;
+    let result = harness
+        .send(&[instruction::fill_order(&buyer.pubkey(), &seller.pubkey(), &mint, 0, &[artist.pubkey()])], &[&buyer])
+        .await;
+    assert_error(result, MarketError::InvalidPaymentMint);
+    let result = harness
+        .send(
+            &[instruction::update_order(&seller.pubkey(), &mint, 0, &harness.treasury, "This is test order.".to_string(), 1)],
+            &[&seller],
+        )
+        .await;
+    assert_error(result, MarketError::InvalidPaymentMint);
+
+    harness
+        .send(
+            &[instruction::fill_token_order(&buyer.pubkey(), &seller.pubkey(), &mint, &payment_mint, 0, &[artist.pubkey()])],
+            &[&buyer],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(harness.token_balance(&buyer_payment).await, 0);
+    assert_eq!(harness.token_balance(&artist_payment).await, royalty(TOKEN_PRICE));
+    assert_eq!(harness.token_balance(&seller_payment).await, TOKEN_PRICE - royalty(TOKEN_PRICE));
+    assert_eq!(harness.token_balance(&pda::token_account(&buyer.pubkey(), &mint)).await, 1);
+}
+
+
+#[tokio::test]
+async fn token_orders_check_payment_accounts() {
+    let harness = Harness::start().await;
+    let seller = harness.user(1).await;
+    let buyer = harness.user(1).await;
+    let mint = harness.mint_nft(&seller, ROYALTY_BASIS_POINTS).await;
+
+    let payment_mint = harness.create_payment_mint().await;
+    let other_mint = harness.create_payment_mint().await;
+    let seller_payment = harness.fund_tokens(&seller.pubkey(), &payment_mint, TOKEN_PRICE).await;
+    harness.fund_tokens(&seller.pubkey(), &other_mint, TOKEN_PRICE).await;
+    let treasury_payment = harness.fund_tokens(&harness.treasury, &payment_mint, 0).await;
+    harness.fund_tokens(&buyer.pubkey(), &payment_mint, TOKEN_PRICE - 1).await;
+    harness.create_token_account(&buyer.pubkey(), &mint).await;
+
+    let create = |treasury: &Pubkey| {
+        instruction::create_token_order(&seller.pubkey(), &mint, &payment_mint, treasury, "This is test order.".to_string(), TOKEN_PRICE, 0, None)
+    };
+
+    // The treasury's payment account must belong to the marketplace treasury.
+    let result = harness.send(&[create(&seller.pubkey())], &[&seller]).await;
+    assert_error(result, MarketError::InvalidTreasury);
+
+    // Paying the fee from an account of another mint.
+    let result = harness
+        .send(
+            &[replace_account(
+                create(&harness.treasury),
+                &seller_payment,
+                pda::token_account(&seller.pubkey(), &other_mint),
+            )],
+            &[&seller],
+        )
+        .await;
+    assert_error(result, MarketError::InvalidPaymentMint);
+
+    harness.send(&[create(&harness.treasury)], &[&seller]).await.unwrap();
+
+    // Raising the price pays the fee on the difference.
+    harness
+        .send(
+            &[instruction::update_token_order(&seller.pubkey(), &mint, &payment_mint, 0, &harness.treasury, "This is test order.".to_string(), 2 * TOKEN_PRICE)],
+            &[&seller],
+        )
+        .await
+        .unwrap();
+    assert_eq!(harness.token_balance(&treasury_payment).await, fee(2 * TOKEN_PRICE));
+
+    let result = harness
+        .send(
+            &[instruction::fill_token_order(&buyer.pubkey(), &seller.pubkey(), &mint, &payment_mint, 0, &[seller.pubkey()])],
+            &[&buyer],
+        )
+        .await;
+    assert_error(result, MarketError::InsufficientMoney);
+}
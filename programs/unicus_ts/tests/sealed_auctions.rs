@@ -0,0 +1,231 @@
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::hash::hashv;
+use common::*;
+use solana_program_test::tokio;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::signature::{Keypair, Signer};
+use unicus_client::{args, instruction, pda, Auction, MarketError, SealedBid};
+
+const PRICE: u64 = LAMPORTS_PER_SOL;
+const DURATION: i64 = 3600;
+const SLASH_BASIS_POINTS: u16 = 1000;
+
+
+fn sealed_auction_args(start_time: i64, end_time: i64, listing_id: u64) -> args::CreateSealedAuction {
+    args::CreateSealedAuction {
+        memo: "This is test auction.".to_string(),
+        price: PRICE,
+        start_time,
+        end_time,
+        listing_id,
+        reveal_end_time: end_time + DURATION,
+        second_price: true,
+        min_deposit: PRICE,
+        slash_bps: SLASH_BASIS_POINTS,
+    }
+}
+
+
+fn commitment(amount: u64, salt: [u8; 32]) -> [u8; 32] {
+    hashv(&[&amount.to_le_bytes(), &salt]).to_bytes()
+}
+
+
+#[tokio::test]
+async fn settles_to_the_second_highest_revealed_bid() {
+    let harness = Harness::start().await;
+    let artist = harness.user(1).await;
+    let creator = harness.user(1).await;
+    let first = harness.user(4).await;
+    let second = harness.user(4).await;
+    let bluffer = harness.user(2).await;
+    let settler = harness.user(1).await;
+    let mint = harness.mint_nft(&artist, ROYALTY_BASIS_POINTS).await;
+    harness.transfer_nft(&artist, &mint, &creator.pubkey()).await;
+
+    let now = harness.now().await;
+    let end_time = now + DURATION;
+    let reveal_end_time = end_time + DURATION;
+    harness
+        .send(
+            &[instruction::create_sealed_auction(&creator.pubkey(), &mint, &harness.treasury, sealed_auction_args(now - 60, end_time, 0))],
+            &[&creator],
+        )
+        .await
+        .unwrap();
+
+    let auction_key = pda::auction(&mint, &creator.pubkey(), 0);
+    let commit_bid = |bidder: &Keypair, amount, salt, deposit| {
+        instruction::commit_bid(&bidder.pubkey(), &creator.pubkey(), &mint, 0, commitment(amount, salt), deposit)
+    };
+    let reveal_bid = |bidder: &Keypair, leader: &Pubkey, amount, salt| {
+        instruction::reveal_bid(&bidder.pubkey(), &creator.pubkey(), &mint, 0, leader, amount, salt)
+    };
+    let withdraw = |bidder: &Keypair| instruction::withdraw_sealed_bid(&bidder.pubkey(), &creator.pubkey(), &auction_key);
+    let resolve = |winner: &Pubkey| {
+        instruction::auction_resolve(&settler.pubkey(), &creator.pubkey(), &mint, 0, winner, &harness.treasury, &[artist.pubkey()])
+    };
+
+    let result = harness.send(&[commit_bid(&first, 2 * PRICE, [1; 32], PRICE - 1)], &[&first]).await;
+    assert_error(result, MarketError::InsufficientMoney);
+
+    harness.send(&[commit_bid(&first, 2 * PRICE, [1; 32], 3 * PRICE)], &[&first]).await.unwrap();
+    harness.send(&[commit_bid(&second, 3 * PRICE, [2; 32], 3 * PRICE)], &[&second]).await.unwrap();
+    harness.send(&[commit_bid(&bluffer, 5 * PRICE, [3; 32], PRICE)], &[&bluffer]).await.unwrap();
+
+    let result = harness.send(&[reveal_bid(&first, &creator.pubkey(), 2 * PRICE, [1; 32])], &[&first]).await;
+    assert_error(result, MarketError::NotRevealPhase);
+
+    harness.warp_to(end_time + 1).await;
+    let late = harness.user(2).await;
+    let result = harness.send(&[commit_bid(&late, 2 * PRICE, [4; 32], 2 * PRICE)], &[&late]).await;
+    assert_error(result, MarketError::NotBiddingPhase);
+
+    let result = harness.send(&[reveal_bid(&first, &creator.pubkey(), 2 * PRICE, [9; 32])], &[&first]).await;
+    assert_error(result, MarketError::InvalidCommitment);
+
+    harness.send(&[reveal_bid(&first, &creator.pubkey(), 2 * PRICE, [1; 32])], &[&first]).await.unwrap();
+    let result = harness.send(&[reveal_bid(&first, &creator.pubkey(), 2 * PRICE, [1; 32])], &[&first]).await;
+    assert_error(result, MarketError::AlreadyRevealed);
+
+    // A bid above its deposit can not be revealed.
+    let result = harness.send(&[reveal_bid(&bluffer, &first.pubkey(), 5 * PRICE, [3; 32])], &[&bluffer]).await;
+    assert_error(result, MarketError::InsufficientMoney);
+
+    let result = harness.send(&[reveal_bid(&second, &creator.pubkey(), 3 * PRICE, [2; 32])], &[&second]).await;
+    assert_error(result, MarketError::InvalidReceiver);
+    harness.send(&[reveal_bid(&second, &first.pubkey(), 3 * PRICE, [2; 32])], &[&second]).await.unwrap();
+
+    let auction: Auction = harness.fetch(&auction_key).await;
+    assert_eq!(auction.refund_receiver, second.pubkey());
+    assert_eq!(auction.price, 3 * PRICE);
+    assert_eq!(auction.second_bid, 2 * PRICE);
+
+    let sealed_bid: SealedBid = harness.fetch(&pda::sealed_bid(&auction_key, &first.pubkey())).await;
+    assert_eq!(sealed_bid.deposit, 3 * PRICE);
+
+    let result = harness.send(&[resolve(&second.pubkey())], &[&settler]).await;
+    assert_error(result, MarketError::RevealNotEnded);
+    let result = harness.send(&[withdraw(&first)], &[&first]).await;
+    assert_error(result, MarketError::RevealNotEnded);
+
+    harness.warp_to(reveal_end_time).await;
+    let second_balance = harness.balance(&second.pubkey()).await;
+    let artist_balance = harness.balance(&artist.pubkey()).await;
+    harness.send(&[resolve(&second.pubkey())], &[&settler]).await.unwrap();
+
+    assert_eq!(harness.balance(&second.pubkey()).await, second_balance + PRICE);
+    assert_eq!(harness.balance(&artist.pubkey()).await, artist_balance + royalty(2 * PRICE));
+    assert_eq!(harness.token_balance(&pda::token_account(&second.pubkey(), &mint)).await, 1);
+    assert!(!harness.exists(&auction_key).await);
+
+    // Revealed bids get their whole deposit back.
+    let first_sealed_bid = pda::sealed_bid(&auction_key, &first.pubkey());
+    let first_balance = harness.balance(&first.pubkey()).await;
+    let sealed_bid_lamports = harness.balance(&first_sealed_bid).await;
+    harness.send(&[withdraw(&first)], &[&first]).await.unwrap();
+    assert_eq!(harness.balance(&first.pubkey()).await, first_balance + sealed_bid_lamports);
+    assert!(!harness.exists(&first_sealed_bid).await);
+
+    // Unrevealed ones lose part of it to the creator.
+    let slash = PRICE * SLASH_BASIS_POINTS as u64 / 10000;
+    let bluffer_sealed_bid = pda::sealed_bid(&auction_key, &bluffer.pubkey());
+    let bluffer_balance = harness.balance(&bluffer.pubkey()).await;
+    let creator_balance = harness.balance(&creator.pubkey()).await;
+    let sealed_bid_lamports = harness.balance(&bluffer_sealed_bid).await;
+    harness.send(&[withdraw(&bluffer)], &[&bluffer]).await.unwrap();
+    assert_eq!(harness.balance(&bluffer.pubkey()).await, bluffer_balance + sealed_bid_lamports - slash);
+    assert_eq!(harness.balance(&creator.pubkey()).await, creator_balance + slash);
+}
+
+
+#[tokio::test]
+async fn create_sealed_auction_checks_its_settings() {
+    let harness = Harness::start().await;
+    let creator = harness.user(1).await;
+    let mint = harness.mint_nft(&creator, ROYALTY_BASIS_POINTS).await;
+    let now = harness.now().await;
+    let create = |args| instruction::create_sealed_auction(&creator.pubkey(), &mint, &harness.treasury, args);
+
+    let args = args::CreateSealedAuction { reveal_end_time: now + DURATION, ..sealed_auction_args(now, now + DURATION, 0) };
+    let result = harness.send(&[create(args)], &[&creator]).await;
+    assert_error(result, MarketError::InvalidRevealTime);
+
+    let args = args::CreateSealedAuction { slash_bps: 10001, ..sealed_auction_args(now, now + DURATION, 0) };
+    let result = harness.send(&[create(args)], &[&creator]).await;
+    assert_error(result, MarketError::InvalidSlash);
+
+    harness.send(&[create(sealed_auction_args(now - 60, now + DURATION, 0))], &[&creator]).await.unwrap();
+    let auction: Auction = harness.fetch(&pda::auction(&mint, &creator.pubkey(), 0)).await;
+    assert!(auction.is_sealed());
+    assert_eq!(auction.min_deposit, PRICE);
+
+    // Open bids are refused, sealed ones need a sealed auction.
+    let bidder = harness.user(3).await;
+    for bid in [
+        instruction::bid(&bidder.pubkey(), &creator.pubkey(), &mint, 0, &creator.pubkey(), PRICE),
+        instruction::deferred_bid(&bidder.pubkey(), &creator.pubkey(), &mint, 0, &creator.pubkey(), PRICE),
+    ] {
+        let result = harness.send(&[bid], &[&bidder]).await;
+        assert_error(result, MarketError::SealedAuction);
+    }
+
+    let other = harness.user(1).await;
+    let open_mint = harness.mint_nft(&other, ROYALTY_BASIS_POINTS).await;
+    harness
+        .send(
+            &[instruction::create_auction(&other.pubkey(), &open_mint, &harness.treasury, auction_args(PRICE, now - 60, now + DURATION, 0))],
+            &[&other],
+        )
+        .await
+        .unwrap();
+    let result = harness
+        .send(
+            &[instruction::commit_bid(&bidder.pubkey(), &other.pubkey(), &open_mint, 0, commitment(PRICE, [1; 32]), PRICE)],
+            &[&bidder],
+        )
+        .await;
+    assert_error(result, MarketError::NotSealedAuction);
+}
+
+
+#[tokio::test]
+async fn sealed_auctions_only_cancel_before_they_start() {
+    let harness = Harness::start().await;
+    let creator = harness.user(1).await;
+    let bidder = harness.user(3).await;
+    let mint = harness.mint_nft(&creator, ROYALTY_BASIS_POINTS).await;
+    let now = harness.now().await;
+    let cancel = || instruction::cancel_auction(&creator.pubkey(), &mint, 0, &creator.pubkey(), &harness.treasury);
+
+    harness
+        .send(
+            &[instruction::create_sealed_auction(&creator.pubkey(), &mint, &harness.treasury, sealed_auction_args(now + 60, now + DURATION, 0))],
+            &[&creator],
+        )
+        .await
+        .unwrap();
+
+    let result = harness
+        .send(
+            &[instruction::commit_bid(&bidder.pubkey(), &creator.pubkey(), &mint, 0, commitment(PRICE, [1; 32]), PRICE)],
+            &[&bidder],
+        )
+        .await;
+    assert_error(result, MarketError::NotBiddingPhase);
+
+    harness.send(&[cancel()], &[&creator]).await.unwrap();
+    assert_eq!(harness.token_balance(&pda::token_account(&creator.pubkey(), &mint)).await, 1);
+
+    harness
+        .send(
+            &[instruction::create_sealed_auction(&creator.pubkey(), &mint, &harness.treasury, sealed_auction_args(now, now + DURATION, 0))],
+            &[&creator],
+        )
+        .await
+        .unwrap();
+    let result = harness.send(&[cancel()], &[&creator]).await;
+    assert_error(result, MarketError::AuctionStarted);
+}
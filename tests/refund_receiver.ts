@@ -0,0 +1,357 @@
+import * as anchor from "@project-serum/anchor";
+import { AnchorError, Program } from "@project-serum/anchor";
+import {
+  TOKEN_PROGRAM_ID,
+  createAssociatedTokenAccountInstruction,
+  getAssociatedTokenAddress,
+  createInitializeMintInstruction,
+  MINT_SIZE,
+  createMintToInstruction,
+  getOrCreateAssociatedTokenAccount,
+} from "@solana/spl-token";
+import { BN } from "bn.js";
+import { assert, expect } from "chai";
+import { UnicusTs } from "../target/types/unicus_ts";
+
+//
+// Regression suite for auctions whose outbid refund could be redirected by
+// passing an arbitrary `refund_receiver` account.
+//
+
+const { LAMPORTS_PER_SOL } = anchor.web3;
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+const mainProgram = anchor.workspace.UnicusTs as Program<UnicusTs>;
+
+const TOKEN_METADATA_PROGRAM_ID = new anchor.web3.PublicKey(
+  "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"
+);
+
+const [marketplaceAccount] = anchor.web3.PublicKey.findProgramAddressSync(
+  [Buffer.from("marketplace")],
+  mainProgram.programId
+);
+
+async function getAccountBalance(pubKey) {
+  let account = await provider.connection.getAccountInfo(pubKey);
+  return (account?.lamports ?? 0) / LAMPORTS_PER_SOL;
+}
+
+const createUser = async (airdropBalance: number) => {
+  let user = anchor.web3.Keypair.generate();
+  const sig = await provider.connection.requestAirdrop(
+    user.publicKey,
+    airdropBalance * LAMPORTS_PER_SOL
+  );
+
+  const latestBlockHash = await provider.connection.getLatestBlockhash();
+  await provider.connection.confirmTransaction({
+    blockhash: latestBlockHash.blockhash,
+    lastValidBlockHeight: latestBlockHash.lastValidBlockHeight,
+    signature: sig,
+  });
+
+  let wallet = new anchor.Wallet(user);
+  let userProvider = new anchor.AnchorProvider(
+    provider.connection,
+    wallet,
+    provider.opts
+  );
+
+  return {
+    key: user,
+    wallet,
+    provider: userProvider,
+  };
+};
+
+const programForUser = (user) =>
+  new anchor.Program(mainProgram.idl, mainProgram.programId, user.provider);
+
+const initializeMarketplace = async () => {
+  let existing = await provider.connection.getAccountInfo(marketplaceAccount);
+  if (existing == null) {
+    const [programData] = anchor.web3.PublicKey.findProgramAddressSync(
+      [mainProgram.programId.toBytes()],
+      new anchor.web3.PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+
+    await mainProgram.methods
+      .initializeMarketplace(
+        anchor.web3.Keypair.generate().publicKey,
+        200,
+        1000
+      )
+      .accounts({
+        marketplace: marketplaceAccount,
+        admin: provider.wallet.publicKey,
+        program: mainProgram.programId,
+        programData: programData,
+      })
+      .rpc();
+  }
+
+  return await mainProgram.account.marketplace.fetch(marketplaceAccount);
+};
+
+//
+// Create a mint with a single token held by the user's associated token account.
+//
+const mintToken = async (user) => {
+  let mintKey = anchor.web3.Keypair.generate();
+  const lamports = await provider.connection.getMinimumBalanceForRentExemption(
+    MINT_SIZE
+  );
+  let tokenAccount = await getAssociatedTokenAddress(
+    mintKey.publicKey,
+    user.key.publicKey
+  );
+
+  const tx = new anchor.web3.Transaction().add(
+    anchor.web3.SystemProgram.createAccount({
+      fromPubkey: user.key.publicKey,
+      newAccountPubkey: mintKey.publicKey,
+      space: MINT_SIZE,
+      programId: TOKEN_PROGRAM_ID,
+      lamports,
+    }),
+    createInitializeMintInstruction(
+      mintKey.publicKey,
+      0,
+      user.key.publicKey,
+      user.key.publicKey
+    ),
+    createAssociatedTokenAccountInstruction(
+      user.key.publicKey,
+      tokenAccount,
+      user.key.publicKey,
+      mintKey.publicKey
+    ),
+    createMintToInstruction(
+      mintKey.publicKey,
+      tokenAccount,
+      user.key.publicKey,
+      1
+    )
+  );
+  await user.provider.sendAndConfirm(tx, [user.key, mintKey]);
+
+  return { mintKey: mintKey.publicKey, tokenAccount };
+};
+
+const auctionAddresses = async (
+  mintKey: anchor.web3.PublicKey,
+  creatorKey: anchor.web3.PublicKey
+) => {
+  const [auctionAccount] = anchor.web3.PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("auction"),
+      mintKey.toBytes(),
+      creatorKey.toBytes(),
+      new BN(0).toArrayLike(Buffer, "le", 8),
+    ],
+    mainProgram.programId
+  );
+  const [bidVault] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("bid_vault"), auctionAccount.toBytes()],
+    mainProgram.programId
+  );
+  const auctionTokenAccount = await getAssociatedTokenAddress(
+    mintKey,
+    auctionAccount,
+    true
+  );
+
+  return { auctionAccount, bidVault, auctionTokenAccount };
+};
+
+//
+// List the user's token in an auction and place a first bid from `bidder`.
+//
+const createAuctionWithBid = async (user, bidder, duration: number) => {
+  const { mintKey, tokenAccount } = await mintToken(user);
+  const { auctionAccount, bidVault, auctionTokenAccount } =
+    await auctionAddresses(mintKey, user.key.publicKey);
+  let marketplace = await mainProgram.account.marketplace.fetch(
+    marketplaceAccount
+  );
+  const now = Math.floor(Date.now() / 1000);
+
+  await programForUser(user)
+    .methods.createAuction(
+      "This is test auction.",
+      new BN(1 * LAMPORTS_PER_SOL),
+      new BN(now - 60),
+      new BN(now + duration),
+      new BN(0),
+      0,
+      new BN(0),
+      new BN(0),
+      null,
+      new BN(0),
+      new BN(0),
+      0,
+      null
+    )
+    .accounts({
+      auction: auctionAccount,
+      bidVault: bidVault,
+      auctionTokenAccount: auctionTokenAccount,
+      mintKey: mintKey,
+      creator: user.key.publicKey,
+      creatorTokenAccount: tokenAccount,
+      marketplace: marketplaceAccount,
+      treasuryAccount: marketplace.treasury,
+    })
+    .rpc();
+
+  await programForUser(bidder)
+    .methods.bid(new BN(1 * LAMPORTS_PER_SOL))
+    .accounts({
+      auction: auctionAccount,
+      bidVault: bidVault,
+      mintKey: mintKey,
+      creator: user.key.publicKey,
+      bidder: bidder.key.publicKey,
+      refundReceiver: user.key.publicKey,
+    })
+    .rpc();
+
+  return { mintKey, tokenAccount, auctionAccount, bidVault, auctionTokenAccount };
+};
+
+const expectInvalidReceiver = (err) => {
+  expect(err).to.be.instanceOf(AnchorError);
+  const anchorError = err as AnchorError;
+  expect(anchorError.error.errorCode.code).equals("InvalidReceiver");
+};
+
+
+describe("refund_receiver", () => {
+
+  before(async () => {
+    await initializeMarketplace();
+  });
+
+
+  it("bid rejects a refund redirected away from the outbid bidder", async () => {
+    let user = await createUser(1);
+    let bidder = await createUser(3);
+    let attacker = await createUser(3);
+
+    const auction = await createAuctionWithBid(user, bidder, 3600);
+    const bidderBalance = await getAccountBalance(bidder.key.publicKey);
+
+    try {
+      await programForUser(attacker)
+        .methods.bid(new BN(2 * LAMPORTS_PER_SOL))
+        .accounts({
+          auction: auction.auctionAccount,
+          bidVault: auction.bidVault,
+          mintKey: auction.mintKey,
+          creator: user.key.publicKey,
+          bidder: attacker.key.publicKey,
+          refundReceiver: attacker.key.publicKey,
+        })
+        .rpc();
+      assert(false, "Bid should fail with a refund receiver that is not the highest bidder.");
+    } catch (err) {
+      expectInvalidReceiver(err);
+    }
+
+    let state = await mainProgram.account.auction.fetch(auction.auctionAccount);
+    expect(state.refundReceiver.toString()).equals(bidder.key.publicKey.toString());
+    expect(state.price.toNumber()).equals(1 * LAMPORTS_PER_SOL);
+    let vault = await mainProgram.account.bidVault.fetch(auction.bidVault);
+    expect(vault.amount.toNumber()).equals(1 * LAMPORTS_PER_SOL);
+    expect(await getAccountBalance(bidder.key.publicKey)).equals(bidderBalance);
+  });
+
+
+  it("cancel auction rejects a refund redirected away from the highest bidder", async () => {
+    let user = await createUser(1);
+    let bidder = await createUser(3);
+
+    const auction = await createAuctionWithBid(user, bidder, 3600);
+    let marketplace = await mainProgram.account.marketplace.fetch(
+      marketplaceAccount
+    );
+
+    try {
+      await programForUser(user)
+        .methods.cancelAuction()
+        .accounts({
+          auction: auction.auctionAccount,
+          bidVault: auction.bidVault,
+          auctionTokenAccount: auction.auctionTokenAccount,
+          mintKey: auction.mintKey,
+          creator: user.key.publicKey,
+          creatorTokenAccount: auction.tokenAccount,
+          refundReceiver: user.key.publicKey,
+          marketplace: marketplaceAccount,
+          treasuryAccount: marketplace.treasury,
+        })
+        .rpc();
+      assert(false, "Cancel auction should fail with a refund receiver that is not the highest bidder.");
+    } catch (err) {
+      expectInvalidReceiver(err);
+    }
+
+    let vault = await mainProgram.account.bidVault.fetch(auction.bidVault);
+    expect(vault.amount.toNumber()).equals(1 * LAMPORTS_PER_SOL);
+  });
+
+
+  it("auction resolve rejects a winner other than the highest bidder", async () => {
+    let user = await createUser(1);
+    let bidder = await createUser(3);
+    let attacker = await createUser(1);
+
+    const auction = await createAuctionWithBid(user, bidder, 2);
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+
+    const attackerTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      attacker.key,
+      auction.mintKey,
+      attacker.key.publicKey
+    );
+    const [metadata] = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("metadata"),
+        TOKEN_METADATA_PROGRAM_ID.toBytes(),
+        auction.mintKey.toBytes(),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    );
+
+    try {
+      await programForUser(attacker)
+        .methods.auctionResolve()
+        .accounts({
+          auction: auction.auctionAccount,
+          bidVault: auction.bidVault,
+          auctionTokenAccount: auction.auctionTokenAccount,
+          mintKey: auction.mintKey,
+          creator: user.key.publicKey,
+          creatorTokenAccount: auction.tokenAccount,
+          refundReceiver: attacker.key.publicKey,
+          refundReceiverTokenAccount: attackerTokenAccount.address,
+          metadata: metadata,
+          marketplace: marketplaceAccount,
+          treasuryAccount: (await mainProgram.account.marketplace.fetch(marketplaceAccount)).treasury,
+          settler: attacker.key.publicKey,
+        })
+        .rpc();
+      assert(false, "Auction resolve should fail with a receiver that is not the highest bidder.");
+    } catch (err) {
+      expectInvalidReceiver(err);
+    }
+
+    let balance = await provider.connection.getTokenAccountBalance(
+      auction.auctionTokenAccount
+    );
+    expect(balance.value.uiAmount).equals(1);
+  });
+});
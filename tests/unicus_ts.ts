@@ -8,8 +8,11 @@ import {
   MINT_SIZE,
   createMintToInstruction,
   getOrCreateAssociatedTokenAccount,
+  createMint as createPaymentMint,
+  mintTo,
 } from "@solana/spl-token";
 import { BN } from "bn.js";
+import { createHash } from "crypto";
 import { assert, expect } from "chai";
 import { UnicusTs } from "../target/types/unicus_ts";
 
@@ -54,6 +57,73 @@ const createUser = async (airdropBalance: number) => {
 };
 
 
+const TOKEN_METADATA_PROGRAM_ID = new anchor.web3.PublicKey(
+  "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"
+);
+
+const getMetadataAddress = (mintKey: anchor.web3.PublicKey) => {
+  const [metadata] = anchor.web3.PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("metadata"),
+      TOKEN_METADATA_PROGRAM_ID.toBytes(),
+      mintKey.toBytes(),
+    ],
+    TOKEN_METADATA_PROGRAM_ID
+  );
+  return metadata;
+};
+
+const [marketplaceAccount] = anchor.web3.PublicKey.findProgramAddressSync(
+  [Buffer.from("marketplace")],
+  mainProgram.programId
+);
+const treasury = anchor.web3.Keypair.generate();
+
+
+const initializeMarketplace = async (
+  feeBasisPoints: number,
+  maxRoyaltyBasisPoints: number
+) => {
+  let existing = await provider.connection.getAccountInfo(marketplaceAccount);
+  if (existing != null) {
+    return await mainProgram.account.marketplace.fetch(marketplaceAccount);
+  }
+
+  const [programData] = anchor.web3.PublicKey.findProgramAddressSync(
+    [mainProgram.programId.toBytes()],
+    new anchor.web3.PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+  );
+
+  await mainProgram.methods
+    .initializeMarketplace(
+      treasury.publicKey,
+      feeBasisPoints,
+      maxRoyaltyBasisPoints
+    )
+    .accounts({
+      marketplace: marketplaceAccount,
+      admin: provider.wallet.publicKey,
+      program: mainProgram.programId,
+      programData: programData,
+    })
+    .rpc();
+
+  return await mainProgram.account.marketplace.fetch(marketplaceAccount);
+};
+
+
+const listingSeed = (listingId: number) =>
+  new BN(listingId).toArrayLike(Buffer, "le", 8);
+
+const getBidVaultAddress = (auctionAccount: anchor.web3.PublicKey) => {
+  const [bidVault] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("bid_vault"), auctionAccount.toBytes()],
+    mainProgram.programId
+  );
+  return bidVault;
+};
+
+
 const programForUser = async (user) => {
   return new anchor.Program(
     mainProgram.idl,
@@ -170,17 +240,70 @@ const mintToken = async (mintKey: anchor.web3.Keypair, user) => {
 
 
 
+const creatorAccounts = (creatorKeys: anchor.web3.PublicKey[]) =>
+  creatorKeys.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }));
+
+
+const mintNft = async (
+  user,
+  creators: { address: anchor.web3.PublicKey; share: number }[],
+  royalty: number
+) => {
+  const mintKey = await createMint(user);
+  const tokenAccount = await createAssociateTokenAccount(
+    mintKey.publicKey,
+    user
+  );
+
+  const [royaltyVersion] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("royalty_version"), mintKey.publicKey.toBytes()],
+    mainProgram.programId
+  );
+
+  let program = await programForUser(user);
+  await program.methods
+    .mintNft(creators, "Unicus", "UNI", "https://unicus.one/nft.json", royalty)
+    .accounts({
+      mintAuthority: user.key.publicKey,
+      mint: mintKey.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      metadata: getMetadataAddress(mintKey.publicKey),
+      tokenAccount: tokenAccount,
+      tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+      payer: user.key.publicKey,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      marketplace: marketplaceAccount,
+      royaltyVersion: royaltyVersion,
+    })
+    .rpc();
+
+  return {
+    mintKey,
+    tokenAccount,
+  };
+};
+
+
+
 const createOrder = async (
   user,
   mintKey: anchor.web3.Keypair,
   owner: anchor.web3.Keypair,
   ownerTokenAccount: anchor.web3.PublicKey,
   memo: string,
-  price: number
+  price: number,
+  listingId: number = 0,
+  expiresAt: number | null = null
 ) => {
   let program = await programForUser(user);
+  let marketplace = await program.account.marketplace.fetch(marketplaceAccount);
   const [orderAccount, bump] = await anchor.web3.PublicKey.findProgramAddress(
-    [Buffer.from("order"), mintKey.publicKey.toBytes()],
+    [
+      Buffer.from("order"),
+      mintKey.publicKey.toBytes(),
+      owner.publicKey.toBytes(),
+      listingSeed(listingId),
+    ],
     program.programId
   );
 
@@ -191,13 +314,20 @@ const createOrder = async (
   );
 
   await program.methods
-    .createOrder(memo, new BN(price))
+    .createOrder(
+      memo,
+      new BN(price),
+      new BN(listingId),
+      expiresAt === null ? null : new BN(expiresAt)
+    )
     .accounts({
       order: orderAccount,
       orderTokenAccount: orderTokenAccount,
       mintKey: mintKey.publicKey,
       creator: owner.publicKey,
       creatorTokenAccount: ownerTokenAccount,
+      marketplace: marketplaceAccount,
+      treasuryAccount: marketplace.treasury,
     })
     .rpc();
 
@@ -218,11 +348,26 @@ const createAuction = async (
   memo: string,
   price: number,
   start_time: number,
-  end_time: number
+  end_time: number,
+  listingId: number = 0,
+  minIncrementBps: number = 0,
+  minIncrement: number = 0,
+  reservePrice: number = 0,
+  reserveHash: number[] | null = null,
+  extensionWindow: number = 0,
+  extensionDuration: number = 0,
+  maxExtensions: number = 0,
+  buyNowPrice: number | null = null
 ) => {
   let program = await programForUser(user);
+  let marketplace = await program.account.marketplace.fetch(marketplaceAccount);
   const [auctionAccount, bump] = await anchor.web3.PublicKey.findProgramAddress(
-    [Buffer.from("auction"), mintKey.publicKey.toBytes()],
+    [
+      Buffer.from("auction"),
+      mintKey.publicKey.toBytes(),
+      owner.publicKey.toBytes(),
+      listingSeed(listingId),
+    ],
     program.programId
   );
 
@@ -233,13 +378,30 @@ const createAuction = async (
   );
 
   await program.methods
-    .createAuction(memo, new BN(price), new BN(start_time), new BN(end_time))
+    .createAuction(
+      memo,
+      new BN(price),
+      new BN(start_time),
+      new BN(end_time),
+      new BN(listingId),
+      minIncrementBps,
+      new BN(minIncrement),
+      new BN(reservePrice),
+      reserveHash,
+      new BN(extensionWindow),
+      new BN(extensionDuration),
+      maxExtensions,
+      buyNowPrice === null ? null : new BN(buyNowPrice)
+    )
     .accounts({
       auction: auctionAccount,
+      bidVault: getBidVaultAddress(auctionAccount),
       auctionTokenAccount: auctionTokenAccount,
       mintKey: mintKey.publicKey,
       creator: owner.publicKey,
       creatorTokenAccount: ownerTokenAccount,
+      marketplace: marketplaceAccount,
+      treasuryAccount: marketplace.treasury,
     })
     .rpc();
 
@@ -254,15 +416,94 @@ const createAuction = async (
 
 
 
+const updateOrder = async (
+  user,
+  mintKey: anchor.web3.PublicKey,
+  memo: string,
+  price: number,
+  listingId: number = 0
+) => {
+  let program = await programForUser(user);
+  let marketplace = await program.account.marketplace.fetch(marketplaceAccount);
+  const [orderAccount] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      Buffer.from("order"),
+      mintKey.toBytes(),
+      user.key.publicKey.toBytes(),
+      listingSeed(listingId),
+    ],
+    program.programId
+  );
+
+  await program.methods
+    .updateOrder(memo, new BN(price))
+    .accounts({
+      order: orderAccount,
+      mintKey: mintKey,
+      creator: user.key.publicKey,
+      marketplace: marketplaceAccount,
+      treasuryAccount: marketplace.treasury,
+    })
+    .rpc();
+
+  return await program.account.order.fetch(orderAccount);
+};
+
+
+
+const closeExpiredOrder = async (
+  cranker,
+  mintKey: anchor.web3.PublicKey,
+  ownerKey: anchor.web3.PublicKey,
+  listingId: number = 0
+) => {
+  let program = await programForUser(cranker);
+  const [orderAccount] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      Buffer.from("order"),
+      mintKey.toBytes(),
+      ownerKey.toBytes(),
+      listingSeed(listingId),
+    ],
+    program.programId
+  );
+
+  await program.methods
+    .closeExpiredOrder()
+    .accounts({
+      order: orderAccount,
+      orderTokenAccount: await getAssociatedTokenAddress(
+        mintKey,
+        orderAccount,
+        true
+      ),
+      mintKey: mintKey,
+      creator: ownerKey,
+      creatorTokenAccount: await getAssociatedTokenAddress(mintKey, ownerKey),
+      closer: cranker.key.publicKey,
+    })
+    .rpc();
+
+  return orderAccount;
+};
+
+
+
 const cancelOrder = async (
   user,
   mintKey: anchor.web3.Keypair,
   owner: anchor.web3.Keypair,
-  ownerTokenAccount: anchor.web3.PublicKey
+  ownerTokenAccount: anchor.web3.PublicKey,
+  listingId: number = 0
 ) => {
   let program = await programForUser(user);
   const [orderAccount, bump] = await anchor.web3.PublicKey.findProgramAddress(
-    [Buffer.from("order"), mintKey.publicKey.toBytes()],
+    [
+      Buffer.from("order"),
+      mintKey.publicKey.toBytes(),
+      owner.publicKey.toBytes(),
+      listingSeed(listingId),
+    ],
     program.programId
   );
 
@@ -296,12 +537,19 @@ const cancelAuction = async (
   mintKey: anchor.web3.PublicKey,
   owner: anchor.web3.Keypair,
   receiverKey: anchor.web3.PublicKey,
-  ownerTokenAccount: anchor.web3.PublicKey
+  ownerTokenAccount: anchor.web3.PublicKey,
+  listingId: number = 0
 ) => {
   let program = await programForUser(user);
+  let marketplace = await program.account.marketplace.fetch(marketplaceAccount);
   
   const [auctionAccount, bump] = await anchor.web3.PublicKey.findProgramAddress(
-    [Buffer.from("auction"), mintKey.toBytes()],
+    [
+      Buffer.from("auction"),
+      mintKey.toBytes(),
+      owner.publicKey.toBytes(),
+      listingSeed(listingId),
+    ],
     program.programId
   );
 
@@ -315,11 +563,14 @@ const cancelAuction = async (
     .cancelAuction()
     .accounts({
       auction: auctionAccount,
+      bidVault: getBidVaultAddress(auctionAccount),
       auctionTokenAccount: auctionTokenAccount,
       mintKey: mintKey,
       creator: owner.publicKey,
       creatorTokenAccount: ownerTokenAccount,
       refundReceiver: receiverKey,
+      marketplace: marketplaceAccount,
+      treasuryAccount: marketplace.treasury,
     })
     .rpc();
 
@@ -333,11 +584,18 @@ const cancelAuction = async (
 const fillOrder = async (
   mintKey: anchor.web3.PublicKey,
   ownerKey: anchor.web3.PublicKey,
-  buyer
+  buyer,
+  creatorKeys: anchor.web3.PublicKey[] = [ownerKey],
+  listingId: number = 0
 ) => {
   let program = await programForUser(buyer);
   const [orderAccount, bump] = await anchor.web3.PublicKey.findProgramAddress(
-    [Buffer.from("order"), mintKey.toBytes()],
+    [
+      Buffer.from("order"),
+      mintKey.toBytes(),
+      ownerKey.toBytes(),
+      listingSeed(listingId),
+    ],
     program.programId
   );
 
@@ -347,11 +605,10 @@ const fillOrder = async (
     true
   );
 
-  let sellerPoints = 3;
   const buyerTokenAccount = await createAssociateTokenAccount(mintKey, buyer);
 
   await program.methods
-    .fillOrder(sellerPoints)
+    .fillOrder()
     .accounts({
       order: orderAccount,
       orderTokenAccount: orderTokenAccount,
@@ -359,538 +616,2574 @@ const fillOrder = async (
       creator: ownerKey,
       buyer: buyer.key.publicKey,
       buyerTokenAccount: buyerTokenAccount,
+      metadata: getMetadataAddress(mintKey),
     })
+    .remainingAccounts(creatorAccounts(creatorKeys))
     .rpc();
 
   return buyerTokenAccount;
 };
 
 
-const bidAuction = async (
+const getDutchAuctionAddress = (
   mintKey: anchor.web3.PublicKey,
   ownerKey: anchor.web3.PublicKey,
-  receiverKey: anchor.web3.PublicKey,
-  bidder,
-  price: number
+  listingId: number = 0
 ) => {
-  let program = await programForUser(bidder);
-
-  const [auctionAccount, bump] = await anchor.web3.PublicKey.findProgramAddress(
-    [Buffer.from("auction"), mintKey.toBytes()],
-    program.programId
+  const [dutchAuction] = anchor.web3.PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("dutch_auction"),
+      mintKey.toBytes(),
+      ownerKey.toBytes(),
+      listingSeed(listingId),
+    ],
+    mainProgram.programId
   );
+  return dutchAuction;
+};
 
 
-  try {
-    await program.methods.bid(
-      new BN(price)
-    ).accounts({
-      auction: auctionAccount,
-      mintKey: mintKey,
-      creator: ownerKey,
-      bidder: bidder.key.publicKey,
-      refundReceiver: receiverKey,
-    }).rpc();
-  } catch(err) {
-      console.log(err);
-  }
+const createDutchAuction = async (
+  user,
+  mintKey: anchor.web3.PublicKey,
+  ownerTokenAccount: anchor.web3.PublicKey,
+  startPrice: number,
+  floorPrice: number,
+  start_time: number,
+  end_time: number,
+  listingId: number = 0
+) => {
+  let program = await programForUser(user);
+  let marketplace = await program.account.marketplace.fetch(marketplaceAccount);
+  const dutchAuctionAccount = getDutchAuctionAddress(
+    mintKey,
+    user.key.publicKey,
+    listingId
+  );
+  const auctionTokenAccount = await getAssociatedTokenAddress(
+    mintKey,
+    dutchAuctionAccount,
+    true
+  );
 
-  let auction = await program.account.auction.fetch(auctionAccount);
+  await program.methods
+    .createDutchAuction(
+      "This is test dutch auction.",
+      new BN(startPrice),
+      new BN(floorPrice),
+      new BN(start_time),
+      new BN(end_time),
+      new BN(listingId)
+    )
+    .accounts({
+      dutchAuction: dutchAuctionAccount,
+      auctionTokenAccount: auctionTokenAccount,
+      mintKey: mintKey,
+      creator: user.key.publicKey,
+      creatorTokenAccount: ownerTokenAccount,
+      marketplace: marketplaceAccount,
+      treasuryAccount: marketplace.treasury,
+    })
+    .rpc();
 
   return {
-    auctionAccount,
-    auction
+    dutchAuctionAccount,
+    auctionTokenAccount,
   };
-  
+};
 
-}
 
+const buyDutch = async (
+  mintKey: anchor.web3.PublicKey,
+  ownerKey: anchor.web3.PublicKey,
+  buyer,
+  maxPrice: number,
+  creatorKeys: anchor.web3.PublicKey[] = [ownerKey],
+  listingId: number = 0
+) => {
+  let program = await programForUser(buyer);
+  const dutchAuctionAccount = getDutchAuctionAddress(mintKey, ownerKey, listingId);
+  const auctionTokenAccount = await getAssociatedTokenAddress(
+    mintKey,
+    dutchAuctionAccount,
+    true
+  );
+  const buyerTokenAccount = await getOrCreateAssociatedTokenAccount(
+    program.provider.connection,
+    buyer.key,
+    mintKey,
+    buyer.key.publicKey
+  );
 
+  await program.methods
+    .buyDutch(new BN(maxPrice))
+    .accounts({
+      dutchAuction: dutchAuctionAccount,
+      auctionTokenAccount: auctionTokenAccount,
+      mintKey: mintKey,
+      creator: ownerKey,
+      buyer: buyer.key.publicKey,
+      buyerTokenAccount: buyerTokenAccount.address,
+      metadata: getMetadataAddress(mintKey),
+    })
+    .remainingAccounts(creatorAccounts(creatorKeys))
+    .rpc();
 
+  return buyerTokenAccount.address;
+};
 
-const auctionResolve = async (
-  user,
+
+const buyNow = async (
   mintKey: anchor.web3.PublicKey,
   ownerKey: anchor.web3.PublicKey,
-  receiverKey: anchor.web3.PublicKey,
-  creatorTokenAccount: anchor.web3.PublicKey,
+  buyer,
+  creatorKeys: anchor.web3.PublicKey[] = [ownerKey],
+  listingId: number = 0
 ) => {
-  let program = await programForUser(user);
-
-  const [auctionAccount, bump] = await anchor.web3.PublicKey.findProgramAddress(
-    [Buffer.from("auction"), mintKey.toBytes()],
+  let program = await programForUser(buyer);
+  const [auctionAccount] = anchor.web3.PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("auction"),
+      mintKey.toBytes(),
+      ownerKey.toBytes(),
+      listingSeed(listingId),
+    ],
     program.programId
   );
-
   const auctionTokenAccount = await getAssociatedTokenAddress(
     mintKey,
     auctionAccount,
     true
   );
-
-    let sellerPoints = 3;
-  /*const refundReceiverTokenAccount = await createAssociateTokenAccount(mintKey, receiverKey);
-  const refundReceiverTokenAccount = await getAssociatedTokenAddress(
-    mintKey,
-    auctionAccount,
-    true
-  ); */
-
-  const refundReceiverTokenAccount = await getOrCreateAssociatedTokenAccount(
+  let auction = await program.account.auction.fetch(auctionAccount);
+  let marketplace = await program.account.marketplace.fetch(marketplaceAccount);
+  const buyerTokenAccount = await getOrCreateAssociatedTokenAccount(
     program.provider.connection,
-    user,
+    buyer.key,
     mintKey,
-    receiverKey,
-    true
+    buyer.key.publicKey
   );
-  console.log("refundREciver token aacct", refundReceiverTokenAccount.address.toString());
-
 
-  try {
-    await program.methods.auctionResolve(sellerPoints)
+  await program.methods
+    .buyNow()
     .accounts({
       auction: auctionAccount,
+      bidVault: getBidVaultAddress(auctionAccount),
       auctionTokenAccount: auctionTokenAccount,
       mintKey: mintKey,
       creator: ownerKey,
-      creatorTokenAccount: creatorTokenAccount,
-      refundReceiver: receiverKey,
-      refundReceiverTokenAccount: refundReceiverTokenAccount.address
-    }).rpc();
-  } catch(err) {
-      console.log(err);
-  }
+      buyer: buyer.key.publicKey,
+      buyerTokenAccount: buyerTokenAccount.address,
+      refundReceiver: auction.refundReceiver,
+      metadata: getMetadataAddress(mintKey),
+      marketplace: marketplaceAccount,
+      treasuryAccount: marketplace.treasury,
+    })
+    .remainingAccounts(creatorAccounts(creatorKeys))
+    .rpc();
 
-  console.log("auction resolved");
-  let auction = await program.account.auction.fetch(auctionAccount);
+  return buyerTokenAccount.address;
+};
 
-  return {
-    auctionAccount,
-    auction,
-    auctionTokenAccount,
-    refundReceiverTokenAccount
-  };
-  
 
-}
+const createSealedAuction = async (
+  user,
+  mintKey: anchor.web3.PublicKey,
+  ownerTokenAccount: anchor.web3.PublicKey,
+  price: number,
+  start_time: number,
+  end_time: number,
+  reveal_end_time: number,
+  secondPrice: boolean,
+  minDeposit: number,
+  slashBps: number,
+  listingId: number = 0
+) => {
+  let program = await programForUser(user);
+  let marketplace = await program.account.marketplace.fetch(marketplaceAccount);
+  const [auctionAccount] = anchor.web3.PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("auction"),
+      mintKey.toBytes(),
+      user.key.publicKey.toBytes(),
+      listingSeed(listingId),
+    ],
+    program.programId
+  );
+  const auctionTokenAccount = await getAssociatedTokenAddress(
+    mintKey,
+    auctionAccount,
+    true
+  );
 
+  await program.methods
+    .createSealedAuction(
+      "This is test sealed auction.",
+      new BN(price),
+      new BN(start_time),
+      new BN(end_time),
+      new BN(listingId),
+      new BN(reveal_end_time),
+      secondPrice,
+      new BN(minDeposit),
+      slashBps
+    )
+    .accounts({
+      auction: auctionAccount,
+      bidVault: getBidVaultAddress(auctionAccount),
+      auctionTokenAccount: auctionTokenAccount,
+      mintKey: mintKey,
+      creator: user.key.publicKey,
+      creatorTokenAccount: ownerTokenAccount,
+      marketplace: marketplaceAccount,
+      treasuryAccount: marketplace.treasury,
+    })
+    .rpc();
 
+  return auctionAccount;
+};
 
 
+const getSealedBidAddress = (
+  auctionAccount: anchor.web3.PublicKey,
+  bidderKey: anchor.web3.PublicKey
+) => {
+  const [sealedBid] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("sealed_bid"), auctionAccount.toBytes(), bidderKey.toBytes()],
+    mainProgram.programId
+  );
+  return sealedBid;
+};
 
 
+const bidCommitment = (amount: number, salt: Uint8Array) =>
+  Array.from(
+    createHash("sha256")
+      .update(new BN(amount).toArrayLike(Buffer, "le", 8))
+      .update(salt)
+      .digest()
+  );
 
 
+const commitBid = async (
+  mintKey: anchor.web3.PublicKey,
+  ownerKey: anchor.web3.PublicKey,
+  bidder,
+  amount: number,
+  salt: Uint8Array,
+  deposit: number,
+  listingId: number = 0
+) => {
+  let program = await programForUser(bidder);
+  const [auctionAccount] = anchor.web3.PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("auction"),
+      mintKey.toBytes(),
+      ownerKey.toBytes(),
+      listingSeed(listingId),
+    ],
+    program.programId
+  );
 
+  await program.methods
+    .commitBid(bidCommitment(amount, salt), new BN(deposit))
+    .accounts({
+      auction: auctionAccount,
+      mintKey: mintKey,
+      creator: ownerKey,
+      bidder: bidder.key.publicKey,
+      sealedBid: getSealedBidAddress(auctionAccount, bidder.key.publicKey),
+    })
+    .rpc();
+};
 
-describe("unicus_ts", () => {
 
-/*
-  
-  it("create order", async () => {
-    let user = await createUser(1);
-    console.log("User Account: ", user.key.publicKey.toString());
+const revealBid = async (
+  mintKey: anchor.web3.PublicKey,
+  ownerKey: anchor.web3.PublicKey,
+  bidder,
+  amount: number,
+  salt: Uint8Array,
+  listingId: number = 0
+) => {
+  let program = await programForUser(bidder);
+  const [auctionAccount] = anchor.web3.PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("auction"),
+      mintKey.toBytes(),
+      ownerKey.toBytes(),
+      listingSeed(listingId),
+    ],
+    program.programId
+  );
+  let auction = await program.account.auction.fetch(auctionAccount);
+
+  await program.methods
+    .revealBid(new BN(amount), Array.from(salt))
+    .accounts({
+      auction: auctionAccount,
+      bidVault: getBidVaultAddress(auctionAccount),
+      mintKey: mintKey,
+      creator: ownerKey,
+      bidder: bidder.key.publicKey,
+      sealedBid: getSealedBidAddress(auctionAccount, bidder.key.publicKey),
+      previousSealedBid: getSealedBidAddress(auctionAccount, auction.refundReceiver),
+    })
+    .rpc();
+
+  return await program.account.auction.fetch(auctionAccount);
+};
+
+
+const withdrawSealedBid = async (
+  auctionAccount: anchor.web3.PublicKey,
+  ownerKey: anchor.web3.PublicKey,
+  bidder
+) => {
+  let program = await programForUser(bidder);
+
+  await program.methods
+    .withdrawSealedBid()
+    .accounts({
+      sealedBid: getSealedBidAddress(auctionAccount, bidder.key.publicKey),
+      bidder: bidder.key.publicKey,
+      creator: ownerKey,
+    })
+    .rpc();
+};
+
+
+const bidAuction = async (
+  mintKey: anchor.web3.PublicKey,
+  ownerKey: anchor.web3.PublicKey,
+  receiverKey: anchor.web3.PublicKey,
+  bidder,
+  price: number,
+  listingId: number = 0
+) => {
+  let program = await programForUser(bidder);
+
+  const [auctionAccount, bump] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      Buffer.from("auction"),
+      mintKey.toBytes(),
+      ownerKey.toBytes(),
+      listingSeed(listingId),
+    ],
+    program.programId
+  );
+
+
+  try {
+    await program.methods.bid(
+      new BN(price)
+    ).accounts({
+      auction: auctionAccount,
+      bidVault: getBidVaultAddress(auctionAccount),
+      mintKey: mintKey,
+      creator: ownerKey,
+      bidder: bidder.key.publicKey,
+      refundReceiver: receiverKey,
+    }).rpc();
+  } catch(err) {
+      console.log(err);
+  }
+
+  let auction = await program.account.auction.fetch(auctionAccount);
+
+  return {
+    auctionAccount,
+    auction
+  };
+  
+
+}
+
+
+
+
+const getBidRefundAddress = (
+  auctionAccount: anchor.web3.PublicKey,
+  bidderKey: anchor.web3.PublicKey
+) => {
+  const [bidRefund] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("bid_refund"), auctionAccount.toBytes(), bidderKey.toBytes()],
+    mainProgram.programId
+  );
+  return bidRefund;
+};
+
+
+const deferredBid = async (
+  mintKey: anchor.web3.PublicKey,
+  ownerKey: anchor.web3.PublicKey,
+  previousBidderKey: anchor.web3.PublicKey,
+  bidder,
+  price: number,
+  listingId: number = 0
+) => {
+  let program = await programForUser(bidder);
+
+  const [auctionAccount] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      Buffer.from("auction"),
+      mintKey.toBytes(),
+      ownerKey.toBytes(),
+      listingSeed(listingId),
+    ],
+    program.programId
+  );
+
+  await program.methods
+    .deferredBid(new BN(price))
+    .accounts({
+      auction: auctionAccount,
+      bidVault: getBidVaultAddress(auctionAccount),
+      mintKey: mintKey,
+      creator: ownerKey,
+      bidder: bidder.key.publicKey,
+      bidderRefund: getBidRefundAddress(auctionAccount, bidder.key.publicKey),
+      previousBidRefund: getBidRefundAddress(auctionAccount, previousBidderKey),
+      previousBidder: previousBidderKey,
+    })
+    .rpc();
+
+  let auction = await program.account.auction.fetch(auctionAccount);
+
+  return {
+    auctionAccount,
+    auction
+  };
+};
+
+
+const claimRefund = async (
+  auctionAccount: anchor.web3.PublicKey,
+  bidder
+) => {
+  let program = await programForUser(bidder);
+  const bidRefund = getBidRefundAddress(auctionAccount, bidder.key.publicKey);
+
+  await program.methods
+    .claimRefund()
+    .accounts({
+      bidRefund: bidRefund,
+      bidder: bidder.key.publicKey,
+    })
+    .rpc();
+
+  return bidRefund;
+};
+
+
+
+
+const auctionResolve = async (
+  user,
+  mintKey: anchor.web3.PublicKey,
+  ownerKey: anchor.web3.PublicKey,
+  receiverKey: anchor.web3.PublicKey,
+  creatorTokenAccount: anchor.web3.PublicKey,
+  creatorKeys: anchor.web3.PublicKey[] = [ownerKey],
+  listingId: number = 0
+) => {
+  let program = await programForUser(user);
+
+  const [auctionAccount, bump] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      Buffer.from("auction"),
+      mintKey.toBytes(),
+      ownerKey.toBytes(),
+      listingSeed(listingId),
+    ],
+    program.programId
+  );
+
+  const auctionTokenAccount = await getAssociatedTokenAddress(
+    mintKey,
+    auctionAccount,
+    true
+  );
+
+  /*const refundReceiverTokenAccount = await createAssociateTokenAccount(mintKey, receiverKey);
+  const refundReceiverTokenAccount = await getAssociatedTokenAddress(
+    mintKey,
+    auctionAccount,
+    true
+  ); */
+
+  const refundReceiverTokenAccount = await getOrCreateAssociatedTokenAccount(
+    program.provider.connection,
+    user,
+    mintKey,
+    receiverKey,
+    true
+  );
+  console.log("refundREciver token aacct", refundReceiverTokenAccount.address.toString());
+  let marketplace = await program.account.marketplace.fetch(marketplaceAccount);
+
+
+  try {
+    await program.methods.auctionResolve()
+    .accounts({
+      auction: auctionAccount,
+      bidVault: getBidVaultAddress(auctionAccount),
+      auctionTokenAccount: auctionTokenAccount,
+      mintKey: mintKey,
+      creator: ownerKey,
+      creatorTokenAccount: creatorTokenAccount,
+      refundReceiver: receiverKey,
+      refundReceiverTokenAccount: refundReceiverTokenAccount.address,
+      metadata: getMetadataAddress(mintKey),
+      marketplace: marketplaceAccount,
+      treasuryAccount: marketplace.treasury,
+      settler: program.provider.publicKey,
+    })
+    .remainingAccounts(creatorAccounts(creatorKeys))
+    .rpc();
+  } catch(err) {
+      console.log(err);
+  }
+
+  console.log("auction resolved");
+  let auction = await program.account.auction.fetch(auctionAccount);
+
+  return {
+    auctionAccount,
+    auction,
+    auctionTokenAccount,
+    refundReceiverTokenAccount
+  };
+  
+
+}
+
+
+
+
+
+
+
+
+
+
+const makeOffer = async (
+  mintKey: anchor.web3.PublicKey,
+  buyer,
+  price: number
+) => {
+  let program = await programForUser(buyer);
+  const [offerAccount] = await anchor.web3.PublicKey.findProgramAddress(
+    [Buffer.from("offer"), mintKey.toBytes(), buyer.key.publicKey.toBytes()],
+    program.programId
+  );
+
+  await program.methods
+    .makeOffer(new BN(price))
+    .accounts({
+      offer: offerAccount,
+      mintKey: mintKey,
+      buyer: buyer.key.publicKey,
+    })
+    .rpc();
+
+  let offer = await program.account.offer.fetch(offerAccount);
+  return {
+    offer,
+    offerAccount,
+  };
+};
+
+
+const cancelOffer = async (mintKey: anchor.web3.PublicKey, buyer) => {
+  let program = await programForUser(buyer);
+  const [offerAccount] = await anchor.web3.PublicKey.findProgramAddress(
+    [Buffer.from("offer"), mintKey.toBytes(), buyer.key.publicKey.toBytes()],
+    program.programId
+  );
+
+  await program.methods
+    .cancelOffer()
+    .accounts({
+      offer: offerAccount,
+      mintKey: mintKey,
+      buyer: buyer.key.publicKey,
+    })
+    .rpc();
+
+  return offerAccount;
+};
+
+
+const acceptOffer = async (
+  mintKey: anchor.web3.PublicKey,
+  seller,
+  sellerTokenAccount: anchor.web3.PublicKey,
+  buyerKey: anchor.web3.PublicKey,
+  buyerTokenAccount: anchor.web3.PublicKey,
+  creatorKeys: anchor.web3.PublicKey[]
+) => {
+  let program = await programForUser(seller);
+  let marketplace = await program.account.marketplace.fetch(marketplaceAccount);
+  const [offerAccount] = await anchor.web3.PublicKey.findProgramAddress(
+    [Buffer.from("offer"), mintKey.toBytes(), buyerKey.toBytes()],
+    program.programId
+  );
+
+  await program.methods
+    .acceptOffer()
+    .accounts({
+      offer: offerAccount,
+      mintKey: mintKey,
+      buyer: buyerKey,
+      buyerTokenAccount: buyerTokenAccount,
+      seller: seller.key.publicKey,
+      sellerTokenAccount: sellerTokenAccount,
+      metadata: getMetadataAddress(mintKey),
+      marketplace: marketplaceAccount,
+      treasuryAccount: marketplace.treasury,
+    })
+    .remainingAccounts(creatorAccounts(creatorKeys))
+    .rpc();
+
+  return offerAccount;
+};
+
+
+
+const makeCollectionOffer = async (
+  collectionMintKey: anchor.web3.PublicKey,
+  buyer,
+  price: number,
+  quantity: number
+) => {
+  let program = await programForUser(buyer);
+  const [collectionOfferAccount] =
+    await anchor.web3.PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection_offer"),
+        collectionMintKey.toBytes(),
+        buyer.key.publicKey.toBytes(),
+      ],
+      program.programId
+    );
+
+  await program.methods
+    .makeCollectionOffer(new BN(price), new BN(quantity))
+    .accounts({
+      collectionOffer: collectionOfferAccount,
+      collectionMint: collectionMintKey,
+      buyer: buyer.key.publicKey,
+    })
+    .rpc();
+
+  let collectionOffer = await program.account.collectionOffer.fetch(
+    collectionOfferAccount
+  );
+  return {
+    collectionOffer,
+    collectionOfferAccount,
+  };
+};
+
+
+const acceptCollectionOffer = async (
+  collectionOfferAccount: anchor.web3.PublicKey,
+  mintKey: anchor.web3.PublicKey,
+  seller,
+  sellerTokenAccount: anchor.web3.PublicKey,
+  buyerKey: anchor.web3.PublicKey,
+  creatorKeys: anchor.web3.PublicKey[]
+) => {
+  let program = await programForUser(seller);
+  let marketplace = await program.account.marketplace.fetch(marketplaceAccount);
+  const buyerTokenAccount = await getAssociatedTokenAddress(mintKey, buyerKey);
+
+  await program.methods
+    .acceptCollectionOffer()
+    .accounts({
+      collectionOffer: collectionOfferAccount,
+      mintKey: mintKey,
+      buyer: buyerKey,
+      buyerTokenAccount: buyerTokenAccount,
+      seller: seller.key.publicKey,
+      sellerTokenAccount: sellerTokenAccount,
+      metadata: getMetadataAddress(mintKey),
+      marketplace: marketplaceAccount,
+      treasuryAccount: marketplace.treasury,
+    })
+    .remainingAccounts(creatorAccounts(creatorKeys))
+    .rpc();
+
+  return buyerTokenAccount;
+};
+
+
+
+const createTokenOrder = async (
+  user,
+  mintKey: anchor.web3.Keypair,
+  ownerTokenAccount: anchor.web3.PublicKey,
+  paymentMint: anchor.web3.PublicKey,
+  memo: string,
+  price: number,
+  listingId: number = 0
+) => {
+  let program = await programForUser(user);
+  let marketplace = await program.account.marketplace.fetch(marketplaceAccount);
+  const [orderAccount] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      Buffer.from("order"),
+      mintKey.publicKey.toBytes(),
+      user.key.publicKey.toBytes(),
+      listingSeed(listingId),
+    ],
+    program.programId
+  );
+
+  const orderTokenAccount = await getAssociatedTokenAddress(
+    mintKey.publicKey,
+    orderAccount,
+    true
+  );
+  const creatorPaymentAccount = await getAssociatedTokenAddress(
+    paymentMint,
+    user.key.publicKey
+  );
+  const treasuryPaymentAccount = await getOrCreateAssociatedTokenAccount(
+    program.provider.connection,
+    user.key,
+    paymentMint,
+    marketplace.treasury
+  );
+
+  await program.methods
+    .createTokenOrder(memo, new BN(price), new BN(listingId), null)
+    .accounts({
+      order: orderAccount,
+      orderTokenAccount: orderTokenAccount,
+      mintKey: mintKey.publicKey,
+      paymentMint: paymentMint,
+      creator: user.key.publicKey,
+      creatorTokenAccount: ownerTokenAccount,
+      creatorPaymentAccount: creatorPaymentAccount,
+      marketplace: marketplaceAccount,
+      treasuryPaymentAccount: treasuryPaymentAccount.address,
+    })
+    .rpc();
+
+  let order = await program.account.order.fetch(orderAccount);
+  return {
+    order,
+    orderAccount,
+    orderTokenAccount,
+  };
+};
+
+
+const fillTokenOrder = async (
+  mintKey: anchor.web3.PublicKey,
+  ownerKey: anchor.web3.PublicKey,
+  paymentMint: anchor.web3.PublicKey,
+  buyer,
+  creatorKeys: anchor.web3.PublicKey[] = [ownerKey],
+  listingId: number = 0
+) => {
+  let program = await programForUser(buyer);
+  const [orderAccount] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      Buffer.from("order"),
+      mintKey.toBytes(),
+      ownerKey.toBytes(),
+      listingSeed(listingId),
+    ],
+    program.programId
+  );
+
+  const orderTokenAccount = await getAssociatedTokenAddress(
+    mintKey,
+    orderAccount,
+    true
+  );
+  const buyerTokenAccount = await createAssociateTokenAccount(mintKey, buyer);
+  const creatorPaymentAccounts = await Promise.all(
+    creatorKeys.map((creatorKey) =>
+      getAssociatedTokenAddress(paymentMint, creatorKey)
+    )
+  );
+
+  await program.methods
+    .fillTokenOrder()
+    .accounts({
+      order: orderAccount,
+      orderTokenAccount: orderTokenAccount,
+      mintKey: mintKey,
+      creator: ownerKey,
+      creatorPaymentAccount: await getAssociatedTokenAddress(
+        paymentMint,
+        ownerKey
+      ),
+      buyer: buyer.key.publicKey,
+      buyerTokenAccount: buyerTokenAccount,
+      buyerPaymentAccount: await getAssociatedTokenAddress(
+        paymentMint,
+        buyer.key.publicKey
+      ),
+      metadata: getMetadataAddress(mintKey),
+    })
+    .remainingAccounts(creatorAccounts(creatorPaymentAccounts))
+    .rpc();
+
+  return buyerTokenAccount;
+};
+
+
+
+
+describe("unicus_ts", () => {
+
+  before(async () => {
+    await initializeMarketplace(200, 1000);
+  });
+
+
+  it("rejects a listing fee routed to a foreign treasury", async () => {
+    let user = await createUser(1);
+    const mintKey = await createMint(user);
+    const tokenAccount = await mintToken(mintKey, user);
+
+    let program = await programForUser(user);
+    const [orderAccount] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        Buffer.from("order"),
+        mintKey.publicKey.toBytes(),
+        user.key.publicKey.toBytes(),
+        listingSeed(0),
+      ],
+      program.programId
+    );
+    const orderTokenAccount = await getAssociatedTokenAddress(
+      mintKey.publicKey,
+      orderAccount,
+      true
+    );
+
+    try {
+      await program.methods
+        .createOrder(
+          "This is test order.",
+          new BN(1 * LAMPORTS_PER_SOL),
+          new BN(0),
+          null
+        )
+        .accounts({
+          order: orderAccount,
+          orderTokenAccount: orderTokenAccount,
+          mintKey: mintKey.publicKey,
+          creator: user.key.publicKey,
+          creatorTokenAccount: tokenAccount,
+          marketplace: marketplaceAccount,
+          treasuryAccount: user.key.publicKey,
+        })
+        .rpc();
+      assert(false, "Create order should fail with a treasury not set on the marketplace.");
+    } catch (err) {
+      expect(err).to.be.instanceOf(AnchorError);
+      const anchorError = err as AnchorError;
+      expect(anchorError.error.errorCode.code).equals("InvalidTreasury");
+    }
+  });
+
+
+  it("fill order rejects a royalty receiver that is not the creator", async () => {
+    let user = await createUser(1);
+    let buyer = await createUser(2);
+
+    const { mintKey, tokenAccount } = await mintNft(
+      user,
+      [{ address: user.key.publicKey, share: 100 }],
+      500
+    );
+    await createOrder(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test order.",
+      1 * LAMPORTS_PER_SOL
+    );
+
+    try {
+      await fillOrder(
+        mintKey.publicKey,
+        user.key.publicKey,
+        buyer,
+        [buyer.key.publicKey]
+      );
+      assert(false, "Fill order should fail when royalties are redirected.");
+    } catch (err) {
+      expect(err).to.be.instanceOf(AnchorError);
+      const anchorError = err as AnchorError;
+      expect(anchorError.error.errorCode.code).equals("InvalidRoyaltyReceiver");
+    }
+  });
+
+
+  it("fill order splits royalties across creators", async () => {
+    let user = await createUser(1);
+    let artist = await createUser(1);
+    let buyer = await createUser(2);
+
+    const { mintKey, tokenAccount } = await mintNft(
+      user,
+      [
+        { address: user.key.publicKey, share: 60 },
+        { address: artist.key.publicKey, share: 40 },
+      ],
+      500
+    );
+    await createOrder(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test order.",
+      1 * LAMPORTS_PER_SOL
+    );
+
+    let artistBalance = await getAccountBalance(artist.key.publicKey);
+    await fillOrder(mintKey.publicKey, user.key.publicKey, buyer, [
+      user.key.publicKey,
+      artist.key.publicKey,
+    ]);
+
+    // 5% royalty on 1 SOL, 40% of which goes to the second creator.
+    expect(await getAccountBalance(artist.key.publicKey)).closeTo(
+      artistBalance + 0.02,
+      0.000001
+    );
+  });
+
+
+  it("mint rejects a royalty above the marketplace maximum", async () => {
+    let user = await createUser(1);
+
+    try {
+      await mintNft(user, [{ address: user.key.publicKey, share: 100 }], 1001);
+      assert(false, "Mint should fail when royalty exceeds 1000 basis points.");
+    } catch (err) {
+      expect(err).to.be.instanceOf(AnchorError);
+      const anchorError = err as AnchorError;
+      expect(anchorError.error.errorCode.code).equals("RoyaltyExceeded");
+    }
+  });
+
+
+  it("mint rejects creator shares that do not sum to 100", async () => {
+    let user = await createUser(1);
+
+    try {
+      await mintNft(
+        user,
+        [
+          { address: user.key.publicKey, share: 60 },
+          { address: user.key.publicKey, share: 60 },
+        ],
+        500
+      );
+      assert(false, "Mint should fail when creator shares exceed 100.");
+    } catch (err) {
+      expect(err).to.be.instanceOf(AnchorError);
+      const anchorError = err as AnchorError;
+      expect(anchorError.error.errorCode.code).equals("InvalidCreatorShares");
+    }
+  });
+
+
+  it("accept offer on an unlisted nft", async () => {
+    let user = await createUser(1);
+    let buyer = await createUser(2);
+
+    const { mintKey, tokenAccount } = await mintNft(
+      user,
+      [{ address: user.key.publicKey, share: 100 }],
+      500
+    );
+    const buyerTokenAccount = await createAssociateTokenAccount(
+      mintKey.publicKey,
+      buyer
+    );
+
+    let offer = await makeOffer(mintKey.publicKey, buyer, 1 * LAMPORTS_PER_SOL);
+    expect(offer.offer.price.toNumber()).equals(1 * LAMPORTS_PER_SOL);
+
+    await acceptOffer(
+      mintKey.publicKey,
+      user,
+      tokenAccount,
+      buyer.key.publicKey,
+      buyerTokenAccount,
+      [user.key.publicKey]
+    );
+
+    let balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      buyerTokenAccount
+    );
+    expect(balance.value.uiAmount).equals(1);
+
+    let offerAccountInfo = await mainProgram.provider.connection.getAccountInfo(
+      offer.offerAccount
+    );
+    expect(offerAccountInfo).equals(null);
+  });
+
+
+  it("cancel offer refunds the buyer", async () => {
+    let user = await createUser(1);
+    let buyer = await createUser(2);
+
+    const { mintKey } = await mintNft(
+      user,
+      [{ address: user.key.publicKey, share: 100 }],
+      500
+    );
+
+    let buyerBalance = await getAccountBalance(buyer.key.publicKey);
+    await makeOffer(mintKey.publicKey, buyer, 1 * LAMPORTS_PER_SOL);
+    let offerAccount = await cancelOffer(mintKey.publicKey, buyer);
+
+    expect(await getAccountBalance(buyer.key.publicKey)).closeTo(
+      buyerBalance,
+      0.0001
+    );
+    let offerAccountInfo = await mainProgram.provider.connection.getAccountInfo(
+      offerAccount
+    );
+    expect(offerAccountInfo).equals(null);
+  });
+
+
+  it("collection offer rejects an nft outside the collection", async () => {
+    let user = await createUser(1);
+    let buyer = await createUser(3);
+
+    const collectionMintKey = await createMint(buyer);
+    let collectionOffer = await makeCollectionOffer(
+      collectionMintKey.publicKey,
+      buyer,
+      1 * LAMPORTS_PER_SOL,
+      2
+    );
+    expect(collectionOffer.collectionOffer.quantity.toNumber()).equals(2);
+
+    const { mintKey, tokenAccount } = await mintNft(
+      user,
+      [{ address: user.key.publicKey, share: 100 }],
+      500
+    );
+
+    try {
+      await acceptCollectionOffer(
+        collectionOffer.collectionOfferAccount,
+        mintKey.publicKey,
+        user,
+        tokenAccount,
+        buyer.key.publicKey,
+        [user.key.publicKey]
+      );
+      assert(false, "Accept should fail for an nft without a verified collection.");
+    } catch (err) {
+      expect(err).to.be.instanceOf(AnchorError);
+      const anchorError = err as AnchorError;
+      expect(anchorError.error.errorCode.code).equals("InvalidCollection");
+    }
+  });
+
+
+  it("fill token order pays seller and creators in spl tokens", async () => {
+    let user = await createUser(1);
+    let buyer = await createUser(1);
+
+    const paymentMint = await createPaymentMint(
+      provider.connection,
+      user.key,
+      user.key.publicKey,
+      null,
+      6
+    );
+    const userPaymentAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      user.key,
+      paymentMint,
+      user.key.publicKey
+    );
+    const buyerPaymentAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      buyer.key,
+      paymentMint,
+      buyer.key.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      user.key,
+      paymentMint,
+      userPaymentAccount.address,
+      user.key,
+      1_000_000
+    );
+    await mintTo(
+      provider.connection,
+      user.key,
+      paymentMint,
+      buyerPaymentAccount.address,
+      user.key,
+      100_000_000
+    );
+
+    const { mintKey, tokenAccount } = await mintNft(
+      user,
+      [{ address: user.key.publicKey, share: 100 }],
+      500
+    );
+    let order = await createTokenOrder(
+      user,
+      mintKey,
+      tokenAccount,
+      paymentMint,
+      "This is test order.",
+      10_000_000
+    );
+    expect(order.order.paymentMint.toString()).equals(paymentMint.toString());
+
+    let buyerTokenAccount = await fillTokenOrder(
+      mintKey.publicKey,
+      user.key.publicKey,
+      paymentMint,
+      buyer
+    );
+
+    let balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      buyerTokenAccount
+    );
+    expect(balance.value.uiAmount).equals(1);
+
+    // 1 token fee paid on listing, the full price back on sale as seller and sole creator.
+    balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      userPaymentAccount.address
+    );
+    expect(Number(balance.value.amount)).equals(1_000_000 - 200_000 + 10_000_000);
+  });
+
+
+  it("an escrowed nft cannot be auctioned while listed", async () => {
+    let user = await createUser(1);
+
+    const { mintKey, tokenAccount } = await mintNft(
+      user,
+      [{ address: user.key.publicKey, share: 100 }],
+      500
+    );
+    await createOrder(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test order.",
+      1 * LAMPORTS_PER_SOL,
+      1
+    );
+
+    try {
+      await createAuction(
+        user,
+        mintKey,
+        user.key,
+        tokenAccount,
+        "This is test auction.",
+        1 * LAMPORTS_PER_SOL,
+        0,
+        Math.floor(Date.now() / 1000) + 3600,
+        2
+      );
+      assert(false, "Create auction should fail while the nft is escrowed.");
+    } catch (err) {
+      expect(err).to.be.instanceOf(AnchorError);
+      const anchorError = err as AnchorError;
+      expect(anchorError.error.errorCode.code).equals("TokenNotHeld");
+    }
+
+    //
+    // Relisting under a new listing id works once the order is cancelled.
+    //
+    await cancelOrder(user, mintKey, user.key, tokenAccount, 1);
+    let order = await createOrder(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test order.",
+      2 * LAMPORTS_PER_SOL,
+      3
+    );
+    expect(order.order.listingId.toNumber()).equals(3);
+  });
+
+
+  it("update order changes price and memo in place", async () => {
+    let user = await createUser(2);
+
+    const { mintKey, tokenAccount } = await mintNft(
+      user,
+      [{ address: user.key.publicKey, share: 100 }],
+      500
+    );
+    await createOrder(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test order.",
+      1 * LAMPORTS_PER_SOL
+    );
+
+    let marketplace = await mainProgram.account.marketplace.fetch(
+      marketplaceAccount
+    );
+    let treasuryBalance = await getAccountBalance(marketplace.treasury);
+
+    let order = await updateOrder(
+      user,
+      mintKey.publicKey,
+      "This is an updated, longer test order memo.",
+      2 * LAMPORTS_PER_SOL
+    );
+    expect(order.price.toNumber()).equals(2 * LAMPORTS_PER_SOL);
+    expect(order.memo).equals("This is an updated, longer test order memo.");
+
+    // Only the fee on the 1 SOL increase is charged.
+    expect(await getAccountBalance(marketplace.treasury)).closeTo(
+      treasuryBalance + 0.02,
+      0.000001
+    );
+  });
+
+
+  it("expired orders cannot be filled and can be closed by anyone", async () => {
+    let user = await createUser(1);
+    let buyer = await createUser(2);
+
+    const { mintKey, tokenAccount } = await mintNft(
+      user,
+      [{ address: user.key.publicKey, share: 100 }],
+      500
+    );
+    await createOrder(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test order.",
+      1 * LAMPORTS_PER_SOL,
+      0,
+      Math.floor(Date.now() / 1000) + 2
+    );
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+
+    try {
+      await fillOrder(mintKey.publicKey, user.key.publicKey, buyer);
+      assert(false, "Fill order should fail once the order has expired.");
+    } catch (err) {
+      expect(err).to.be.instanceOf(AnchorError);
+      const anchorError = err as AnchorError;
+      expect(anchorError.error.errorCode.code).equals("OrderExpired");
+    }
+
+    let orderAccount = await closeExpiredOrder(
+      buyer,
+      mintKey.publicKey,
+      user.key.publicKey
+    );
+    let balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      tokenAccount
+    );
+    expect(balance.value.uiAmount).equals(1);
+    expect(
+      await mainProgram.provider.connection.getAccountInfo(orderAccount)
+    ).equals(null);
+  });
+
+
+  it("only the admin can update the marketplace", async () => {
+    let user = await createUser(1);
+    let program = await programForUser(user);
+
+    try {
+      await program.methods
+        .setFee(0)
+        .accounts({
+          marketplace: marketplaceAccount,
+          admin: user.key.publicKey,
+        })
+        .rpc();
+      assert(false, "Set fee should fail for a non-admin signer.");
+    } catch (err) {
+      expect(err).to.be.instanceOf(AnchorError);
+      const anchorError = err as AnchorError;
+      expect(anchorError.error.errorCode.code).equals("Unauthorized");
+    }
+  });
+
+
+  it("emits events when an order is listed and filled", async () => {
+    let user = await createUser(1);
+    let buyer = await createUser(2);
+
+    let events = [];
+    const listeners = ["OrderCreated", "OrderFilled"].map((name) =>
+      mainProgram.addEventListener(name, (event) => events.push({ name, event }))
+    );
+
+    const { mintKey, tokenAccount } = await mintNft(
+      user,
+      [{ address: user.key.publicKey, share: 100 }],
+      500
+    );
+    await createOrder(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test order.",
+      1 * LAMPORTS_PER_SOL
+    );
+    await fillOrder(mintKey.publicKey, user.key.publicKey, buyer);
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+
+    for (const listener of listeners) {
+      await mainProgram.removeEventListener(listener);
+    }
+
+    const created = events.find(
+      ({ name, event }) =>
+        name == "OrderCreated" && event.mint.equals(mintKey.publicKey)
+    ).event;
+    expect(created.creator.toString()).equals(user.key.publicKey.toString());
+    expect(created.price.toNumber()).equals(1 * LAMPORTS_PER_SOL);
+    expect(created.fee.toNumber()).equals(0.02 * LAMPORTS_PER_SOL);
+
+    const filled = events.find(
+      ({ name, event }) =>
+        name == "OrderFilled" && event.mint.equals(mintKey.publicKey)
+    ).event;
+    expect(filled.buyer.toString()).equals(buyer.key.publicKey.toString());
+    expect(filled.price.toNumber()).equals(1 * LAMPORTS_PER_SOL);
+    expect(filled.royalty.toNumber()).equals(0.05 * LAMPORTS_PER_SOL);
+    expect(filled.fee.toNumber()).equals(0);
+  });
+
+
+  it("emits events when an offer is made and accepted", async () => {
+    let user = await createUser(1);
+    let buyer = await createUser(2);
+
+    let events = [];
+    const listeners = ["OfferMade", "OrderFilled"].map((name) =>
+      mainProgram.addEventListener(name, (event) => events.push({ name, event }))
+    );
+
+    const { mintKey, tokenAccount } = await mintNft(
+      user,
+      [{ address: user.key.publicKey, share: 100 }],
+      500
+    );
+    const buyerTokenAccount = await createAssociateTokenAccount(
+      mintKey.publicKey,
+      buyer
+    );
+    let offer = await makeOffer(mintKey.publicKey, buyer, 1 * LAMPORTS_PER_SOL);
+    await acceptOffer(
+      mintKey.publicKey,
+      user,
+      tokenAccount,
+      buyer.key.publicKey,
+      buyerTokenAccount,
+      [user.key.publicKey]
+    );
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+
+    for (const listener of listeners) {
+      await mainProgram.removeEventListener(listener);
+    }
+
+    const made = events.find(
+      ({ name, event }) =>
+        name == "OfferMade" && event.mint.equals(mintKey.publicKey)
+    ).event;
+    expect(made.offer.toString()).equals(offer.offerAccount.toString());
+    expect(made.buyer.toString()).equals(buyer.key.publicKey.toString());
+    expect(made.price.toNumber()).equals(1 * LAMPORTS_PER_SOL);
+
+    const filled = events.find(
+      ({ name, event }) =>
+        name == "OrderFilled" && event.mint.equals(mintKey.publicKey)
+    ).event;
+    expect(filled.order.toString()).equals(offer.offerAccount.toString());
+    expect(filled.seller.toString()).equals(user.key.publicKey.toString());
+    expect(filled.buyer.toString()).equals(buyer.key.publicKey.toString());
+    expect(filled.royalty.toNumber()).equals(0.05 * LAMPORTS_PER_SOL);
+    expect(filled.fee.toNumber()).equals(0.02 * LAMPORTS_PER_SOL);
+  });
+
+
+/*
+  
+  it("create order", async () => {
+    let user = await createUser(1);
+    console.log("User Account: ", user.key.publicKey.toString());
+
+    //
+    // Create mint.
+    //
+    const mintKey = await createMint(user);
+    console.log("Mint key: ", mintKey.publicKey.toString());
+
+    //
+    // Mint a token.
+    //
+    const tokenAccount = await mintToken(mintKey, user);
+    console.log("Owner Token Account: ", tokenAccount.toString());
+
+    var balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      tokenAccount
+    );
+    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
+    expect(balance.value.uiAmount).equals(1);
+
+    //
+    // Create an order.
+    //
+    let order = await createOrder(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test order.",
+      1 * LAMPORTS_PER_SOL
+    );
+    console.log("[Order Created]");
+    console.log("Order Account: ", order.orderAccount.toString());
+    console.log("Order Token Account: ", order.orderTokenAccount.toString());
+    console.log("Order.creator: ", order.order.creator.toString());
+    console.log("Order.mintKey: ", order.order.mintKey.toString());
+    console.log("Order.memo: ", order.order.memo);
+    console.log("Order.price: ", order.order.price.toNumber());
+
+    //
+    // Check result.
+    //
+    balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      tokenAccount
+    );
+    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
+    expect(balance.value.uiAmount).equals(0);
+
+    balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      order.orderTokenAccount
+    );
+    console.log("Order Token Account Balance: ", balance.value.uiAmount);
+    expect(balance.value.uiAmount).equals(1);
+
+    expect(order.order.creator.toString()).equals(
+      user.key.publicKey.toString()
+    );
+    expect(order.order.mintKey.toString()).equals(mintKey.publicKey.toString());
+    expect(order.order.memo).equals("This is test order.");
+    expect(order.order.price.toNumber()).equals(1 * LAMPORTS_PER_SOL);
+  });
+
+
+
+  it("cancel order", async () => {
+    let user = await createUser(1);
+    console.log("User Account: ", user.key.publicKey.toString());
+
+    //
+    // Create mint.
+    //
+    const mintKey = await createMint(user);
+    console.log("Mint key: ", mintKey.publicKey.toString());
+
+    //
+    // Mint a token.
+    //
+    const tokenAccount = await mintToken(mintKey, user);
+    console.log("Owner Token Account: ", tokenAccount.toString());
+
+    var balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      tokenAccount
+    );
+    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
+    expect(balance.value.uiAmount).equals(1);
+
+    //
+    // Create an order.
+    //
+    var order = await createOrder(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test order.",
+      1 * LAMPORTS_PER_SOL
+    );
+    console.log("[Order Created]");
+    console.log("Order Account: ", order.orderAccount.toString());
+    console.log("Order Token Account: ", order.orderTokenAccount.toString());
+
+    //
+    // Check result.
+    //
+    balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      tokenAccount
+    );
+    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
+    expect(balance.value.uiAmount).equals(0);
+
+    balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      order.orderTokenAccount
+    );
+    console.log("Order Token Account Balance: ", balance.value.uiAmount);
+    expect(balance.value.uiAmount).equals(1);
+
+    //
+    // Cancel order.
+    //
+    //@ts-ignore
+    order = await cancelOrder(user, mintKey, user.key, tokenAccount);
+    console.log("[Order Canceled]");
+
+    //
+    // Check result.
+    //
+    balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      tokenAccount
+    );
+    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
+    expect(balance.value.uiAmount).equals(1);
+
+    let orderAccountInfo = await mainProgram.provider.connection.getAccountInfo(
+      order.orderAccount
+    );
+    console.log("Order Account: ", orderAccountInfo);
+    expect(orderAccountInfo).equals(null);
+
+    try {
+      balance = await mainProgram.provider.connection.getTokenAccountBalance(
+        order.orderTokenAccount
+      );
+      assert(false, "Order token account should be closed by cancel program.");
+    } catch (e) {
+      expect(e.toString()).contain("could not find account");
+    }
+  });
+
+
+
+  it("fill order", async () => {
+    let user = await createUser(1);
+    console.log("User Account: ", user.key.publicKey.toString());
+
+    let buyer = await createUser(2);
+    console.log("Buyer Account: ", buyer.key.publicKey.toString());
+
+    //
+    // Create mint.
+    //
+    const mintKey = await createMint(user);
+    console.log("Mint key: ", mintKey.publicKey.toString());
+
+    //
+    // Mint a token.
+    //
+    const tokenAccount = await mintToken(mintKey, user);
+    console.log("Owner Token Account: ", tokenAccount.toString());
+
+    var balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      tokenAccount
+    );
+    expect(balance.value.uiAmount).equals(1);
+
+    //
+    // Create an order.
+    //
+    var order = await createOrder(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test order.",
+      1 * LAMPORTS_PER_SOL
+    );
+    console.log("[Order Created]");
+    console.log("Order Account: ", order.orderAccount.toString());
+    console.log("Order Token Account: ", order.orderTokenAccount.toString());
+
+    console.log(
+      "Creator Account Balance: ",
+      await getAccountBalance(user.key.publicKey)
+    );
+    console.log(
+      "Buyer Account Balance: ",
+      await getAccountBalance(buyer.key.publicKey)
+    );
+
+    //
+    // Fill order.
+    //
+    let buyerTokenAccount = await fillOrder(
+      mintKey.publicKey,
+      user.key.publicKey,
+      buyer
+    );
+    console.log("[Order Filled]");
+    console.log("Buyer Token Account: ", buyerTokenAccount.toString());
+    console.log(
+      "Creator Account Balance: ",
+      await getAccountBalance(user.key.publicKey)
+    );
+    console.log(
+      "Buyer Account Balance: ",
+      await getAccountBalance(buyer.key.publicKey)
+    );
+
+    //
+    // Check result.
+    //
+    balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      tokenAccount
+    );
+    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
+    expect(balance.value.uiAmount).equals(0);
+
+    balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      buyerTokenAccount
+    );
+    console.log("Buyer Token Account Balance: ", balance.value.uiAmount);
+    expect(balance.value.uiAmount).equals(1);
+
+    let orderAccountInfo = await mainProgram.provider.connection.getAccountInfo(
+      order.orderAccount
+    );
+    console.log("Order Account: ", orderAccountInfo);
+    expect(orderAccountInfo).equals(null);
+
+    try {
+      balance = await mainProgram.provider.connection.getTokenAccountBalance(
+        order.orderTokenAccount
+      );
+      assert(false, "Order token account should be closed by fill program.");
+    } catch (e) {
+      expect(e.toString()).contain("could not find account");
+    }
+  });
+
+
+  it("fill order insufficient money error check", async () => {
+    let user = await createUser(1);
+    console.log("User Account: ", user.key.publicKey.toString());
+
+    let buyer = await createUser(1);
+    console.log("Buyer Account: ", buyer.key.publicKey.toString());
+
+    //
+    // Create mint.
+    //
+    const mintKey = await createMint(user);
+    console.log("Mint key: ", mintKey.publicKey.toString());
+
+    //
+    // Mint a token.
+    //
+    const tokenAccount = await mintToken(mintKey, user);
+    console.log("Owner Token Account: ", tokenAccount.toString());
+
+    //
+    // Create an order.
+    //
+    var order = await createOrder(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test order.",
+      2 * LAMPORTS_PER_SOL
+    );
+    console.log("[Order Created]");
+    console.log("Order Account: ", order.orderAccount.toString());
+    console.log("Order Token Account: ", order.orderTokenAccount.toString());
+
+    console.log(
+      "Creator Account Balance: ",
+      await getAccountBalance(user.key.publicKey)
+    );
+    console.log(
+      "Buyer Account Balance: ",
+      await getAccountBalance(buyer.key.publicKey)
+    );
+
+    try {
+      //
+      // Fill order.
+      //
+      await fillOrder(mintKey.publicKey, user.key.publicKey, buyer);
+      assert(
+        false,
+        "Fill order should be failed because buyer has no enough money."
+      );
+    } catch (err) {
+      expect(err).to.be.instanceOf(AnchorError);
+      const anchorError = err as AnchorError;
+      expect(anchorError.error.errorCode.number).equals(6005);
+      expect(anchorError.error.errorCode.code).equals("InsufficientMoney");
+    }
+  });
+
+
+  
+  it("create auction", async () => {
+    let user = await createUser(1); 
+    console.log("User Account: ", user.key.publicKey.toString());
+    //
+    // Create mint.
+    //
+    const mintKey = await createMint(user);
+    console.log("Mint key: ", mintKey.publicKey.toString());
+    //
+    // Mint a token.
+    //
+    const tokenAccount = await mintToken(mintKey, user);
+    console.log("Owner Token Account: ", tokenAccount.toString());
+    var balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      tokenAccount
+    );
+    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
+    expect(balance.value.uiAmount).equals(1);
+    //
+    // Create an auction.
+    //
+    let auction = await createAuction(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test order.",
+      1 * LAMPORTS_PER_SOL,
+      1659518580,
+      1659528580
+    );
+    console.log("[Auction Created]");
+    console.log("Auction Account: ", auction.auctionAccount.toString());
+    console.log("Auction Token Account: ", auction.auctionTokenAccount.toString());
+    console.log("Auction.creator: ", auction.auction.creator.toString());
+    console.log("Auction.mintKey: ", auction.auction.mintKey.toString());
+    console.log("Auction.memo: ", auction.auction.memo);
+    console.log("Auction.price: ", auction.auction.price.toNumber());
+    console.log("Auction.start_time: ", auction.auction.startTime.toNumber());
+    console.log("Auction.end_time: ", auction.auction.endTime.toNumber());
+    //
+    // Check result.
+    //
+    balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      tokenAccount
+    );
+    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
+    expect(balance.value.uiAmount).equals(0);
+    balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      auction.auctionTokenAccount
+    );
+    console.log("Auction Token Account Balance: ", balance.value.uiAmount);
+    expect(balance.value.uiAmount).equals(1);
+    expect(auction.auction.creator.toString()).equals(
+      user.key.publicKey.toString()
+    );
+    expect(auction.auction.mintKey.toString()).equals(mintKey.publicKey.toString());
+    expect(auction.auction.memo).equals("This is test order.");
+    expect(auction.auction.price.toNumber()).equals(1 * LAMPORTS_PER_SOL);
+  });
+
+
+
+  
+  it("bid auction", async () => {
+    let user = await createUser(1);
+    console.log("User Account: ", user.key.publicKey.toString());
+
+    let bidder = await createUser(2);
+    console.log("Bidder Account: ", bidder.key.publicKey.toString());
+
+    //
+    // Create mint.
+    //
+    const mintKey = await createMint(user);
+    console.log("Mint key: ", mintKey.publicKey.toString());
+
+    //
+    // Mint a token.
+    //
+    const tokenAccount = await mintToken(mintKey, user);
+    console.log("Owner Token Account: ", tokenAccount.toString());
+
+    var balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      tokenAccount
+    );
+    expect(balance.value.uiAmount).equals(1);
+
+    //
+    // Create an order. 1663319647  1663146847
+    //
+    let auction = await createAuction(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test auction.",
+      1 * LAMPORTS_PER_SOL,
+      1663146847,
+      1663319647
+    );
+    console.log("[Auction Created]");
+    console.log("AUction Account: ", auction.auctionAccount.toString());
+    console.log("Auction Token Account: ", auction.auctionTokenAccount.toString());
+
+    console.log(
+      "Creator Account Balance: ",
+      await getAccountBalance(user.key.publicKey)
+    );
+    console.log(
+      "Bidder Account Balance: ",
+      await getAccountBalance(bidder.key.publicKey)
+    );
+
+    console.log("refund receiver", auction.auction.refundReceiver.toString());
+
+    //
+    // Fill order.
+    //
+    let bid = await bidAuction(
+      mintKey.publicKey,
+      user.key.publicKey,
+      auction.auction.refundReceiver,
+      bidder,
+      1 * LAMPORTS_PER_SOL
+    );
+    console.log("[Bid Filled]");
+    
+    console.log(
+      "Creator Account Balance: ",
+      await getAccountBalance(user.key.publicKey)
+    );
+    console.log(
+      "Bidder Account Balance: ",
+      await getAccountBalance(bidder.key.publicKey)
+    );
+
+    //
+    // Check result.
+    //
+    console.log("bid winner is", bid.auction.refundReceiver.toString());
+    console.log("new auction price is", bid.auction.price.toNumber());
+
+  });
+
+
+  it("escrows bids in the bid vault and refunds outbid bidders", async () => {
+    let user = await createUser(1);
+    let firstBidder = await createUser(3);
+    let secondBidder = await createUser(3);
+
+    const mintKey = await createMint(user);
+    const tokenAccount = await mintToken(mintKey, user);
+
+    let auction = await createAuction(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test auction.",
+      1 * LAMPORTS_PER_SOL,
+      Math.floor(Date.now() / 1000) - 60,
+      Math.floor(Date.now() / 1000) + 3600
+    );
+    const bidVault = getBidVaultAddress(auction.auctionAccount);
+    const auctionBalance = await getAccountBalance(auction.auctionAccount);
+
+    await bidAuction(
+      mintKey.publicKey,
+      user.key.publicKey,
+      auction.auction.refundReceiver,
+      firstBidder,
+      1 * LAMPORTS_PER_SOL
+    );
+    const outbidBalance = await getAccountBalance(firstBidder.key.publicKey);
+
+    let bid = await bidAuction(
+      mintKey.publicKey,
+      user.key.publicKey,
+      firstBidder.key.publicKey,
+      secondBidder,
+      2 * LAMPORTS_PER_SOL
+    );
+    expect(bid.auction.refundReceiver.toString()).equals(
+      secondBidder.key.publicKey.toString()
+    );
+
+    let vault = await mainProgram.account.bidVault.fetch(bidVault);
+    expect(vault.amount.toNumber()).equals(2 * LAMPORTS_PER_SOL);
+    expect(await getAccountBalance(auction.auctionAccount)).equals(auctionBalance);
+    expect(await getAccountBalance(firstBidder.key.publicKey)).to.be.closeTo(
+      outbidBalance + 1,
+      1e-9
+    );
+
+    await cancelAuction(
+      user,
+      mintKey.publicKey,
+      user.key,
+      secondBidder.key.publicKey,
+      tokenAccount
+    );
+
+    let vaultInfo = await mainProgram.provider.connection.getAccountInfo(bidVault);
+    expect(vaultInfo).to.be.null;
+  });
+
+
+
+  
+  it("rejects bids below the minimum increment", async () => {
+    let user = await createUser(1);
+    let firstBidder = await createUser(3);
+    let secondBidder = await createUser(3);
+
+    const mintKey = await createMint(user);
+    const tokenAccount = await mintToken(mintKey, user);
+
+    // 10% or 0.2 SOL, whichever is larger.
+    await createAuction(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test auction.",
+      1 * LAMPORTS_PER_SOL,
+      Math.floor(Date.now() / 1000) - 60,
+      Math.floor(Date.now() / 1000) + 3600,
+      0,
+      1000,
+      0.2 * LAMPORTS_PER_SOL
+    );
+    await bidAuction(
+      mintKey.publicKey,
+      user.key.publicKey,
+      user.key.publicKey,
+      firstBidder,
+      1 * LAMPORTS_PER_SOL
+    );
 
-    //
-    // Create mint.
-    //
-    const mintKey = await createMint(user);
-    console.log("Mint key: ", mintKey.publicKey.toString());
+    let program = await programForUser(secondBidder);
+    const [auctionAccount] = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("auction"),
+        mintKey.publicKey.toBytes(),
+        user.key.publicKey.toBytes(),
+        listingSeed(0),
+      ],
+      program.programId
+    );
 
-    //
-    // Mint a token.
-    //
+    for (const price of [1, 1.1]) {
+      try {
+        await program.methods
+          .bid(new BN(price * LAMPORTS_PER_SOL))
+          .accounts({
+            auction: auctionAccount,
+            bidVault: getBidVaultAddress(auctionAccount),
+            mintKey: mintKey.publicKey,
+            creator: user.key.publicKey,
+            bidder: secondBidder.key.publicKey,
+            refundReceiver: firstBidder.key.publicKey,
+          })
+          .rpc();
+        assert(false, "Bid should fail below the minimum increment.");
+      } catch (err) {
+        expect(err).to.be.instanceOf(AnchorError);
+        const anchorError = err as AnchorError;
+        expect(anchorError.error.errorCode.code).equals("InsufficientMoney");
+      }
+    }
+
+    let bid = await bidAuction(
+      mintKey.publicKey,
+      user.key.publicKey,
+      firstBidder.key.publicKey,
+      secondBidder,
+      1.2 * LAMPORTS_PER_SOL
+    );
+    expect(bid.auction.refundReceiver.toString()).equals(
+      secondBidder.key.publicKey.toString()
+    );
+  });
+
+
+  it("returns the nft and the bid when the revealed reserve is not met", async () => {
+    let user = await createUser(1);
+    let bidder = await createUser(3);
+
+    const mintKey = await createMint(user);
     const tokenAccount = await mintToken(mintKey, user);
-    console.log("Owner Token Account: ", tokenAccount.toString());
 
-    var balance = await mainProgram.provider.connection.getTokenAccountBalance(
-      tokenAccount
+    const reservePrice = new BN(2 * LAMPORTS_PER_SOL);
+    const salt = anchor.web3.Keypair.generate().publicKey.toBytes();
+    const reserveHash = Array.from(
+      createHash("sha256")
+        .update(reservePrice.toArrayLike(Buffer, "le", 8))
+        .update(salt)
+        .digest()
     );
-    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
-    expect(balance.value.uiAmount).equals(1);
 
-    //
-    // Create an order.
-    //
-    let order = await createOrder(
+    let auction = await createAuction(
       user,
       mintKey,
       user.key,
       tokenAccount,
-      "This is test order.",
+      "This is test auction.",
+      1 * LAMPORTS_PER_SOL,
+      Math.floor(Date.now() / 1000) - 60,
+      Math.floor(Date.now() / 1000) + 2,
+      0,
+      0,
+      0,
+      0,
+      reserveHash
+    );
+    await bidAuction(
+      mintKey.publicKey,
+      user.key.publicKey,
+      user.key.publicKey,
+      bidder,
       1 * LAMPORTS_PER_SOL
     );
-    console.log("[Order Created]");
-    console.log("Order Account: ", order.orderAccount.toString());
-    console.log("Order Token Account: ", order.orderTokenAccount.toString());
-    console.log("Order.creator: ", order.order.creator.toString());
-    console.log("Order.mintKey: ", order.order.mintKey.toString());
-    console.log("Order.memo: ", order.order.memo);
-    console.log("Order.price: ", order.order.price.toNumber());
-
-    //
-    // Check result.
-    //
-    balance = await mainProgram.provider.connection.getTokenAccountBalance(
+    const bidderBalance = await getAccountBalance(bidder.key.publicKey);
+
+    await mainProgram.methods
+      .revealReserve(reservePrice, Array.from(salt))
+      .accounts({
+        auction: auction.auctionAccount,
+        mintKey: mintKey.publicKey,
+        creator: user.key.publicKey,
+      })
+      .rpc();
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+
+    await auctionResolve(
+      user.key,
+      mintKey.publicKey,
+      user.key.publicKey,
+      bidder.key.publicKey,
       tokenAccount
     );
-    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
-    expect(balance.value.uiAmount).equals(0);
 
-    balance = await mainProgram.provider.connection.getTokenAccountBalance(
-      order.orderTokenAccount
+    let balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      tokenAccount
     );
-    console.log("Order Token Account Balance: ", balance.value.uiAmount);
     expect(balance.value.uiAmount).equals(1);
-
-    expect(order.order.creator.toString()).equals(
-      user.key.publicKey.toString()
+    expect(await getAccountBalance(bidder.key.publicKey)).to.be.closeTo(
+      bidderBalance + 1,
+      1e-9
     );
-    expect(order.order.mintKey.toString()).equals(mintKey.publicKey.toString());
-    expect(order.order.memo).equals("This is test order.");
-    expect(order.order.price.toNumber()).equals(1 * LAMPORTS_PER_SOL);
   });
 
 
-
-  it("cancel order", async () => {
+  it("refuses to resolve an auction before its hidden reserve is revealed", async () => {
     let user = await createUser(1);
-    console.log("User Account: ", user.key.publicKey.toString());
+    let bidder = await createUser(3);
 
-    //
-    // Create mint.
-    //
     const mintKey = await createMint(user);
-    console.log("Mint key: ", mintKey.publicKey.toString());
-
-    //
-    // Mint a token.
-    //
     const tokenAccount = await mintToken(mintKey, user);
-    console.log("Owner Token Account: ", tokenAccount.toString());
 
-    var balance = await mainProgram.provider.connection.getTokenAccountBalance(
-      tokenAccount
+    const reservePrice = new BN(2 * LAMPORTS_PER_SOL);
+    const salt = anchor.web3.Keypair.generate().publicKey.toBytes();
+    const reserveHash = Array.from(
+      createHash("sha256")
+        .update(reservePrice.toArrayLike(Buffer, "le", 8))
+        .update(salt)
+        .digest()
     );
-    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
-    expect(balance.value.uiAmount).equals(1);
 
-    //
-    // Create an order.
-    //
-    var order = await createOrder(
+    let auction = await createAuction(
       user,
       mintKey,
       user.key,
       tokenAccount,
-      "This is test order.",
-      1 * LAMPORTS_PER_SOL
+      "This is test auction.",
+      1 * LAMPORTS_PER_SOL,
+      Math.floor(Date.now() / 1000) - 60,
+      Math.floor(Date.now() / 1000) + 2,
+      0,
+      0,
+      0,
+      0,
+      reserveHash
     );
-    console.log("[Order Created]");
-    console.log("Order Account: ", order.orderAccount.toString());
-    console.log("Order Token Account: ", order.orderTokenAccount.toString());
+    await bidAuction(
+      mintKey.publicKey,
+      user.key.publicKey,
+      user.key.publicKey,
+      bidder,
+      3 * LAMPORTS_PER_SOL
+    );
+    await new Promise((resolve) => setTimeout(resolve, 4000));
 
-    //
-    // Check result.
-    //
-    balance = await mainProgram.provider.connection.getTokenAccountBalance(
+    // The bid clears the reserve, but nobody can settle until the creator reveals it.
+    const bidderTokenAccount = await getOrCreateAssociatedTokenAccount(
+      mainProgram.provider.connection,
+      user.key,
+      mintKey.publicKey,
+      bidder.key.publicKey,
+      true
+    );
+    try {
+      await mainProgram.methods
+        .auctionResolve()
+        .accounts({
+          auction: auction.auctionAccount,
+          bidVault: getBidVaultAddress(auction.auctionAccount),
+          auctionTokenAccount: auction.auctionTokenAccount,
+          mintKey: mintKey.publicKey,
+          creator: user.key.publicKey,
+          creatorTokenAccount: tokenAccount,
+          refundReceiver: bidder.key.publicKey,
+          refundReceiverTokenAccount: bidderTokenAccount.address,
+          metadata: getMetadataAddress(mintKey.publicKey),
+          marketplace: marketplaceAccount,
+          treasuryAccount: (await mainProgram.account.marketplace.fetch(marketplaceAccount)).treasury,
+          settler: mainProgram.provider.publicKey,
+        })
+        .remainingAccounts(creatorAccounts([user.key.publicKey]))
+        .rpc();
+      assert(false, "Resolve should wait for the reserve to be revealed.");
+    } catch (err) {
+      expect(err).to.be.instanceOf(AnchorError);
+      const anchorError = err as AnchorError;
+      expect(anchorError.error.errorCode.code).equals("ReserveNotRevealed");
+    }
+
+    await mainProgram.methods
+      .revealReserve(reservePrice, Array.from(salt))
+      .accounts({
+        auction: auction.auctionAccount,
+        mintKey: mintKey.publicKey,
+        creator: user.key.publicKey,
+      })
+      .rpc();
+
+    await auctionResolve(
+      user.key,
+      mintKey.publicKey,
+      user.key.publicKey,
+      bidder.key.publicKey,
       tokenAccount
     );
-    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
-    expect(balance.value.uiAmount).equals(0);
 
-    balance = await mainProgram.provider.connection.getTokenAccountBalance(
-      order.orderTokenAccount
+    let balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      bidderTokenAccount.address
     );
-    console.log("Order Token Account Balance: ", balance.value.uiAmount);
     expect(balance.value.uiAmount).equals(1);
+  });
 
-    //
-    // Cancel order.
-    //
-    //@ts-ignore
-    order = await cancelOrder(user, mintKey, user.key, tokenAccount);
-    console.log("[Order Canceled]");
 
-    //
-    // Check result.
-    //
-    balance = await mainProgram.provider.connection.getTokenAccountBalance(
-      tokenAccount
+  it("sells a dutch auction at its decayed price", async () => {
+    let user = await createUser(1);
+    let buyer = await createUser(3);
+
+    const { mintKey, tokenAccount } = await mintNft(
+      user,
+      [{ address: user.key.publicKey, share: 100 }],
+      500
     );
-    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
-    expect(balance.value.uiAmount).equals(1);
 
-    let orderAccountInfo = await mainProgram.provider.connection.getAccountInfo(
-      order.orderAccount
+    // Halfway through its window, so the price is about 1.5 SOL.
+    const now = Math.floor(Date.now() / 1000);
+    await createDutchAuction(
+      user,
+      mintKey.publicKey,
+      tokenAccount,
+      2 * LAMPORTS_PER_SOL,
+      1 * LAMPORTS_PER_SOL,
+      now - 500,
+      now + 500
     );
-    console.log("Order Account: ", orderAccountInfo);
-    expect(orderAccountInfo).equals(null);
 
     try {
-      balance = await mainProgram.provider.connection.getTokenAccountBalance(
-        order.orderTokenAccount
-      );
-      assert(false, "Order token account should be closed by cancel program.");
-    } catch (e) {
-      expect(e.toString()).contain("could not find account");
+      await buyDutch(mintKey.publicKey, user.key.publicKey, buyer, 1.3 * LAMPORTS_PER_SOL);
+      assert(false, "Buy should fail below the current dutch auction price.");
+    } catch (err) {
+      expect(err).to.be.instanceOf(AnchorError);
+      const anchorError = err as AnchorError;
+      expect(anchorError.error.errorCode.code).equals("InsufficientMoney");
     }
-  });
 
+    const buyerBalance = await getAccountBalance(buyer.key.publicKey);
+    const buyerTokenAccount = await buyDutch(
+      mintKey.publicKey,
+      user.key.publicKey,
+      buyer,
+      2 * LAMPORTS_PER_SOL
+    );
+
+    let balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      buyerTokenAccount
+    );
+    expect(balance.value.uiAmount).equals(1);
+    expect(buyerBalance - (await getAccountBalance(buyer.key.publicKey))).to.be.closeTo(
+      1.5,
+      0.05
+    );
+    expect(
+      await mainProgram.provider.connection.getAccountInfo(
+        getDutchAuctionAddress(mintKey.publicKey, user.key.publicKey)
+      )
+    ).to.be.null;
+  });
 
 
-  it("fill order", async () => {
+  it("settles a second price sealed-bid auction and slashes unrevealed bids", async () => {
     let user = await createUser(1);
-    console.log("User Account: ", user.key.publicKey.toString());
+    let highBidder = await createUser(5);
+    let lowBidder = await createUser(5);
+    let silentBidder = await createUser(5);
 
-    let buyer = await createUser(2);
-    console.log("Buyer Account: ", buyer.key.publicKey.toString());
+    const { mintKey, tokenAccount } = await mintNft(
+      user,
+      [{ address: user.key.publicKey, share: 100 }],
+      0
+    );
 
-    //
-    // Create mint.
-    //
-    const mintKey = await createMint(user);
-    console.log("Mint key: ", mintKey.publicKey.toString());
+    const now = Math.floor(Date.now() / 1000);
+    const auctionAccount = await createSealedAuction(
+      user,
+      mintKey.publicKey,
+      tokenAccount,
+      1 * LAMPORTS_PER_SOL,
+      now - 60,
+      now + 8,
+      now + 16,
+      true,
+      1 * LAMPORTS_PER_SOL,
+      5000
+    );
 
-    //
-    // Mint a token.
-    //
-    const tokenAccount = await mintToken(mintKey, user);
-    console.log("Owner Token Account: ", tokenAccount.toString());
+    const highSalt = anchor.web3.Keypair.generate().publicKey.toBytes();
+    const lowSalt = anchor.web3.Keypair.generate().publicKey.toBytes();
+    const silentSalt = anchor.web3.Keypair.generate().publicKey.toBytes();
+    await commitBid(mintKey.publicKey, user.key.publicKey, highBidder, 3 * LAMPORTS_PER_SOL, highSalt, 4 * LAMPORTS_PER_SOL);
+    await commitBid(mintKey.publicKey, user.key.publicKey, lowBidder, 2 * LAMPORTS_PER_SOL, lowSalt, 4 * LAMPORTS_PER_SOL);
+    await commitBid(mintKey.publicKey, user.key.publicKey, silentBidder, 2 * LAMPORTS_PER_SOL, silentSalt, 2 * LAMPORTS_PER_SOL);
 
-    var balance = await mainProgram.provider.connection.getTokenAccountBalance(
+    try {
+      await revealBid(mintKey.publicKey, user.key.publicKey, highBidder, 3 * LAMPORTS_PER_SOL, highSalt);
+      assert(false, "Reveal should fail while the auction is still taking bids.");
+    } catch (err) {
+      expect(err).to.be.instanceOf(AnchorError);
+      const anchorError = err as AnchorError;
+      expect(anchorError.error.errorCode.code).equals("NotRevealPhase");
+    }
+
+    await new Promise((resolve) => setTimeout(resolve, 10000));
+    await revealBid(mintKey.publicKey, user.key.publicKey, lowBidder, 2 * LAMPORTS_PER_SOL, lowSalt);
+    let auction = await revealBid(mintKey.publicKey, user.key.publicKey, highBidder, 3 * LAMPORTS_PER_SOL, highSalt);
+    expect(auction.refundReceiver.toString()).equals(highBidder.key.publicKey.toString());
+    expect(auction.price.toNumber()).equals(3 * LAMPORTS_PER_SOL);
+    expect(auction.secondBid.toNumber()).equals(2 * LAMPORTS_PER_SOL);
+
+    await new Promise((resolve) => setTimeout(resolve, 8000));
+    const highBalance = await getAccountBalance(highBidder.key.publicKey);
+    await auctionResolve(
+      user.key,
+      mintKey.publicKey,
+      user.key.publicKey,
+      highBidder.key.publicKey,
       tokenAccount
     );
-    expect(balance.value.uiAmount).equals(1);
 
-    //
-    // Create an order.
-    //
-    var order = await createOrder(
-      user,
-      mintKey,
-      user.key,
-      tokenAccount,
-      "This is test order.",
-      1 * LAMPORTS_PER_SOL
+    // The winner pays the runner-up's 2 SOL and gets the other 1 SOL back.
+    expect(await getAccountBalance(highBidder.key.publicKey)).to.be.closeTo(
+      highBalance + 1,
+      0.01
     );
-    console.log("[Order Created]");
-    console.log("Order Account: ", order.orderAccount.toString());
-    console.log("Order Token Account: ", order.orderTokenAccount.toString());
 
-    console.log(
-      "Creator Account Balance: ",
-      await getAccountBalance(user.key.publicKey)
+    const lowBalance = await getAccountBalance(lowBidder.key.publicKey);
+    await withdrawSealedBid(auctionAccount, user.key.publicKey, lowBidder);
+    expect(await getAccountBalance(lowBidder.key.publicKey)).to.be.closeTo(
+      lowBalance + 4,
+      0.01
     );
-    console.log(
-      "Buyer Account Balance: ",
-      await getAccountBalance(buyer.key.publicKey)
+
+    const silentBalance = await getAccountBalance(silentBidder.key.publicKey);
+    await withdrawSealedBid(auctionAccount, user.key.publicKey, silentBidder);
+    expect(await getAccountBalance(silentBidder.key.publicKey)).to.be.closeTo(
+      silentBalance + 1,
+      0.01
     );
+  });
 
-    //
-    // Fill order.
-    //
-    let buyerTokenAccount = await fillOrder(
+
+  it("buy now ends the auction and refunds the leading bidder", async () => {
+    let user = await createUser(1);
+    let bidder = await createUser(3);
+    let buyer = await createUser(5);
+
+    const { mintKey, tokenAccount } = await mintNft(
+      user,
+      [{ address: user.key.publicKey, share: 100 }],
+      500
+    );
+
+    let auction = await createAuction(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test auction.",
+      1 * LAMPORTS_PER_SOL,
+      Math.floor(Date.now() / 1000) - 60,
+      Math.floor(Date.now() / 1000) + 3600,
+      0,
+      0,
+      0,
+      0,
+      null,
+      0,
+      0,
+      0,
+      3 * LAMPORTS_PER_SOL
+    );
+    await bidAuction(
       mintKey.publicKey,
       user.key.publicKey,
-      buyer
-    );
-    console.log("[Order Filled]");
-    console.log("Buyer Token Account: ", buyerTokenAccount.toString());
-    console.log(
-      "Creator Account Balance: ",
-      await getAccountBalance(user.key.publicKey)
-    );
-    console.log(
-      "Buyer Account Balance: ",
-      await getAccountBalance(buyer.key.publicKey)
+      user.key.publicKey,
+      bidder,
+      1 * LAMPORTS_PER_SOL
     );
+    const bidderBalance = await getAccountBalance(bidder.key.publicKey);
+    const creatorBalance = await getAccountBalance(user.key.publicKey);
 
-    //
-    // Check result.
-    //
-    balance = await mainProgram.provider.connection.getTokenAccountBalance(
-      tokenAccount
-    );
-    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
-    expect(balance.value.uiAmount).equals(0);
+    const buyerTokenAccount = await buyNow(mintKey.publicKey, user.key.publicKey, buyer);
 
-    balance = await mainProgram.provider.connection.getTokenAccountBalance(
+    let balance = await mainProgram.provider.connection.getTokenAccountBalance(
       buyerTokenAccount
     );
-    console.log("Buyer Token Account Balance: ", balance.value.uiAmount);
     expect(balance.value.uiAmount).equals(1);
-
-    let orderAccountInfo = await mainProgram.provider.connection.getAccountInfo(
-      order.orderAccount
+    expect(await getAccountBalance(bidder.key.publicKey)).to.be.closeTo(
+      bidderBalance + 1,
+      1e-9
     );
-    console.log("Order Account: ", orderAccountInfo);
-    expect(orderAccountInfo).equals(null);
-
-    try {
-      balance = await mainProgram.provider.connection.getTokenAccountBalance(
-        order.orderTokenAccount
-      );
-      assert(false, "Order token account should be closed by fill program.");
-    } catch (e) {
-      expect(e.toString()).contain("could not find account");
-    }
+    // The sole creator gets both the royalty and the rest of the price, plus the rent back.
+    expect(await getAccountBalance(user.key.publicKey)).to.be.greaterThan(
+      creatorBalance + 3
+    );
+    expect(
+      await mainProgram.provider.connection.getAccountInfo(auction.auctionAccount)
+    ).to.be.null;
   });
 
 
-  it("fill order insufficient money error check", async () => {
+  it("rejects bids before the auction starts", async () => {
     let user = await createUser(1);
-    console.log("User Account: ", user.key.publicKey.toString());
-
-    let buyer = await createUser(1);
-    console.log("Buyer Account: ", buyer.key.publicKey.toString());
+    let bidder = await createUser(3);
 
-    //
-    // Create mint.
-    //
     const mintKey = await createMint(user);
-    console.log("Mint key: ", mintKey.publicKey.toString());
-
-    //
-    // Mint a token.
-    //
     const tokenAccount = await mintToken(mintKey, user);
-    console.log("Owner Token Account: ", tokenAccount.toString());
 
-    //
-    // Create an order.
-    //
-    var order = await createOrder(
+    try {
+      await createAuction(
+        user,
+        mintKey,
+        user.key,
+        tokenAccount,
+        "This is test auction.",
+        1 * LAMPORTS_PER_SOL,
+        Math.floor(Date.now() / 1000) + 7200,
+        Math.floor(Date.now() / 1000) + 3600
+      );
+      assert(false, "Create auction should fail when it starts after it ends.");
+    } catch (err) {
+      expect(err).to.be.instanceOf(AnchorError);
+      const anchorError = err as AnchorError;
+      expect(anchorError.error.errorCode.code).equals("InvalidStartTime");
+    }
+
+    let auction = await createAuction(
       user,
       mintKey,
       user.key,
       tokenAccount,
-      "This is test order.",
-      2 * LAMPORTS_PER_SOL
-    );
-    console.log("[Order Created]");
-    console.log("Order Account: ", order.orderAccount.toString());
-    console.log("Order Token Account: ", order.orderTokenAccount.toString());
-
-    console.log(
-      "Creator Account Balance: ",
-      await getAccountBalance(user.key.publicKey)
-    );
-    console.log(
-      "Buyer Account Balance: ",
-      await getAccountBalance(buyer.key.publicKey)
+      "This is test auction.",
+      1 * LAMPORTS_PER_SOL,
+      Math.floor(Date.now() / 1000) + 3600,
+      Math.floor(Date.now() / 1000) + 7200
     );
 
+    let program = await programForUser(bidder);
     try {
-      //
-      // Fill order.
-      //
-      await fillOrder(mintKey.publicKey, user.key.publicKey, buyer);
-      assert(
-        false,
-        "Fill order should be failed because buyer has no enough money."
-      );
+      await program.methods
+        .bid(new BN(1 * LAMPORTS_PER_SOL))
+        .accounts({
+          auction: auction.auctionAccount,
+          bidVault: getBidVaultAddress(auction.auctionAccount),
+          mintKey: mintKey.publicKey,
+          creator: user.key.publicKey,
+          bidder: bidder.key.publicKey,
+          refundReceiver: user.key.publicKey,
+        })
+        .rpc();
+      assert(false, "Bid should fail before the auction starts.");
     } catch (err) {
       expect(err).to.be.instanceOf(AnchorError);
       const anchorError = err as AnchorError;
-      expect(anchorError.error.errorCode.number).equals(6005);
-      expect(anchorError.error.errorCode.code).equals("InsufficientMoney");
+      expect(anchorError.error.errorCode.code).equals("AuctionNotStarted");
     }
   });
 
 
-  
-  it("create auction", async () => {
-    let user = await createUser(1); 
-    console.log("User Account: ", user.key.publicKey.toString());
-    //
-    // Create mint.
-    //
+  it("extends the auction when a bid lands near its end", async () => {
+    let user = await createUser(1);
+    let firstBidder = await createUser(3);
+    let secondBidder = await createUser(3);
+
     const mintKey = await createMint(user);
-    console.log("Mint key: ", mintKey.publicKey.toString());
-    //
-    // Mint a token.
-    //
     const tokenAccount = await mintToken(mintKey, user);
-    console.log("Owner Token Account: ", tokenAccount.toString());
-    var balance = await mainProgram.provider.connection.getTokenAccountBalance(
-      tokenAccount
+
+    const endTime = Math.floor(Date.now() / 1000) + 30;
+    let auction = await createAuction(
+      user,
+      mintKey,
+      user.key,
+      tokenAccount,
+      "This is test auction.",
+      1 * LAMPORTS_PER_SOL,
+      Math.floor(Date.now() / 1000) - 60,
+      endTime,
+      0,
+      0,
+      0,
+      0,
+      null,
+      60,
+      120,
+      1
     );
-    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
-    expect(balance.value.uiAmount).equals(1);
-    //
-    // Create an auction.
-    //
+
+    let bid = await bidAuction(
+      mintKey.publicKey,
+      user.key.publicKey,
+      user.key.publicKey,
+      firstBidder,
+      1 * LAMPORTS_PER_SOL
+    );
+    expect(bid.auction.endTime.toNumber()).equals(endTime + 120);
+    expect(bid.auction.extensions).equals(1);
+
+    // The cap is reached, so later bids leave the end time alone.
+    bid = await bidAuction(
+      mintKey.publicKey,
+      user.key.publicKey,
+      firstBidder.key.publicKey,
+      secondBidder,
+      2 * LAMPORTS_PER_SOL
+    );
+    expect(bid.auction.price.toNumber()).equals(2 * LAMPORTS_PER_SOL);
+    expect(bid.auction.endTime.toNumber()).equals(endTime + 120);
+    expect(bid.auction.extensions).equals(1);
+  });
+
+
+  it("credits outbid bidders to a claimable refund account", async () => {
+    let user = await createUser(1);
+    let firstBidder = await createUser(3);
+    let secondBidder = await createUser(3);
+
+    const mintKey = await createMint(user);
+    const tokenAccount = await mintToken(mintKey, user);
+
     let auction = await createAuction(
       user,
       mintKey,
       user.key,
       tokenAccount,
-      "This is test order.",
+      "This is test auction.",
       1 * LAMPORTS_PER_SOL,
-      1659518580,
-      1659528580
+      Math.floor(Date.now() / 1000) - 60,
+      Math.floor(Date.now() / 1000) + 3600
     );
-    console.log("[Auction Created]");
-    console.log("Auction Account: ", auction.auctionAccount.toString());
-    console.log("Auction Token Account: ", auction.auctionTokenAccount.toString());
-    console.log("Auction.creator: ", auction.auction.creator.toString());
-    console.log("Auction.mintKey: ", auction.auction.mintKey.toString());
-    console.log("Auction.memo: ", auction.auction.memo);
-    console.log("Auction.price: ", auction.auction.price.toNumber());
-    console.log("Auction.start_time: ", auction.auction.startTime.toNumber());
-    console.log("Auction.end_time: ", auction.auction.endTime.toNumber());
-    //
-    // Check result.
-    //
-    balance = await mainProgram.provider.connection.getTokenAccountBalance(
-      tokenAccount
+
+    await deferredBid(
+      mintKey.publicKey,
+      user.key.publicKey,
+      user.key.publicKey,
+      firstBidder,
+      1 * LAMPORTS_PER_SOL
     );
-    console.log("Owner Token Account Balance: ", balance.value.uiAmount);
-    expect(balance.value.uiAmount).equals(0);
-    balance = await mainProgram.provider.connection.getTokenAccountBalance(
-      auction.auctionTokenAccount
+    const outbidBalance = await getAccountBalance(firstBidder.key.publicKey);
+
+    await deferredBid(
+      mintKey.publicKey,
+      user.key.publicKey,
+      firstBidder.key.publicKey,
+      secondBidder,
+      2 * LAMPORTS_PER_SOL
     );
-    console.log("Auction Token Account Balance: ", balance.value.uiAmount);
-    expect(balance.value.uiAmount).equals(1);
-    expect(auction.auction.creator.toString()).equals(
-      user.key.publicKey.toString()
+
+    // The outbid amount waits in the refund account rather than the bidder's wallet.
+    const bidRefund = getBidRefundAddress(
+      auction.auctionAccount,
+      firstBidder.key.publicKey
     );
-    expect(auction.auction.mintKey.toString()).equals(mintKey.publicKey.toString());
-    expect(auction.auction.memo).equals("This is test order.");
-    expect(auction.auction.price.toNumber()).equals(1 * LAMPORTS_PER_SOL);
+    let refund = await mainProgram.account.bidRefund.fetch(bidRefund);
+    expect(refund.amount.toNumber()).equals(1 * LAMPORTS_PER_SOL);
+    expect(await getAccountBalance(firstBidder.key.publicKey)).equals(outbidBalance);
+
+    let vault = await mainProgram.account.bidVault.fetch(
+      getBidVaultAddress(auction.auctionAccount)
+    );
+    expect(vault.amount.toNumber()).equals(2 * LAMPORTS_PER_SOL);
+
+    await claimRefund(auction.auctionAccount, firstBidder);
+    expect(await getAccountBalance(firstBidder.key.publicKey)).to.be.greaterThan(
+      outbidBalance + 1
+    );
+    expect(await mainProgram.provider.connection.getAccountInfo(bidRefund)).to.be.null;
   });
 
 
 
   
-  it("bid auction", async () => {
+  it("lets anyone settle an ended auction for the crank fee", async () => {
     let user = await createUser(1);
-    console.log("User Account: ", user.key.publicKey.toString());
+    let bidder = await createUser(3);
+    let settler = await createUser(1);
 
-    let bidder = await createUser(2);
-    console.log("Bidder Account: ", bidder.key.publicKey.toString());
+    await mainProgram.methods
+      .setCrankFee(5000)
+      .accounts({
+        marketplace: marketplaceAccount,
+        admin: mainProgram.provider.publicKey,
+      })
+      .rpc();
 
-    //
-    // Create mint.
-    //
     const mintKey = await createMint(user);
-    console.log("Mint key: ", mintKey.publicKey.toString());
-
-    //
-    // Mint a token.
-    //
     const tokenAccount = await mintToken(mintKey, user);
-    console.log("Owner Token Account: ", tokenAccount.toString());
-
-    var balance = await mainProgram.provider.connection.getTokenAccountBalance(
-      tokenAccount
-    );
-    expect(balance.value.uiAmount).equals(1);
 
-    //
-    // Create an order. 1663319647  1663146847
-    //
     let auction = await createAuction(
       user,
       mintKey,
@@ -898,56 +3191,71 @@ describe("unicus_ts", () => {
       tokenAccount,
       "This is test auction.",
       1 * LAMPORTS_PER_SOL,
-      1663146847,
-      1663319647
+      Math.floor(Date.now() / 1000) - 60,
+      Math.floor(Date.now() / 1000) + 2
     );
-    console.log("[Auction Created]");
-    console.log("AUction Account: ", auction.auctionAccount.toString());
-    console.log("Auction Token Account: ", auction.auctionTokenAccount.toString());
 
-    console.log(
-      "Creator Account Balance: ",
-      await getAccountBalance(user.key.publicKey)
-    );
-    console.log(
-      "Bidder Account Balance: ",
-      await getAccountBalance(bidder.key.publicKey)
-    );
+    await mainProgram.methods
+      .setCrankFee(0)
+      .accounts({
+        marketplace: marketplaceAccount,
+        admin: mainProgram.provider.publicKey,
+      })
+      .rpc();
 
-    console.log("refund receiver", auction.auction.refundReceiver.toString());
+    // Half of the 2% listing fee is held back for whoever settles the auction.
+    expect(auction.auction.crankFee.toNumber()).equals(0.01 * LAMPORTS_PER_SOL);
 
-    //
-    // Fill order.
-    //
-    let bid = await bidAuction(
+    await bidAuction(
       mintKey.publicKey,
       user.key.publicKey,
-      auction.auction.refundReceiver,
+      user.key.publicKey,
       bidder,
       1 * LAMPORTS_PER_SOL
     );
-    console.log("[Bid Filled]");
-    
-    console.log(
-      "Creator Account Balance: ",
-      await getAccountBalance(user.key.publicKey)
-    );
-    console.log(
-      "Bidder Account Balance: ",
-      await getAccountBalance(bidder.key.publicKey)
-    );
+    await new Promise((resolve) => setTimeout(resolve, 4000));
 
-    //
-    // Check result.
-    //
-    console.log("bid winner is", bid.auction.refundReceiver.toString());
-    console.log("new auction price is", bid.auction.price.toNumber());
+    const settlerBalance = await getAccountBalance(settler.key.publicKey);
+    const bidderTokenAccount = await getAssociatedTokenAddress(
+      mintKey.publicKey,
+      bidder.key.publicKey
+    );
 
+    // The winner never signs; the settler pays for their token account.
+    let program = await programForUser(settler);
+    await program.methods
+      .auctionResolve()
+      .accounts({
+        auction: auction.auctionAccount,
+        bidVault: getBidVaultAddress(auction.auctionAccount),
+        auctionTokenAccount: auction.auctionTokenAccount,
+        mintKey: mintKey.publicKey,
+        creator: user.key.publicKey,
+        creatorTokenAccount: tokenAccount,
+        refundReceiver: bidder.key.publicKey,
+        refundReceiverTokenAccount: bidderTokenAccount,
+        metadata: getMetadataAddress(mintKey.publicKey),
+        marketplace: marketplaceAccount,
+        treasuryAccount: (await mainProgram.account.marketplace.fetch(marketplaceAccount)).treasury,
+        settler: settler.key.publicKey,
+      })
+      .remainingAccounts(creatorAccounts([user.key.publicKey]))
+      .rpc();
+
+    let balance = await mainProgram.provider.connection.getTokenAccountBalance(
+      bidderTokenAccount
+    );
+    expect(balance.value.uiAmount).equals(1);
+    // The crank fee covers the rent of the new token account and the transaction fee.
+    expect(await getAccountBalance(settler.key.publicKey)).to.be.greaterThan(
+      settlerBalance
+    );
+    expect(
+      await mainProgram.provider.connection.getAccountInfo(auction.auctionAccount)
+    ).to.be.null;
   });
 
 
-
-  
   it("cancel auction", async () => {
     let user = await createUser(1);
     console.log("User Account: ", user.key.publicKey.toString());
@@ -971,7 +3279,7 @@ describe("unicus_ts", () => {
     expect(balance.value.uiAmount).equals(1);
 
     //
-    // Create an auction.
+    // Create an auction that has not started yet.
     //
     let auction = await createAuction(
       user,
@@ -980,8 +3288,8 @@ describe("unicus_ts", () => {
       tokenAccount,
       "This is test auction.",
       1 * LAMPORTS_PER_SOL,
-      1663146847,
-      1663319647
+      Math.floor(Date.now() / 1000) + 3600,
+      Math.floor(Date.now() / 1000) + 7200
     );
     console.log("[Auction Created]");
     console.log("AUction Account: ", auction.auctionAccount.toString());
@@ -1000,7 +3308,7 @@ describe("unicus_ts", () => {
     // Cancel auction
     //
     //@ts-ignore
-    auction = await cancelAuction(user, mintKey.publicKey, user.key, auction.auction.refundReceiver, tokenAccount);
+    auction = await cancelAuction(user, mintKey.publicKey, user.key, user.key.publicKey, tokenAccount);
     console.log("[auction Canceled]");
 
   